@@ -162,7 +162,7 @@ impl Board {
                 c += 1;
             } else if chr == '/' {
                 //row divider
-                r -= 1;
+                r = r.checked_sub(1).ok_or("too many rows in FEN")?;
                 c = 0;
             } else {
                 // number stating number of blank spaces in this row
@@ -172,6 +172,9 @@ impl Board {
                 {
                     c += num_blanks as u8;
                 }
+                if c > 8 {
+                    return Err("too many squares in one row of FEN");
+                }
             }
         }
 
@@ -218,6 +221,40 @@ impl Board {
 
         // castle rights searching ate the space, so no need to check for it
 
+        // ignore any castle rights for which the king and rook are not on their starting squares
+        for (right, king_sq, rook_sq, color) in [
+            (
+                CastleRights::WHITE_KINGSIDE,
+                Square::E1,
+                Square::H1,
+                Color::White,
+            ),
+            (
+                CastleRights::WHITE_QUEENSIDE,
+                Square::E1,
+                Square::A1,
+                Color::White,
+            ),
+            (
+                CastleRights::BLACK_KINGSIDE,
+                Square::E8,
+                Square::H8,
+                Color::Black,
+            ),
+            (
+                CastleRights::BLACK_QUEENSIDE,
+                Square::E8,
+                Square::A8,
+                Color::Black,
+            ),
+        ] {
+            let kings = board[Piece::King] & board[color];
+            let rooks = board[Piece::Rook] & board[color];
+            if !(kings.contains(king_sq) && rooks.contains(rook_sq)) {
+                board.castle_rights &= !right;
+            }
+        }
+
         // en passant square
         board.en_passant_square = {
             let ep_file_chr = fen_chrs
@@ -229,9 +266,19 @@ impl Board {
                 let ep_rank_chr = fen_chrs
                     .next()
                     .ok_or("reached end of string while parsing en passant rank")?;
-                Some(Square::from_algebraic(&format!(
-                    "{ep_file_chr}{ep_rank_chr}"
-                ))?)
+                let ep_sq = Square::from_algebraic(&format!("{ep_file_chr}{ep_rank_chr}"))?;
+                // the pawn which just moved two squares must be right in front of the square
+                let (ep_rank, pushed_rank) = match board.player {
+                    Color::White => (5, 4),
+                    Color::Black => (2, 3),
+                };
+                let pushed_sq =
+                    Square::new(pushed_rank, ep_sq.file()).ok_or("illegal en passant square")?;
+                let opponent_pawns = board[Piece::Pawn] & board[!board.player];
+                if ep_sq.rank() != ep_rank || !opponent_pawns.contains(pushed_sq) {
+                    return Err("illegal en passant square");
+                }
+                Some(ep_sq)
             }
         };
 
@@ -256,7 +303,8 @@ impl Board {
             let rule50_num = rule50_buf
                 .parse::<u8>()
                 .map_err(|_| "could not parse rule50 counter")?;
-            if rule50_num > 100 {
+            // after 150 plies, the game is automatically drawn by the 75-move rule
+            if rule50_num > 150 {
                 return Err("rule 50 number is too high");
             }
 
@@ -279,6 +327,84 @@ impl Board {
         Ok(board)
     }
 
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    /// Describe this board in Forsyth-Edwards notation.
+    /// Since a `Board` does not track the move number, the full move counter is always 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use tomato::base::Board;
+    ///
+    /// let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 1";
+    /// assert_eq!(Board::from_fen(fen)?.to_fen(), fen);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::with_capacity(90);
+        for r in (0..8).rev() {
+            let mut num_blanks = 0;
+            for c in 0..8 {
+                let sq = Square::new(r, c).unwrap();
+                match self.type_at_square(sq) {
+                    Some(p) => {
+                        if num_blanks > 0 {
+                            fen.push(char::from(b'0' + num_blanks));
+                            num_blanks = 0;
+                        }
+                        fen.push(match self.color_at_square(sq).unwrap() {
+                            Color::White => p.code(),
+                            Color::Black => p.code().to_ascii_lowercase(),
+                        });
+                    }
+                    None => num_blanks += 1,
+                }
+            }
+            if num_blanks > 0 {
+                fen.push(char::from(b'0' + num_blanks));
+            }
+            if r > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push(' ');
+        fen.push(match self.player {
+            Color::White => 'w',
+            Color::Black => 'b',
+        });
+
+        fen.push(' ');
+        let old_len = fen.len();
+        for (right, chr) in [
+            (CastleRights::WHITE_KINGSIDE, 'K'),
+            (CastleRights::WHITE_QUEENSIDE, 'Q'),
+            (CastleRights::BLACK_KINGSIDE, 'k'),
+            (CastleRights::BLACK_QUEENSIDE, 'q'),
+        ] {
+            if self.castle_rights & right != CastleRights::NONE {
+                fen.push(chr);
+            }
+        }
+        if fen.len() == old_len {
+            fen.push('-');
+        }
+
+        fen.push(' ');
+        match self.en_passant_square {
+            Some(sq) => fen += &sq.to_string(),
+            None => fen.push('-'),
+        }
+        fen.push(' ');
+        fen += &self.rule50.to_string();
+        fen += " 1";
+
+        fen
+    }
+
     #[inline(always)]
     #[must_use]
     /// Get the squares occupied by the pieces of each type (i.e. Black or
@@ -408,6 +534,16 @@ impl Board {
             return false;
         }
 
+        if !square_attackers(self, self.king_sqs[!self.player as usize], self.player).is_empty() {
+            // the player to move could capture the opponent's king
+            return false;
+        }
+
+        if !(self[Piece::Pawn] & Bitboard::new(0xFF00_0000_0000_00FF)).is_empty() {
+            // pawns can never stand on the first or last rank
+            return false;
+        }
+
         // TODO validate pinners
         true
     }
@@ -543,7 +679,8 @@ impl Board {
         if is_pawn_move || capturee.is_some() {
             self.rule50 = 0;
         } else {
-            self.rule50 += 1;
+            // saturate so that games which ignore the 75-move rule cannot overflow the counter
+            self.rule50 = self.rule50.saturating_add(1);
         }
 
         /* Non-meta fields of the board are now in their final state. */
@@ -658,25 +795,51 @@ impl Board {
     /// # }
     /// ```
     pub fn is_drawn(&self) -> bool {
-        const DARK_SQUARES: Bitboard = Bitboard::new(0xAA55_AA55_AA55_AA55);
         // 50 move rule = 100 ply
-        if self.rule50 >= 100 {
-            return true;
-        }
-        // check for drawn by insufficient material
-        match self.occupancy().len() {
-            0 | 1 => unreachable!(), // a king is missing
-            2 => true,               // only two kings
-            3 => !(self[Piece::Knight] | self[Piece::Bishop]).is_empty(), //KNK or KBK
-            // same colored bishops
-            4 => {
-                self[Piece::Bishop].more_than_one()
-                    && !(self[Piece::Bishop] & DARK_SQUARES).has_single_bit()
+        self.rule50 >= 100 || self.is_dead()
+    }
+
+    #[must_use]
+    /// Determine whether neither player can possibly checkmate the other due to insufficient
+    /// material.
+    /// This is the case when the only pieces other than the kings are either a single minor piece
+    /// or any number of bishops which all stand on squares of the same color.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use tomato::base::Board;
+    ///
+    /// assert!(Board::from_fen("8/8/3k4/8/4b3/2KB4/8/8 w - - 0 1")?.is_dead());
+    /// assert!(Board::from_fen("8/8/3k4/3b4/4b3/2K5/8/8 w - - 0 1")?.is_dead());
+    /// assert!(!Board::from_fen("8/8/3k4/3b4/4n3/2K5/8/8 w - - 0 1")?.is_dead());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_dead(&self) -> bool {
+        const DARK_SQUARES: Bitboard = Bitboard::new(0xAA55_AA55_AA55_AA55);
+        let kings = self[Piece::King];
+        let others = self.occupancy() ^ kings;
+        match others.len() {
+            0 => true,                                                    // only two kings
+            1 => !(self[Piece::Knight] | self[Piece::Bishop]).is_empty(), // KNK or KBK
+            // bishops which are all on the same color can never attack the other color
+            _ => {
+                others == self[Piece::Bishop]
+                    && ((others & DARK_SQUARES).is_empty() || (others & !DARK_SQUARES).is_empty())
             }
-            _ => false,
         }
     }
 
+    #[inline(always)]
+    #[must_use]
+    /// Get the number of plies since the last capture or pawn move, for the 50- and 75-move
+    /// rules.
+    pub const fn rule50(&self) -> u8 {
+        self.rule50
+    }
+
     /// Compute the hash value of this board from scratch. This should
     /// generally only be used for debug purposes, as in most cases iteratively
     /// updating the hashes as moves are made is enough.
@@ -780,7 +943,11 @@ impl Default for Board {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::base::Square;
+    use crate::base::{
+        fuzz::mutate,
+        movegen::{get_moves, GenMode},
+        Square,
+    };
 
     /// A helper function which will load a board from a FEN and then try
     /// running the given move on that board.
@@ -980,6 +1147,80 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Test that FENs describing impossible positions are rejected.
+    fn reject_illegal_fens() {
+        for fen in [
+            // no kings
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+            // two white kings
+            "4k3/8/8/8/8/8/8/K3K3 w - - 0 1",
+            // the player not to move is in check
+            "4k3/8/8/8/8/8/8/4RK2 w - - 0 1",
+            // pawns on the back rank
+            "P3k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/p3K3 b - - 0 1",
+            // en passant squares on the wrong rank, or with no pawn to capture
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e4 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq e3 0 1",
+            // malformed board sections
+            "9/9/9/9/9/9/9/9/9 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/44K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0",
+        ] {
+            assert!(Board::from_fen(fen).is_err(), "{fen}");
+        }
+    }
+
+    #[test]
+    /// Test that castle rights are ignored when the king or rook is not on its starting square.
+    fn sanitize_castle_rights() {
+        let b = Board::from_fen("r3k3/8/8/8/8/8/8/4K2R w KQkq - 0 1").unwrap();
+        assert!(b.castle_rights.kingside(Color::White));
+        assert!(!b.castle_rights.queenside(Color::White));
+        assert!(!b.castle_rights.kingside(Color::Black));
+        assert!(b.castle_rights.queenside(Color::Black));
+    }
+
+    #[test]
+    /// Test that converting a board to a FEN and back results in the same board.
+    fn fen_round_trip() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b Kq e3 0 1",
+            "r1bq1b1r/ppp2kpp/2n5/3np3/2B5/8/PPPP1PPP/RNBQK2R w KQ - 0 1",
+            "3k4/R7/1R6/5K2/8/8/8/8 w - - 37 1",
+        ] {
+            let b = Board::from_fen(fen).unwrap();
+            assert_eq!(b.to_fen(), fen);
+            assert_eq!(Board::from_fen(&b.to_fen()).unwrap(), b);
+        }
+    }
+
+    #[test]
+    /// Test that loading FENs with random characters swapped, inserted, or deleted never panics.
+    fn mutated_fens() {
+        const FENS: [&str; 3] = [
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 12 34",
+        ];
+        const ALPHABET: &[u8] = b" /-0123456789abcdefghkqrnbpwKQRBNP";
+        let rng = fastrand::Rng::with_seed(1234);
+        for _ in 0..10_000 {
+            let fen = mutate(FENS[rng.usize(..FENS.len())], ALPHABET, &rng);
+            if let Ok(b) = Board::from_fen(&fen) {
+                // anything we accept must be playable
+                for m in get_moves::<{ GenMode::All }>(&b) {
+                    let mut b2 = b;
+                    b2.make_move(m);
+                    assert!(b2.is_valid(), "{fen} {m}");
+                }
+            }
+        }
+    }
+
     /// Tests regarding drawn positions.
     mod draws {
         use super::*;
@@ -1020,5 +1261,20 @@ mod tests {
         fn rule50() {
             draw_helper("rnbqk2r/ppppnp1p/4p1pb/8/4P3/1P1P4/PBP2PPP/RN1QKBNR w KQkq - 100 100");
         }
+
+        #[test]
+        /// Test that any number of bishops on squares of the same color is a draw, but that
+        /// bishops on both colors or any other extra material is not.
+        fn same_color_bishops() {
+            draw_helper("K1k5/8/8/4b3/3B4/8/3b4/B7 w - - 0 1");
+            for fen in [
+                "K1k5/8/8/8/3B4/8/4b3/8 w - - 0 1",
+                "K1k5/8/8/8/3B4/8/3n4/8 w - - 0 1",
+                "K1k5/8/8/8/3B4/8/3p4/8 w - - 0 1",
+                "K1k5/8/8/8/8/8/3N4/3n4 w - - 0 1",
+            ] {
+                assert!(!Board::from_fen(fen).unwrap().is_drawn(), "{fen}");
+            }
+        }
     }
 }
@@ -0,0 +1,45 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Helpers for fuzz-style tests, which feed randomly corrupted input to a parser.
+
+use fastrand::Rng;
+
+#[must_use]
+/// Corrupt `input` by swapping, inserting, or deleting a few random characters, each drawn from
+/// `alphabet`.
+///
+/// # Panics
+///
+/// This function will panic if `input` or `alphabet` is empty.
+pub fn mutate(input: &str, alphabet: &[u8], rng: &Rng) -> String {
+    let mut bytes = input.as_bytes().to_vec();
+    for _ in 0..rng.usize(1..4) {
+        let idx = rng.usize(..bytes.len());
+        let chr = alphabet[rng.usize(..alphabet.len())];
+        match rng.u8(0..3) {
+            0 => bytes[idx] = chr,
+            1 => bytes.insert(idx, chr),
+            _ => {
+                bytes.remove(idx);
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
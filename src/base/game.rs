@@ -22,12 +22,132 @@ use super::movegen::is_legal;
 
 use super::{
     movegen::{get_moves, has_moves, GenMode},
-    Board, Move,
+    Board, Color, Move,
 };
 
 use nohash_hasher::IntMap;
 
-use std::default::Default;
+use std::{
+    default::Default,
+    fmt::{Display, Formatter},
+};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+/// The final result of a chess game.
+pub enum GameResult {
+    /// White won the game.
+    WhiteWins,
+    /// Black won the game.
+    BlackWins,
+    /// The game was drawn.
+    Draw,
+}
+
+impl GameResult {
+    #[must_use]
+    /// Get the result of a game which was won by `winner`.
+    pub const fn win_for(winner: Color) -> GameResult {
+        match winner {
+            Color::White => GameResult::WhiteWins,
+            Color::Black => GameResult::BlackWins,
+        }
+    }
+
+    #[must_use]
+    /// Get the color which won the game, or `None` if the game was drawn.
+    pub const fn winner(self) -> Option<Color> {
+        match self {
+            GameResult::WhiteWins => Some(Color::White),
+            GameResult::BlackWins => Some(Color::Black),
+            GameResult::Draw => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+/// The reason that a chess game ended.
+pub enum Termination {
+    /// The player to move was checkmated by `winner`.
+    Checkmate {
+        /// The color which delivered checkmate.
+        winner: Color,
+    },
+    /// The player to move has no legal moves, but is not in check.
+    Stalemate,
+    /// Neither player has enough material to ever deliver checkmate.
+    InsufficientMaterial,
+    /// The same position has occurred three times.
+    /// Under FIDE rules, this draw must be claimed by a player.
+    ThreefoldRepetition,
+    /// The same position has occurred five times, which is automatically a draw.
+    FivefoldRepetition,
+    /// Fifty moves have been played by each player without a capture or pawn move.
+    /// Under FIDE rules, this draw must be claimed by a player.
+    FiftyMoveRule,
+    /// Seventy-five moves have been played by each player without a capture or pawn move, which
+    /// is automatically a draw.
+    SeventyFiveMoveRule,
+    /// The player `loser` resigned.
+    Resignation {
+        /// The color which resigned.
+        loser: Color,
+    },
+    /// The game was stopped by an outside arbiter, such as a tournament manager, with the given
+    /// result.
+    Adjudication(GameResult),
+}
+
+impl Termination {
+    #[must_use]
+    /// Get the result of the game which ended for this reason.
+    pub fn result(self) -> GameResult {
+        match self {
+            Termination::Checkmate { winner } => GameResult::win_for(winner),
+            Termination::Resignation { loser } => GameResult::win_for(!loser),
+            Termination::Adjudication(result) => result,
+            _ => GameResult::Draw,
+        }
+    }
+
+    #[must_use]
+    /// Determine whether this termination ends the game without either player needing to claim
+    /// it.
+    /// Threefold repetition and the fifty-move rule are the only terminations which must be
+    /// claimed.
+    pub const fn is_automatic(self) -> bool {
+        !matches!(
+            self,
+            Termination::ThreefoldRepetition | Termination::FiftyMoveRule
+        )
+    }
+}
+
+/// Get the capitalized name of a color, for describing terminations.
+const fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+impl Display for Termination {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Termination::Checkmate { winner } => write!(f, "{} mates", color_name(*winner)),
+            Termination::Stalemate => write!(f, "stalemate"),
+            Termination::InsufficientMaterial => write!(f, "insufficient material"),
+            Termination::ThreefoldRepetition => write!(f, "threefold repetition"),
+            Termination::FivefoldRepetition => write!(f, "fivefold repetition"),
+            Termination::FiftyMoveRule => write!(f, "fifty move rule"),
+            Termination::SeventyFiveMoveRule => write!(f, "seventy-five move rule"),
+            Termination::Resignation { loser } => write!(f, "{} resigns", color_name(*loser)),
+            Termination::Adjudication(result) => match result.winner() {
+                Some(winner) => write!(f, "{} wins by adjudication", color_name(winner)),
+                None => write!(f, "draw by adjudication"),
+            },
+        }
+    }
+}
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -46,6 +166,10 @@ pub struct Game {
     /// The keys are the Zobrist hashes of the position and the values are the number of times that
     /// position has been repeated.
     repetitions: IntMap<u64, u8>,
+    /// The termination imposed on this game from outside of the rules of chess, such as a
+    /// resignation or an adjudication.
+    /// Any moves or undos on the game will clear it.
+    adjudication: Option<Termination>,
 }
 
 impl Game {
@@ -57,21 +181,23 @@ impl Game {
             history: vec![b],
             moves: Vec::new(),
             repetitions: IntMap::from_iter([(b.hash, 1)]),
+            adjudication: None,
         }
     }
 
     /// Construct a new [`Game`] using the Forsyth-Edwards notation description of its position.
+    /// The halfmove clock in the FEN is carried over for the fifty- and seventy-five-move rules.
     ///
     /// # Errors
     ///
     /// This function will return an `Err` if the FEN string is invalid.
     pub fn from_fen(fen: &str) -> Result<Game, &'static str> {
         let b = Board::from_fen(fen)?;
-        // TODO extract 50 move rule from the FEN
         Ok(Game {
             history: vec![b],
             moves: Vec::new(),
             repetitions: IntMap::from_iter([(b.hash, 1)]),
+            adjudication: None,
         })
     }
 
@@ -84,6 +210,7 @@ impl Game {
         self.moves.clear();
         self.repetitions.clear();
         self.repetitions.insert(start_board.hash, 1);
+        self.adjudication = None;
     }
 
     /// Make a move, assuming said move is legal.
@@ -109,6 +236,7 @@ impl Game {
         *num_reps += 1;
         self.history.push(new_board);
         self.moves.push(m);
+        self.adjudication = None;
     }
 
     #[allow(clippy::result_unit_err)]
@@ -143,6 +271,7 @@ impl Game {
         if *num_reps == 0 {
             self.repetitions.remove(&b_removed.hash);
         }
+        self.adjudication = None;
 
         Ok(m_removed)
     }
@@ -155,6 +284,19 @@ impl Game {
         self.history.last().unwrap()
     }
 
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    /// Get the position from which this game started.
+    pub fn start(&self) -> &Board {
+        self.history.first().unwrap()
+    }
+
+    #[must_use]
+    /// Get the moves played in this game, in order.
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
     #[must_use]
     /// Detect how the game has ended.
     ///
@@ -175,11 +317,108 @@ impl Game {
         Some(!b.checkers.is_empty())
     }
 
+    /// End the game by the resignation of `loser`.
+    /// The resignation is forgotten if any move is made or undone afterward.
+    pub fn resign(&mut self, loser: Color) {
+        self.adjudication = Some(Termination::Resignation { loser });
+    }
+
+    /// End the game with a result decided by an outside arbiter, such as a match runner declaring
+    /// a hopeless position lost.
+    /// The adjudication is forgotten if any move is made or undone afterward.
+    pub fn adjudicate(&mut self, result: GameResult) {
+        self.adjudication = Some(Termination::Adjudication(result));
+    }
+
+    #[must_use]
+    /// Determine exactly how the game has ended, or `None` if the game is not over.
+    ///
+    /// Resignations and adjudications take priority, followed by checkmate and stalemate, since a
+    /// mating move ends the game even if it also completes a repetition or the fifty-move rule.
+    /// Automatic draws (insufficient material, fivefold repetition, and the seventy-five move rule)
+    /// are reported before draws which could merely be claimed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use tomato::base::{
+    ///     game::{Game, GameResult, Termination},
+    ///     Color,
+    /// };
+    ///
+    /// let g = Game::from_fen("rnbqkbnr/ppppp2p/5p2/6pQ/4P3/2N5/PPPP1PPP/R1B1KBNR b KQkq - 1 3")?;
+    /// assert_eq!(
+    ///     g.termination(),
+    ///     Some(Termination::Checkmate {
+    ///         winner: Color::White
+    ///     })
+    /// );
+    /// assert_eq!(g.result(), Some(GameResult::WhiteWins));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn termination(&self) -> Option<Termination> {
+        if self.adjudication.is_some() {
+            return self.adjudication;
+        }
+
+        let b = self.board();
+        if get_moves::<{ GenMode::All }>(b).is_empty() {
+            return Some(if b.checkers.is_empty() {
+                Termination::Stalemate
+            } else {
+                Termination::Checkmate { winner: !b.player }
+            });
+        }
+
+        if b.is_dead() {
+            return Some(Termination::InsufficientMaterial);
+        }
+
+        let num_reps = self.num_repetitions();
+        if num_reps >= 5 {
+            return Some(Termination::FivefoldRepetition);
+        }
+        // 75 move rule = 150 ply
+        if b.rule50() >= 150 {
+            return Some(Termination::SeventyFiveMoveRule);
+        }
+        if num_reps >= 3 {
+            return Some(Termination::ThreefoldRepetition);
+        }
+        if b.rule50() >= 100 {
+            return Some(Termination::FiftyMoveRule);
+        }
+
+        None
+    }
+
+    #[must_use]
+    /// Get the result of the game, or `None` if the game is not over.
+    /// Claimable draws are treated as the end of the game.
+    pub fn result(&self) -> Option<GameResult> {
+        self.termination().map(Termination::result)
+    }
+
+    #[must_use]
+    /// Get the number of plies since the last capture or pawn move, including those before the
+    /// start of this game.
+    pub fn rule50(&self) -> u8 {
+        self.board().rule50()
+    }
+
+    #[must_use]
+    /// Get the number of times that the current position has occurred in this game, including
+    /// the current occurrence.
+    pub fn num_repetitions(&self) -> u8 {
+        *self.repetitions.get(&self.board().hash).unwrap_or(&0)
+    }
+
     #[must_use]
     /// Determine whether this game been drawn due to history (i.e. repetition or the 50 move rule).
     pub fn drawn_by_repetition(&self) -> bool {
-        let num_reps = self.repetitions.get(&self.board().hash).unwrap_or(&0);
-        *num_reps >= 3
+        self.num_repetitions() >= 3
     }
 
     #[must_use]
@@ -354,4 +593,147 @@ mod tests {
             assert!(moves.contains(em));
         }
     }
+
+    /// Play a sequence of moves, given in UCI notation, on `g`.
+    fn play(g: &mut Game, moves: &[&str]) {
+        for uci in moves {
+            let m = Move::from_uci(uci, g.board()).unwrap();
+            g.try_move(m).unwrap();
+        }
+    }
+
+    #[test]
+    /// Test that checkmate is reported for the correct winner.
+    fn checkmate() {
+        let mut g = Game::new();
+        play(&mut g, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        assert_eq!(
+            g.termination(),
+            Some(Termination::Checkmate {
+                winner: Color::Black
+            })
+        );
+        assert_eq!(g.result(), Some(GameResult::BlackWins));
+
+        let g = Game::from_fen("3k4/R7/1R6/5K2/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(g.termination(), None);
+        let mut g = g;
+        play(&mut g, &["b6b8"]);
+        assert_eq!(g.result(), Some(GameResult::WhiteWins));
+    }
+
+    #[test]
+    /// Test that a stalemate is a draw.
+    fn stalemate() {
+        let g = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(g.termination(), Some(Termination::Stalemate));
+        assert_eq!(g.result(), Some(GameResult::Draw));
+    }
+
+    #[test]
+    /// Test that positions where no checkmate is possible are draws, including those with many
+    /// same-colored bishops.
+    fn insufficient_material() {
+        for fen in [
+            "8/8/3k4/8/8/2K5/8/8 w - - 0 1",
+            "8/8/3k4/8/8/2KN4/8/8 w - - 0 1",
+            "8/8/3k4/8/4b3/2KB4/8/8 w - - 0 1",
+            "8/8/3k4/4b3/3B4/2K5/7B/8 w - - 0 1",
+        ] {
+            let g = Game::from_fen(fen).unwrap();
+            assert_eq!(
+                g.termination(),
+                Some(Termination::InsufficientMaterial),
+                "{fen}"
+            );
+        }
+        let g = Game::from_fen("8/8/3k4/8/3b4/2KB4/8/8 w - - 0 1").unwrap();
+        assert_eq!(g.termination(), None);
+    }
+
+    #[test]
+    /// Test that threefold repetition is a claimable draw, and that fivefold repetition is an
+    /// automatic one.
+    fn repetition() {
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut g = Game::new();
+        play(&mut g, &shuffle);
+        assert_eq!(g.num_repetitions(), 2);
+        assert_eq!(g.termination(), None);
+        play(&mut g, &shuffle);
+        assert_eq!(g.termination(), Some(Termination::ThreefoldRepetition));
+        assert!(!Termination::ThreefoldRepetition.is_automatic());
+        play(&mut g, &shuffle);
+        play(&mut g, &shuffle);
+        assert_eq!(g.num_repetitions(), 5);
+        assert_eq!(g.termination(), Some(Termination::FivefoldRepetition));
+        assert!(Termination::FivefoldRepetition.is_automatic());
+        assert_eq!(g.result(), Some(GameResult::Draw));
+    }
+
+    #[test]
+    /// Test that the halfmove clock is read from the FEN and causes draws by the fifty and
+    /// seventy-five move rules.
+    fn move_rules() {
+        let fen = "8/8/3k4/8/8/2K4R/8/8 w - - 98 80";
+        let mut g = Game::from_fen(fen).unwrap();
+        assert_eq!(g.rule50(), 98);
+        play(&mut g, &["h3h1"]);
+        assert_eq!(g.termination(), None);
+        play(&mut g, &["d6e6"]);
+        assert_eq!(g.rule50(), 100);
+        assert_eq!(g.termination(), Some(Termination::FiftyMoveRule));
+        assert!(!Termination::FiftyMoveRule.is_automatic());
+
+        let mut g = Game::from_fen("8/8/3k4/8/8/2K4R/8/8 w - - 149 80").unwrap();
+        assert_eq!(g.termination(), Some(Termination::FiftyMoveRule));
+        play(&mut g, &["h3h1"]);
+        assert_eq!(g.termination(), Some(Termination::SeventyFiveMoveRule));
+
+        // a pawn move resets the counter
+        let mut g = Game::from_fen("8/8/3k4/8/8/2K4R/4P3/8 w - - 99 80").unwrap();
+        play(&mut g, &["e2e4"]);
+        assert_eq!(g.rule50(), 0);
+        assert_eq!(g.termination(), None);
+    }
+
+    #[test]
+    /// Test that checkmate takes priority over the fifty-move rule.
+    fn mate_beats_rule50() {
+        let mut g = Game::from_fen("3k4/R7/1R6/5K2/8/8/8/8 w - - 99 80").unwrap();
+        play(&mut g, &["b6b8"]);
+        assert_eq!(
+            g.termination(),
+            Some(Termination::Checkmate {
+                winner: Color::White
+            })
+        );
+    }
+
+    #[test]
+    /// Test that resignations and adjudications end the game until the next move is made.
+    fn resign_and_adjudicate() {
+        let mut g = Game::new();
+        g.resign(Color::White);
+        assert_eq!(
+            g.termination(),
+            Some(Termination::Resignation {
+                loser: Color::White
+            })
+        );
+        assert_eq!(g.result(), Some(GameResult::BlackWins));
+        play(&mut g, &["e2e4"]);
+        assert_eq!(g.termination(), None);
+
+        g.adjudicate(GameResult::Draw);
+        assert_eq!(g.result(), Some(GameResult::Draw));
+        assert_eq!(g.termination().unwrap().to_string(), "draw by adjudication");
+        g.undo().unwrap();
+        assert_eq!(g.termination(), None);
+
+        g.adjudicate(GameResult::WhiteWins);
+        assert_eq!(g.result(), Some(GameResult::WhiteWins));
+        g.clear();
+        assert_eq!(g, Game::new());
+    }
 }
@@ -37,6 +37,9 @@ pub use color::Color;
 mod direction;
 pub use direction::Direction;
 
+#[cfg(test)]
+pub mod fuzz;
+
 pub mod game;
 
 mod magic;
@@ -47,6 +50,8 @@ pub mod movegen;
 mod moves;
 pub use moves::Move;
 
+pub mod pgn;
+
 mod piece;
 pub use piece::Piece;
 
@@ -120,7 +120,7 @@ const fn create_step_attacks(dirs: &[Direction], max_dist: u8) -> [Bitboard; 64]
     attacks
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, std::marker::ConstParamTy)]
 /// The possible modes for move generation.
 /// They are inteded for use as const-generic parameters for `get_moves()`.
 pub enum GenMode {
@@ -151,7 +151,7 @@ impl Move {
     ///
     /// This function will return an `Err` if `s` describes an illegal UCI move.
     pub fn from_uci(s: &str, board: &Board) -> Result<Move, &'static str> {
-        if !(s.len() == 4 || s.len() == 5) {
+        if !(s.len() == 4 || s.len() == 5) || !s.is_ascii() {
             return Err("string was neither a normal move or a promotion");
         }
         let from_sq = Square::from_algebraic(&s[0..2])?;
@@ -301,17 +301,141 @@ impl Move {
     }
 
     #[allow(clippy::missing_panics_doc)]
-    /// Given the string of an algebraic-notation move, get the `Move` which can be played.
+    /// Given the string of a move in standard algebraic notation (SAN), get the `Move` which it
+    /// describes in `b`.
+    ///
+    /// The piece type, destination, disambiguation, and promotion type are resolved directly
+    /// against the legal moves in `b`.
+    /// Common deviations from strict SAN are accepted:
+    ///
+    /// * castling may be written with zeros (`0-0` and `0-0-0`),
+    /// * the capture marker `x` and the check and mate markers `+` and `#` may be missing or
+    ///   superfluous,
+    /// * the `=` of a promotion may be left out, and the promotion piece may be lowercase
+    ///   (`e8Q` or `e8q`),
+    /// * the moving piece may be disambiguated more than necessary (`Ngf3` or `Ng1f3`), and
+    /// * annotations such as `!?` and the en passant marker `e.p.` are ignored.
     ///
     /// # Errors
     ///
-    /// This function will return an `Err` if `s` is not a valid algebraically-represented move in
-    /// `b`.
+    /// This function will return an `Err` describing the problem if `s` is not well-formed SAN,
+    /// if no legal move in `b` matches it, or if it is ambiguous.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tomato::base::{Board, Move, Square};
+    ///
+    /// let b = Board::default();
+    /// let nf3 = Move::normal(Square::G1, Square::F3);
+    /// assert_eq!(Move::from_algebraic("Nf3", &b), Ok(nf3));
+    /// assert_eq!(Move::from_algebraic("Ng1f3+", &b), Ok(nf3));
+    /// assert!(Move::from_algebraic("Nd4", &b).is_err());
+    /// ```
     pub fn from_algebraic(s: &str, b: &Board) -> Result<Move, &'static str> {
-        get_moves::<{ GenMode::All }>(b)
+        if !s.is_ascii() {
+            return Err("non-ASCII character in algebraic move");
+        }
+        let s = s.trim().trim_end_matches(['!', '?']);
+        let s = s.strip_suffix("e.p.").unwrap_or(s).trim_end();
+        let s = s.trim_end_matches(['+', '#']);
+
+        let moves = get_moves::<{ GenMode::All }>(b);
+
+        let castle_kingside = match s {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+        if let Some(kingside) = castle_kingside {
+            return moves
+                .into_iter()
+                .find(|m| {
+                    m.is_castle() && (m.from_square().file() < m.to_square().file()) == kingside
+                })
+                .ok_or("castling is not legal in this position");
+        }
+
+        let mut body = s.as_bytes();
+        let mover_type = match body.first() {
+            None => return Err("empty algebraic move"),
+            Some(&c @ (b'K' | b'Q' | b'R' | b'B' | b'N' | b'P')) => {
+                body = &body[1..];
+                Piece::from_code(char::from(c)).unwrap()
+            }
+            Some(c) if c.is_ascii_uppercase() => return Err("unrecognized piece type"),
+            Some(_) => Piece::Pawn,
+        };
+
+        // the promotion type comes at the end, possibly after an `=`
+        let mut promote_type = None;
+        if let [rest @ .., sep, promote_code] = body {
+            if sep.is_ascii_digit() || *sep == b'=' {
+                let code = char::from(promote_code.to_ascii_uppercase());
+                match Piece::from_code(code) {
+                    Some(p) if Piece::PROMOTING.contains(&p) => {
+                        promote_type = Some(p);
+                        body = if *sep == b'=' {
+                            rest
+                        } else {
+                            &body[..body.len() - 1]
+                        };
+                    }
+                    _ if *sep == b'=' => return Err("invalid promotion type"),
+                    _ => (),
+                }
+            }
+        }
+
+        let [rest @ .., file @ b'a'..=b'h', rank @ b'1'..=b'8'] = body else {
+            return Err("missing or invalid destination square");
+        };
+        let to_sq = Square::new(rank - b'1', file - b'a').unwrap();
+
+        let rest = match rest {
+            [rest @ .., b'x' | b':'] => rest,
+            _ => rest,
+        };
+        let (from_file, from_rank) = match *rest {
+            // a pawn which is not identified by its file can only be pushing forward
+            [] if mover_type == Piece::Pawn => (Some(to_sq.file()), None),
+            [] => (None, None),
+            [file @ b'a'..=b'h'] => (Some(file - b'a'), None),
+            [rank @ b'1'..=b'8'] => (None, Some(rank - b'1')),
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => (Some(file - b'a'), Some(rank - b'1')),
+            _ => return Err("invalid disambiguation of moving piece"),
+        };
+
+        if promote_type.is_some() && mover_type != Piece::Pawn {
+            return Err("only pawns can promote");
+        }
+        if promote_type.is_none()
+            && mover_type == Piece::Pawn
+            && (to_sq.rank() == 0 || to_sq.rank() == 7)
+        {
+            return Err("missing promotion type");
+        }
+
+        let reaching: Vec<Move> = moves
             .into_iter()
-            .find(|m| m.to_algebraic(b).unwrap().as_str() == s)
-            .ok_or("not a legal algebraic move")
+            .filter(|m| {
+                m.to_square() == to_sq
+                    && b.type_at_square(m.from_square()) == Some(mover_type)
+                    && m.promote_type() == promote_type
+            })
+            .collect();
+        if reaching.is_empty() {
+            return Err("no piece of the given type can move to the destination square");
+        }
+        let mut matching = reaching.into_iter().filter(|m| {
+            (from_file.is_none() || from_file == Some(m.from_square().file()))
+                && (from_rank.is_none() || from_rank == Some(m.from_square().rank()))
+        });
+        match (matching.next(), matching.next()) {
+            (Some(m), None) => Ok(m),
+            (None, _) => Err("no piece on the disambiguating square can make the move"),
+            (Some(_), Some(_)) => Err("ambiguous algebraic move"),
+        }
     }
 
     #[inline(always)]
@@ -493,4 +617,205 @@ mod tests {
         assert_eq!(m.to_algebraic(&b).unwrap(), s);
         assert_eq!(Move::from_algebraic(s, &b).unwrap(), m);
     }
+
+    /// Check that each of the given algebraic strings resolves to the move `m` in `b`.
+    fn algebraic_helper(fen: &str, strs: &[&str], m: Move) {
+        let b = Board::from_fen(fen).unwrap();
+        for s in strs {
+            assert_eq!(Move::from_algebraic(s, &b), Ok(m), "{s}");
+        }
+    }
+
+    /// Check that each of the given algebraic strings is rejected in `b` with the given error.
+    fn algebraic_error_helper(fen: &str, strs: &[&str], err: &str) {
+        let b = Board::from_fen(fen).unwrap();
+        for s in strs {
+            assert_eq!(Move::from_algebraic(s, &b), Err(err), "{s}");
+        }
+    }
+
+    #[test]
+    /// Test that moves which need no disambiguation are accepted with or without it.
+    fn algebraic_redundant_disambiguation() {
+        algebraic_helper(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[
+                "Nf3", "Ngf3", "N1f3", "Ng1f3", "Nxf3", "Ng1xf3", "Nf3+", "Nf3!?",
+            ],
+            Move::normal(Square::G1, Square::F3),
+        );
+    }
+
+    #[test]
+    /// Test that two pieces on the same rank are told apart by their files.
+    fn algebraic_file_identifier() {
+        let fen = "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1";
+        algebraic_helper(
+            fen,
+            &["Nbd2", "Nb1d2"],
+            Move::normal(Square::B1, Square::D2),
+        );
+        algebraic_helper(
+            fen,
+            &["Nfd2", "Nf1d2"],
+            Move::normal(Square::F1, Square::D2),
+        );
+        algebraic_error_helper(fen, &["Nd2", "N1d2"], "ambiguous algebraic move");
+        algebraic_error_helper(
+            fen,
+            &["Ncd2", "N2d2"],
+            "no piece on the disambiguating square can make the move",
+        );
+    }
+
+    #[test]
+    /// Test that two pieces on the same file are told apart by their ranks.
+    fn algebraic_rank_identifier_lenient() {
+        let fen = "4k3/8/8/8/8/1N6/8/1N2K3 w - - 0 1";
+        algebraic_helper(
+            fen,
+            &["N1d2", "Nb1d2"],
+            Move::normal(Square::B1, Square::D2),
+        );
+        algebraic_helper(
+            fen,
+            &["N3d2", "Nb3d2"],
+            Move::normal(Square::B3, Square::D2),
+        );
+        algebraic_error_helper(fen, &["Nd2", "Nbd2"], "ambiguous algebraic move");
+    }
+
+    #[test]
+    /// Test that a piece which shares both a rank and a file with other pieces that could make
+    /// the same move must be identified by its square.
+    fn algebraic_square_identifier() {
+        let fen = "2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1";
+        algebraic_helper(
+            fen,
+            &["Qh4e1", "Qh4xe1"],
+            Move::normal(Square::H4, Square::E1),
+        );
+        algebraic_helper(
+            fen,
+            &["Qee1", "Qe4e1"],
+            Move::normal(Square::E4, Square::E1),
+        );
+        algebraic_helper(
+            fen,
+            &["Q1e1", "Qh1e1"],
+            Move::normal(Square::H1, Square::E1),
+        );
+        algebraic_error_helper(fen, &["Qe1", "Qhe1", "Q4e1"], "ambiguous algebraic move");
+    }
+
+    #[test]
+    /// Test that pawn captures are identified by the file of the capturing pawn.
+    fn algebraic_pawn_identifier() {
+        let fen = "4k3/8/8/3p4/2P1P3/8/8/4K3 w - - 0 1";
+        algebraic_helper(
+            fen,
+            &["exd5", "ed5", "e4xd5", "exd5+"],
+            Move::normal(Square::E4, Square::D5),
+        );
+        algebraic_helper(fen, &["cxd5", "cd5"], Move::normal(Square::C4, Square::D5));
+        algebraic_error_helper(
+            fen,
+            &["d5", "xd5"],
+            "no piece on the disambiguating square can make the move",
+        );
+        algebraic_error_helper(
+            fen,
+            &["exf5", "Nd5"],
+            "no piece of the given type can move to the destination square",
+        );
+    }
+
+    #[test]
+    /// Test that en passant captures can be written with or without a marker.
+    fn algebraic_en_passant() {
+        algebraic_helper(
+            "rnbqkbnr/ppppp1pp/8/4Pp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            &["exf6", "exf6 e.p.", "exf6e.p.", "ef6"],
+            Move::en_passant(Square::E5, Square::F6),
+        );
+    }
+
+    #[test]
+    /// Test that promotions are accepted with and without `=`, in either case.
+    fn algebraic_promotions() {
+        let fen = "3r3k/4P3/8/8/8/8/8/K7 w - - 0 1";
+        algebraic_helper(
+            fen,
+            &["e8=Q", "e8Q", "e8q", "e8=Q+", "e8=q"],
+            Move::promoting(Square::E7, Square::E8, Piece::Queen),
+        );
+        algebraic_helper(
+            fen,
+            &["exd8=N", "exd8N", "ed8n", "exd8=N!"],
+            Move::promoting(Square::E7, Square::D8, Piece::Knight),
+        );
+        algebraic_error_helper(fen, &["e8", "exd8"], "missing promotion type");
+        algebraic_error_helper(fen, &["e8=K", "e8=P"], "invalid promotion type");
+        algebraic_error_helper(fen, &["Kb2=Q"], "only pawns can promote");
+    }
+
+    #[test]
+    /// Test that castling can be written with letters or zeros.
+    fn algebraic_castling() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let b = Board::from_fen(fen).unwrap();
+        for s in ["O-O", "0-0", "O-O+"] {
+            let m = Move::from_algebraic(s, &b).unwrap();
+            assert!(m.is_castle());
+            assert_eq!(m.to_algebraic(&b).unwrap(), "O-O");
+        }
+        for s in ["O-O-O", "0-0-0"] {
+            let m = Move::from_algebraic(s, &b).unwrap();
+            assert!(m.is_castle());
+            assert_eq!(m.to_algebraic(&b).unwrap(), "O-O-O");
+        }
+        algebraic_error_helper(
+            "r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1",
+            &["O-O", "0-0-0"],
+            "castling is not legal in this position",
+        );
+    }
+
+    #[test]
+    /// Test that malformed strings are rejected with a description of the problem.
+    fn algebraic_errors() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        algebraic_error_helper(fen, &["", "+", "!!"], "empty algebraic move");
+        algebraic_error_helper(
+            fen,
+            &["Nf9", "N", "--", "Ni3", "e4x"],
+            "missing or invalid destination square",
+        );
+        algebraic_error_helper(fen, &["Zf3", "Xe4"], "unrecognized piece type");
+        algebraic_error_helper(
+            fen,
+            &["Nxxf3", "Nz1f3", "Ng1g2f3"],
+            "invalid disambiguation of moving piece",
+        );
+        algebraic_error_helper(fen, &["Nf3\u{e9}"], "non-ASCII character in algebraic move");
+    }
+
+    #[test]
+    /// Test that every legal move in a collection of positions survives conversion to algebraic
+    /// notation and back.
+    fn algebraic_round_trip() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            "2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            let b = Board::from_fen(fen).expect(fen);
+            for m in get_moves::<{ GenMode::All }>(&b) {
+                let s = m.to_algebraic(&b).unwrap();
+                assert_eq!(Move::from_algebraic(&s, &b), Ok(m), "{s} in {fen}");
+            }
+        }
+    }
 }
@@ -0,0 +1,997 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Reading and writing games in Portable Game Notation (PGN).
+//!
+//! A PGN file is a sequence of games, each made of a section of tag pairs (such as
+//! `[White "Tomato"]`) followed by movetext.
+//! Movetext consists of moves in standard algebraic notation, along with move numbers, comments,
+//! numeric annotation glyphs (NAGs), recursive variations, and finally a result token.
+//!
+//! `PgnReader` reads games one at a time from any buffered input, so that arbitrarily large files
+//! can be processed without holding more than one game in memory.
+//! Games are written back out through the `Display` implementation of `PgnGame`.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    io::BufRead,
+    mem::take,
+    str::FromStr,
+};
+
+use super::{
+    game::{Game, GameResult},
+    Board, Color, Move,
+};
+
+/// The column after which the movetext of a written game is wrapped onto a new line.
+const LINE_WIDTH: usize = 80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// The result of a game, as recorded in PGN.
+pub enum PgnResult {
+    /// White won the game (`1-0`).
+    WhiteWins,
+    /// Black won the game (`0-1`).
+    BlackWins,
+    /// The game was drawn (`1/2-1/2`).
+    Draw,
+    /// The game is still in progress, was abandoned, or its result is unknown (`*`).
+    Unfinished,
+}
+
+impl PgnResult {
+    #[must_use]
+    /// Get the result described by a PGN result token, if `token` is one.
+    pub fn from_token(token: &str) -> Option<PgnResult> {
+        match token {
+            "1-0" => Some(PgnResult::WhiteWins),
+            "0-1" => Some(PgnResult::BlackWins),
+            "1/2-1/2" => Some(PgnResult::Draw),
+            "*" => Some(PgnResult::Unfinished),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    /// Get the PGN token describing this result.
+    pub const fn token(self) -> &'static str {
+        match self {
+            PgnResult::WhiteWins => "1-0",
+            PgnResult::BlackWins => "0-1",
+            PgnResult::Draw => "1/2-1/2",
+            PgnResult::Unfinished => "*",
+        }
+    }
+}
+
+impl From<GameResult> for PgnResult {
+    fn from(result: GameResult) -> PgnResult {
+        match result {
+            GameResult::WhiteWins => PgnResult::WhiteWins,
+            GameResult::BlackWins => PgnResult::BlackWins,
+            GameResult::Draw => PgnResult::Draw,
+        }
+    }
+}
+
+impl Display for PgnResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.token())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A move in a PGN game, along with all the annotations attached to it.
+pub struct PgnMove {
+    /// The move played.
+    pub m: Move,
+    /// The comments which came immediately before this move.
+    pub comments_before: Vec<String>,
+    /// The numeric annotation glyphs attached to this move, such as 1 for `!` or 2 for `?`.
+    pub nags: Vec<u8>,
+    /// The comments which came immediately after this move.
+    pub comments_after: Vec<String>,
+    /// Alternative lines which could have been played instead of this move.
+    /// Each variation starts from the position just before this move.
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    #[must_use]
+    /// Construct a `PgnMove` with no annotations.
+    pub fn new(m: Move) -> PgnMove {
+        PgnMove {
+            m,
+            comments_before: Vec::new(),
+            nags: Vec::new(),
+            comments_after: Vec::new(),
+            variations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A single game stored in PGN.
+pub struct PgnGame {
+    /// The tag pairs of this game, in the order in which they appear.
+    pub tags: Vec<(String, String)>,
+    /// The game reached by playing out the main line.
+    pub game: Game,
+    /// The moves of the main line, along with their annotations.
+    /// These are the same moves as those played in `game`.
+    pub moves: Vec<PgnMove>,
+    /// Comments in the movetext which are not attached to any move.
+    /// These only occur in games where no moves were played.
+    pub comments: Vec<String>,
+    /// The result token at the end of the movetext.
+    pub result: PgnResult,
+}
+
+impl PgnGame {
+    #[must_use]
+    /// Construct a `PgnGame` describing the main line of `game`, with the given result.
+    /// The game receives the seven tags required by the PGN standard, with unknown values, as well
+    /// as a `FEN` tag if the game did not start from the standard starting position.
+    pub fn from_game(game: &Game, result: PgnResult) -> PgnGame {
+        let mut tags: Vec<(String, String)> = [
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", result.token()),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        if *game.start() != Board::default() {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), game.start().to_fen()));
+        }
+
+        PgnGame {
+            tags,
+            game: game.clone(),
+            moves: game.moves().iter().copied().map(PgnMove::new).collect(),
+            comments: Vec::new(),
+            result,
+        }
+    }
+
+    #[must_use]
+    /// Get the value of the first tag with the given name, if there is one.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Set the value of the tag with the given name, adding the tag if it is not already present.
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            Some((_, old_value)) => *old_value = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    #[must_use]
+    /// Get the number of the first full move in this game, as given by its `FEN` tag.
+    fn first_move_number(&self) -> usize {
+        self.tag("FEN")
+            .and_then(|fen| fen.split_ascii_whitespace().nth(5))
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1)
+    }
+}
+
+impl Display for PgnGame {
+    /// Write this game in PGN, with its movetext wrapped to 80 columns.
+    /// Annotation glyphs are always written in their numeric form (e.g. `$1` rather than `!`).
+    /// Since PGN comments cannot contain a closing brace, any closing braces in comments are
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any of the moves in this game are illegal.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.tags {
+            let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{name} \"{escaped}\"]")?;
+        }
+        writeln!(f)?;
+
+        let mut tokens: Vec<String> = self.comments.iter().map(|c| comment_token(c)).collect();
+        let mut start = self.game.clone();
+        start.clear();
+        let numbering = Numbering {
+            first_move: self.first_move_number(),
+            black_first: start.board().player == Color::Black,
+        };
+        write_line(&mut tokens, &mut start, &self.moves, numbering)?;
+        tokens.push(self.result.token().to_string());
+
+        let mut width = 0;
+        for token in tokens {
+            if 0 < width {
+                if LINE_WIDTH < width + 1 + token.len() {
+                    writeln!(f)?;
+                    width = 0;
+                } else {
+                    write!(f, " ")?;
+                    width += 1;
+                }
+            }
+            write!(f, "{token}")?;
+            width += token.len();
+        }
+        writeln!(f)
+    }
+}
+
+impl FromStr for PgnGame {
+    type Err = String;
+
+    /// Parse the first game in a PGN string.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is no game in `s` or if the first game is
+    /// malformed.
+    fn from_str(s: &str) -> Result<PgnGame, String> {
+        PgnReader::new(s.as_bytes())
+            .next()
+            .unwrap_or_else(|| Err("no game found".to_string()))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// The information needed to number the moves of a game.
+struct Numbering {
+    /// The number of the first full move of the game.
+    first_move: usize,
+    /// Whether Black made the first move of the game.
+    black_first: bool,
+}
+
+/// Construct the token for a comment, dropping any characters which would end it early.
+fn comment_token(comment: &str) -> String {
+    format!("{{{}}}", comment.replace('}', ""))
+}
+
+/// Write the tokens for a line of moves, starting from the position in `g`, to `tokens`.
+/// `g` will be left at the end of the line.
+///
+/// # Errors
+///
+/// This function will return an error if any of the moves in the line are illegal.
+fn write_line(
+    tokens: &mut Vec<String>,
+    g: &mut Game,
+    line: &[PgnMove],
+    numbering: Numbering,
+) -> fmt::Result {
+    // Black's moves only need a number if something interrupted the flow of the movetext since
+    // White's move
+    let mut needs_number = true;
+    for pm in line {
+        if !pm.comments_before.is_empty() {
+            tokens.extend(pm.comments_before.iter().map(|c| comment_token(c)));
+            needs_number = true;
+        }
+        let ply = g.len() - 1 + usize::from(numbering.black_first);
+        let number = numbering.first_move + ply / 2;
+        if ply & 1 == 0 {
+            tokens.push(format!("{number}."));
+        } else if needs_number {
+            tokens.push(format!("{number}..."));
+        }
+        tokens.push(pm.m.to_algebraic(g.board()).map_err(|_| fmt::Error)?);
+        needs_number = false;
+
+        tokens.extend(pm.nags.iter().map(|nag| format!("${nag}")));
+        if !pm.comments_after.is_empty() {
+            tokens.extend(pm.comments_after.iter().map(|c| comment_token(c)));
+            needs_number = true;
+        }
+        for variation in pm.variations.iter().filter(|v| !v.is_empty()) {
+            let mut var_tokens = Vec::new();
+            write_line(&mut var_tokens, &mut g.clone(), variation, numbering)?;
+            // the parentheses hug the first and last tokens of the variation
+            var_tokens[0].insert(0, '(');
+            var_tokens.last_mut().unwrap().push(')');
+            tokens.append(&mut var_tokens);
+            needs_number = true;
+        }
+        g.make_move(pm.m);
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A token in a PGN file.
+enum Token {
+    /// A tag pair, containing the name and (unescaped) value of the tag.
+    Tag(String, String),
+    /// A move number indication.
+    /// The periods following the number are ignored.
+    MoveNumber,
+    /// A move in standard algebraic notation.
+    San(String),
+    /// A numeric annotation glyph, either given by number (e.g. `$1`) or as a suffix (e.g. `!`).
+    Nag(u8),
+    /// A comment, with surrounding whitespace removed.
+    Comment(String),
+    /// The start of a recursive variation.
+    Open,
+    /// The end of a recursive variation.
+    Close,
+    /// A game termination marker.
+    Result(PgnResult),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A line of moves read from movetext.
+struct Line {
+    /// The moves of the line.
+    moves: Vec<PgnMove>,
+    /// The comments in the line which were not attached to any move.
+    comments: Vec<String>,
+    /// The result token which ended the line, if any.
+    result: Option<PgnResult>,
+}
+
+#[derive(Debug)]
+/// A reader which parses PGN games one at a time from an input.
+///
+/// Each call to `next` yields either the next game or an error describing why it could not be
+/// read.
+/// After an error, the reader skips ahead to the next game, so one malformed game does not
+/// prevent reading the rest of a file.
+///
+/// # Examples
+///
+/// ```
+/// use tomato::base::pgn::{PgnReader, PgnResult};
+///
+/// let pgn = "[White \"Tomato\"]\n\n1. e4 e5 2. Nf3 {the king's knight} Nc6 1-0\n\n1. d4 *\n";
+/// let games: Vec<_> = PgnReader::new(pgn.as_bytes()).collect();
+/// assert_eq!(games.len(), 2);
+/// let first = games[0].as_ref().unwrap();
+/// assert_eq!(first.tag("White"), Some("Tomato"));
+/// assert_eq!(first.moves.len(), 4);
+/// assert_eq!(first.result, PgnResult::WhiteWins);
+/// ```
+pub struct PgnReader<R> {
+    /// The source of PGN text.
+    input: R,
+    /// The line of input currently being tokenized.
+    line: String,
+    /// The byte offset of the next character to be tokenized in `line`.
+    pos: usize,
+    /// The number of lines read so far, for error reporting.
+    line_number: usize,
+    /// A token which was read but not yet used.
+    peeked: Option<Token>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    /// Construct a new reader which will read games from `input`.
+    pub fn new(input: R) -> PgnReader<R> {
+        PgnReader {
+            input,
+            line: String::new(),
+            pos: 0,
+            line_number: 0,
+            peeked: None,
+        }
+    }
+
+    /// Construct an error message pointing at the current line of input.
+    fn error(&self, msg: &str) -> String {
+        format!("line {}: {msg}", self.line_number)
+    }
+
+    /// Get the next character in the current line without consuming it.
+    fn peek_char(&self) -> Option<char> {
+        self.line[self.pos..].chars().next()
+    }
+
+    /// Consume and return the next character in the current line.
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.peek_char()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Move on to the next line of input.
+    /// Returns `false` if the input is exhausted.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the input could not be read.
+    fn next_line(&mut self) -> Result<bool, String> {
+        self.line.clear();
+        self.pos = 0;
+        let n_read = self
+            .input
+            .read_line(&mut self.line)
+            .map_err(|e| self.error(&format!("could not read input: {e}")))?;
+        self.line_number += 1;
+        if self.line.starts_with('%') {
+            // escaped line: ignore it entirely
+            self.pos = self.line.len();
+        }
+        Ok(n_read != 0)
+    }
+
+    /// Consume characters from the current line while they satisfy `pred`, returning them.
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while let Some(c) = self.peek_char() {
+            if !pred(c) {
+                break;
+            }
+            self.next_char();
+        }
+        self.line[start..self.pos].to_string()
+    }
+
+    /// Read the next token, returning `None` once the input is exhausted.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the input is not valid PGN.
+    fn next_token(&mut self) -> Result<Option<Token>, String> {
+        if let Some(tok) = self.peeked.take() {
+            return Ok(Some(tok));
+        }
+        loop {
+            let Some(c) = self.next_char() else {
+                if self.next_line()? {
+                    continue;
+                }
+                return Ok(None);
+            };
+            return Ok(Some(match c {
+                c if c.is_whitespace() || c == '.' => continue,
+                '[' => self.tag()?,
+                '{' => self.brace_comment()?,
+                ';' => {
+                    let comment = self.take_while(|c| c != '\n');
+                    Token::Comment(comment.trim().to_string())
+                }
+                '(' => Token::Open,
+                ')' => Token::Close,
+                '*' => Token::Result(PgnResult::Unfinished),
+                '$' => {
+                    let digits = self.take_while(|c| c.is_ascii_digit());
+                    Token::Nag(
+                        digits
+                            .parse()
+                            .map_err(|_| self.error(&format!("invalid NAG `${digits}`")))?,
+                    )
+                }
+                '!' | '?' => {
+                    let suffix = format!("{c}{}", self.take_while(|c| c == '!' || c == '?'));
+                    Token::Nag(match suffix.as_str() {
+                        "!" => 1,
+                        "?" => 2,
+                        "!!" => 3,
+                        "??" => 4,
+                        "!?" => 5,
+                        "?!" => 6,
+                        _ => return Err(self.error(&format!("invalid annotation `{suffix}`"))),
+                    })
+                }
+                c if c.is_ascii_alphanumeric() => {
+                    let symbol = format!("{c}{}", self.take_while(is_symbol_char));
+                    if let Some(result) = PgnResult::from_token(&symbol) {
+                        Token::Result(result)
+                    } else if symbol.bytes().all(|b| b.is_ascii_digit()) {
+                        Token::MoveNumber
+                    } else {
+                        Token::San(symbol)
+                    }
+                }
+                c => return Err(self.error(&format!("unexpected character `{c}`"))),
+            }));
+        }
+    }
+
+    /// Read the rest of a tag pair, after its opening bracket.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the tag pair is malformed.
+    fn tag(&mut self) -> Result<Token, String> {
+        self.take_while(char::is_whitespace);
+        let name = self.take_while(is_symbol_char);
+        if name.is_empty() {
+            return Err(self.error("missing tag name"));
+        }
+        self.take_while(char::is_whitespace);
+        if self.next_char() != Some('"') {
+            return Err(self.error(&format!("missing value for tag `{name}`")));
+        }
+        let mut value = String::new();
+        loop {
+            match self.next_char() {
+                Some('"') => break,
+                Some('\\') => match self.next_char() {
+                    Some(c @ ('"' | '\\')) => value.push(c),
+                    _ => return Err(self.error("invalid escape in tag value")),
+                },
+                Some('\n') | None => {
+                    return Err(self.error(&format!("unterminated value for tag `{name}`")))
+                }
+                Some(c) => value.push(c),
+            }
+        }
+        self.take_while(char::is_whitespace);
+        if self.next_char() != Some(']') {
+            return Err(self.error(&format!("missing `]` after tag `{name}`")));
+        }
+        Ok(Token::Tag(name, value))
+    }
+
+    /// Read the rest of a brace comment, after its opening brace.
+    /// Brace comments may span multiple lines.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the input ends before the comment does.
+    fn brace_comment(&mut self) -> Result<Token, String> {
+        let mut comment = String::new();
+        loop {
+            comment += &self.take_while(|c| c != '}');
+            if self.next_char() == Some('}') {
+                return Ok(Token::Comment(comment.trim().to_string()));
+            }
+            if !self.next_line()? {
+                return Err(self.error("unterminated comment"));
+            }
+        }
+    }
+
+    /// Read the next game.
+    /// Returns `None` if there are no more games.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the game is malformed or contains an illegal move.
+    fn read_game(&mut self) -> Result<Option<PgnGame>, String> {
+        let mut tags = Vec::new();
+        let first = loop {
+            match self.next_token()? {
+                Some(Token::Tag(name, value)) => tags.push((name, value)),
+                tok => break tok,
+            }
+        };
+        if first.is_none() && tags.is_empty() {
+            return Ok(None);
+        }
+        self.peeked = first;
+
+        let mut game = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => Game::from_fen(fen).map_err(|e| self.error(e))?,
+            None => Game::new(),
+        };
+        let Line {
+            moves,
+            comments,
+            result,
+        } = self.read_movetext(&mut game, false)?;
+        // if the movetext was cut off, fall back on the result given by the tags
+        let result = result
+            .or_else(|| {
+                tags.iter()
+                    .find(|(name, _)| name == "Result")
+                    .and_then(|(_, value)| PgnResult::from_token(value))
+            })
+            .unwrap_or(PgnResult::Unfinished);
+
+        Ok(Some(PgnGame {
+            tags,
+            game,
+            moves,
+            comments,
+            result,
+        }))
+    }
+
+    /// Read a line of moves, playing them out on `g`.
+    /// If `in_variation` is true, the line must end with a closing parenthesis; otherwise, it
+    /// ends with a result token, the start of the next game, or the end of the input.
+    ///
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the line is malformed or contains an illegal move.
+    fn read_movetext(&mut self, g: &mut Game, in_variation: bool) -> Result<Line, String> {
+        let mut line: Vec<PgnMove> = Vec::new();
+        // comments waiting to be attached to the next move
+        let mut pending = Vec::new();
+        // whether a variation has closed since the most recent move
+        let mut after_variation = false;
+        let result = loop {
+            match self.next_token()? {
+                None if in_variation => return Err(self.error("unterminated variation")),
+                None => break None,
+                Some(tok @ Token::Tag(..)) => {
+                    if in_variation {
+                        return Err(self.error("tag pair inside variation"));
+                    }
+                    // this is the start of the next game
+                    self.peeked = Some(tok);
+                    break None;
+                }
+                Some(Token::MoveNumber) => (),
+                Some(Token::San(san)) => {
+                    let m = Move::from_algebraic(&san, g.board())
+                        .map_err(|e| self.error(&format!("{e}: `{san}`")))?;
+                    let mut pm = PgnMove::new(m);
+                    pm.comments_before = take(&mut pending);
+                    line.push(pm);
+                    g.make_move(m);
+                    after_variation = false;
+                }
+                Some(Token::Nag(nag)) => match line.last_mut() {
+                    Some(pm) => pm.nags.push(nag),
+                    None => return Err(self.error("annotation before any move")),
+                },
+                Some(Token::Comment(comment)) => match line.last_mut() {
+                    Some(pm) if pending.is_empty() && !after_variation => {
+                        pm.comments_after.push(comment);
+                    }
+                    _ => pending.push(comment),
+                },
+                Some(Token::Open) => {
+                    let Some(pm) = line.last_mut() else {
+                        return Err(self.error("variation before any move"));
+                    };
+                    // the variation is an alternative to the most recent move
+                    let mut var_game = g.clone();
+                    var_game.undo().map_err(|e| self.error(e))?;
+                    let variation = self.read_movetext(&mut var_game, true)?;
+                    pm.variations.push(variation.moves);
+                    after_variation = true;
+                }
+                Some(Token::Close) if in_variation => break None,
+                Some(Token::Close) => return Err(self.error("unmatched `)`")),
+                Some(Token::Result(_)) if in_variation => {
+                    return Err(self.error("result inside variation"))
+                }
+                Some(Token::Result(result)) => break Some(result),
+            }
+        };
+
+        if in_variation && line.is_empty() {
+            return Err(self.error("empty variation"));
+        }
+        // trailing comments belong to the last move, if there is one
+        if let Some(pm) = line.last_mut() {
+            pm.comments_after.append(&mut pending);
+        }
+        Ok(Line {
+            moves: line,
+            comments: pending,
+            result,
+        })
+    }
+
+    /// Skip the rest of a malformed game, stopping after its result token or just before the
+    /// start of the next game.
+    fn skip_game(&mut self) {
+        loop {
+            match self.next_token() {
+                Ok(None | Some(Token::Result(_))) => return,
+                Ok(Some(tok @ Token::Tag(..))) => {
+                    self.peeked = Some(tok);
+                    return;
+                }
+                Ok(Some(_)) => (),
+                // give up on the rest of the line, or on the whole input if it cannot be read
+                Err(_) => {
+                    self.pos = self.line.len();
+                    if self.line.is_empty() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_game() {
+            Ok(game) => game.map(Ok),
+            Err(e) => {
+                self.skip_game();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Determine whether `c` may be part of a PGN symbol, such as a tag name or a move.
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_+#=:-/".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Read};
+
+    use super::*;
+    use crate::base::{movegen::GenMode, Square};
+
+    /// Games which exercise every feature of PGN that we support.
+    const GAMES: [&str; 7] = [
+        "[Event \"Scholar's mate\"]\n[Result \"1-0\"]\n\n\
+         1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6?? 4. Qxf7# 1-0\n",
+        "1. e4 e5 (1... c5 2. Nf3 (2. c3 d5) 2... d6) 2. Nf3 {the main line} Nc6 $1 \
+         3. Bb5 a6 *\n",
+        "[SetUp \"1\"]\n[FEN \"3k4/R7/1R6/5K2/8/8/8/8 b - - 0 40\"]\n\n\
+         40... Ke8 (40... Kc8 {also losing}) 41. Rb8# 1-0\n",
+        "[FEN \"4k3/1P6/8/8/8/8/8/R3K2R w KQ - 0 1\"]\n\n\
+         1. O-O-O Kf7 2. b8=Q Kg6 1/2-1/2\n",
+        "[Event \"The \\\"Big\\\" one \\\\ 2023\"]\n\n\
+         {a comment before any moves} 1. d4 ; a comment to the end of the line\n\
+         % an escaped line\n\
+         d5 {a comment\nspanning lines} 2. c4 !? dxc4 $6 $18 0-1\n",
+        "{nothing happened in this game} *\n",
+        "1. Nf3 (1. e4 (1. d4 d5) 1... e5 (1... c5 2. Nf3) 2. Nf3) (1. c4) 1... Nf6 \
+         {a} {b} (1... d5 {c}) {d} 2. g3 *\n",
+    ];
+
+    /// Parse a game, write it back out, and check that parsing the written game gives the same
+    /// result, and that writing it again gives the same text.
+    fn round_trip(pgn: &str) -> PgnGame {
+        let parsed: PgnGame = pgn.parse().unwrap();
+        let written = parsed.to_string();
+        let reparsed: PgnGame = written.parse().unwrap();
+        assert_eq!(parsed, reparsed, "written form:\n{written}");
+        assert_eq!(reparsed.to_string(), written);
+        parsed
+    }
+
+    #[test]
+    /// Test that every game in the suite survives being written out and read back in.
+    fn round_trip_suite() {
+        for pgn in GAMES {
+            round_trip(pgn);
+        }
+    }
+
+    #[test]
+    /// Test that randomly-played games survive being written out and read back in.
+    fn random_games_round_trip() {
+        let rng = fastrand::Rng::with_seed(1862);
+        for _ in 0..20 {
+            let mut g = Game::new();
+            for _ in 0..rng.usize(0..120) {
+                let moves = g.get_moves::<{ GenMode::All }>();
+                if moves.is_empty() {
+                    break;
+                }
+                g.make_move(moves[rng.usize(..moves.len())]);
+            }
+            let pgn = PgnGame::from_game(&g, PgnResult::Unfinished);
+            assert_eq!(round_trip(&pgn.to_string()).game, g);
+        }
+    }
+
+    #[test]
+    /// Test that tags are read in order and with escapes resolved.
+    fn tags() {
+        let pgn: PgnGame = GAMES[4].parse().unwrap();
+        assert_eq!(
+            pgn.tags,
+            vec![("Event".to_string(), "The \"Big\" one \\ 2023".to_string())]
+        );
+        assert_eq!(pgn.tag("Event"), Some("The \"Big\" one \\ 2023"));
+        assert_eq!(pgn.tag("Site"), None);
+    }
+
+    #[test]
+    /// Test that comments and annotations are attached to the right moves.
+    fn annotations() {
+        let pgn: PgnGame = GAMES[4].parse().unwrap();
+        assert!(pgn.comments.is_empty());
+        assert_eq!(pgn.moves.len(), 4);
+        assert_eq!(pgn.moves[0].comments_before, ["a comment before any moves"]);
+        assert_eq!(
+            pgn.moves[0].comments_after,
+            ["a comment to the end of the line"]
+        );
+        assert_eq!(pgn.moves[1].comments_after, ["a comment\nspanning lines"]);
+        assert_eq!(pgn.moves[2].nags, [5]);
+        assert_eq!(pgn.moves[3].nags, [6, 18]);
+        assert_eq!(pgn.result, PgnResult::BlackWins);
+
+        let pgn: PgnGame = GAMES[0].parse().unwrap();
+        assert_eq!(pgn.moves[5].nags, [4]);
+        assert_eq!(pgn.game.end_state(), Some(true));
+
+        let pgn: PgnGame = GAMES[5].parse().unwrap();
+        assert!(pgn.moves.is_empty());
+        assert_eq!(pgn.comments, ["nothing happened in this game"]);
+    }
+
+    #[test]
+    /// Test that variations branch off from the position before the move they replace.
+    fn variations() {
+        let pgn: PgnGame = GAMES[1].parse().unwrap();
+        assert_eq!(pgn.moves.len(), 6);
+        let e5 = &pgn.moves[1];
+        assert_eq!(e5.variations.len(), 1);
+        let sicilian = &e5.variations[0];
+        assert_eq!(sicilian[0].m, Move::normal(Square::C7, Square::C5));
+        assert_eq!(sicilian.len(), 3);
+        assert_eq!(
+            sicilian[1].variations,
+            [vec![
+                PgnMove::new(Move::normal(Square::C2, Square::C3)),
+                PgnMove::new(Move::normal(Square::D7, Square::D5)),
+            ]]
+        );
+
+        let pgn: PgnGame = GAMES[6].parse().unwrap();
+        let nf3 = &pgn.moves[0];
+        assert_eq!(nf3.variations.len(), 2);
+        assert_eq!(nf3.variations[0][0].variations[0].len(), 2);
+        let nf6 = &pgn.moves[1];
+        assert_eq!(nf6.comments_after, ["a", "b"]);
+        assert_eq!(nf6.variations[0][0].comments_after, ["c"]);
+        assert_eq!(pgn.moves[2].comments_before, ["d"]);
+    }
+
+    #[test]
+    /// Test that games may start from a FEN, with move numbers continuing from the FEN.
+    fn fen_start() {
+        let pgn = round_trip(GAMES[2]);
+        assert_eq!(pgn.moves.len(), 2);
+        assert_eq!(pgn.game.end_state(), Some(true));
+        assert!(pgn
+            .to_string()
+            .contains("40... Ke8 (40... Kc8 {also losing}) 41. Rb8# 1-0"));
+
+        let pgn = round_trip(GAMES[3]);
+        assert!(pgn.moves[0].m.is_castle());
+        assert!(pgn.moves[2].m.is_promotion());
+        assert_eq!(pgn.result, PgnResult::Draw);
+    }
+
+    #[test]
+    /// Test that a game made from a `Game` records its starting position when necessary.
+    fn from_game() {
+        let pgn = PgnGame::from_game(&Game::new(), PgnResult::Unfinished);
+        assert_eq!(pgn.tags.len(), 7);
+        assert_eq!(pgn.tag("Result"), Some("*"));
+
+        let fen = "3k4/R7/1R6/5K2/8/8/8/8 w - - 0 1";
+        let mut g = Game::from_fen(fen).unwrap();
+        g.make_move(Move::normal(Square::B6, Square::B8));
+        let mut pgn = PgnGame::from_game(&g, PgnResult::WhiteWins);
+        pgn.set_tag("White", "Tomato");
+        assert_eq!(pgn.tag("White"), Some("Tomato"));
+        assert_eq!(pgn.tag("FEN"), Some(fen));
+        let written = pgn.to_string();
+        assert!(written.ends_with("\n\n1. Rb8# 1-0\n"));
+        assert_eq!(round_trip(&written), pgn);
+    }
+
+    #[test]
+    /// Test that long games are wrapped to fit in 80 columns.
+    fn line_wrapping() {
+        let mut g = Game::new();
+        for _ in 0..20 {
+            for (from, to) in [
+                (Square::G1, Square::F3),
+                (Square::G8, Square::F6),
+                (Square::F3, Square::G1),
+                (Square::F6, Square::G8),
+            ] {
+                g.make_move(Move::normal(from, to));
+            }
+        }
+        let written = PgnGame::from_game(&g, PgnResult::Draw).to_string();
+        assert!(written.lines().all(|l| l.len() <= LINE_WIDTH));
+        assert!(10 < written.lines().count());
+    }
+
+    #[test]
+    /// Test that a malformed game is reported with its line number, and that the games after it
+    /// can still be read.
+    fn errors_and_recovery() {
+        let pgn = "[Event \"a\"]\n\n1. e4 e4 1-0\n\n[Event \"b\"]\n\n1. d4 d5 *\n\
+                   [Event \"c\"\n1. c4 (1. d4 *\n\n1. e4 1-0\n";
+        let games: Vec<_> = PgnReader::new(pgn.as_bytes()).collect();
+        assert_eq!(games.len(), 4);
+        assert!(games[0].as_ref().unwrap_err().starts_with("line 3: "));
+        assert_eq!(games[1].as_ref().unwrap().tag("Event"), Some("b"));
+        assert!(games[2].is_err());
+        assert_eq!(games[3].as_ref().unwrap().moves.len(), 1);
+
+        for bad in [
+            "1. e4 )",
+            "(1. e4) *",
+            "1. e4 (1. d4 1-0) *",
+            "1. e4 () *",
+            "1. e4 {unterminated",
+            "$1 1. e4 *",
+            "1. e4 ?!? *",
+            "1. e4 & *",
+            "[FEN \"8/8/8 w - - 0 1\"]\n1. e4 *",
+        ] {
+            assert!(bad.parse::<PgnGame>().is_err(), "{bad}");
+        }
+        assert!("".parse::<PgnGame>().is_err());
+    }
+
+    #[test]
+    /// Test that movetext without a result token ends at the start of the next game.
+    fn missing_result() {
+        let pgn = "[Result \"0-1\"]\n1. e4 e5\n[Event \"x\"]\n1. d4\n";
+        let games: Vec<_> = PgnReader::new(pgn.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].moves.len(), 2);
+        assert_eq!(games[0].result, PgnResult::BlackWins);
+        assert_eq!(games[1].result, PgnResult::Unfinished);
+    }
+
+    /// An endless input, which repeats the same text forever.
+    struct Endless {
+        /// The text to repeat.
+        text: &'static [u8],
+        /// The position in `text` of the next byte to be read.
+        pos: usize,
+    }
+
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.text.len() - self.pos);
+            buf[..n].copy_from_slice(&self.text[self.pos..self.pos + n]);
+            self.pos = (self.pos + n) % self.text.len();
+            Ok(n)
+        }
+    }
+
+    #[test]
+    /// Test that games are read one at a time, without waiting for the end of the input.
+    fn streaming() {
+        let input = Endless {
+            text: b"[Event \"?\"]\n\n1. e4 e5 2. Nf3 Nc6 *\n\n",
+            pos: 0,
+        };
+        let games = PgnReader::new(BufReader::new(input)).take(1000);
+        let mut n_games = 0;
+        for game in games {
+            assert_eq!(game.unwrap().moves.len(), 4);
+            n_games += 1;
+        }
+        assert_eq!(n_games, 1000);
+    }
+}
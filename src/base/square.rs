@@ -111,6 +111,9 @@ impl Square {
     ///
     /// The ranks run from 0 to 7 (instead of 1 through 8), and the files run from A to H.
     pub fn new(rank: u8, file: u8) -> Option<Square> {
+        if 8 <= rank || 8 <= file {
+            return None;
+        }
         Square::try_from((rank << 3) | file).ok()
     }
 
@@ -199,7 +202,6 @@ impl Square {
         unsafe { transmute(self as u8 ^ 56) }
     }
 
-    #[allow(clippy::missing_panics_doc)]
     /// Convert an algebraic string (such as 'e7') to a square.
     ///
     /// To get an `Ok` result, the string must be two characters.
@@ -209,26 +211,24 @@ impl Square {
     ///
     /// This function will return an `Err` if `s` is not a legal algebraic square.
     pub fn from_algebraic(s: &str) -> Result<Square, &'static str> {
-        if s.len() != 2 {
-            return Err("square name must be 2 characters");
-        }
         let mut chars = s.chars();
+        let (Some(file_chr), Some(rank_chr), None) = (chars.next(), chars.next(), chars.next())
+        else {
+            return Err("square name must be 2 characters");
+        };
         let file: u8 = "abcdefgh"
-            .match_indices(chars.next().unwrap())
+            .match_indices(file_chr)
             .next()
             .ok_or("illegal file for square")?
             .0
             .try_into()
             .map_err(|_| "bad file name")?;
-        let rank: u8 = chars
-            .next()
-            .unwrap()
+        let rank: u8 = rank_chr
             .to_digit(10)
             .ok_or("expected number for square rank")?
             .try_into()
             .map_err(|_| "bad rank name")?;
-        // will not fail because we have already validated the rank and file
-        Ok(Square::new(rank - 1, file).unwrap())
+        Square::new(rank.wrapping_sub(1), file).ok_or("illegal rank for square")
     }
 
     #[must_use]
@@ -360,4 +360,12 @@ mod tests {
         assert_eq!(Square::from_algebraic("e4"), Ok(Square::E4));
         assert_eq!(Square::from_algebraic("f7"), Ok(Square::F7));
     }
+
+    #[test]
+    /// Test that malformed square names are rejected instead of causing a panic.
+    fn square_from_bad_algebraic() {
+        for name in ["", "e", "e0", "e9", "i4", "E4", "é", "e4e", "4e"] {
+            assert!(Square::from_algebraic(name).is_err(), "{name}");
+        }
+    }
 }
@@ -0,0 +1,349 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! A generator of training data for the tuner, made by playing the engine against itself.
+//!
+//! Each game starts with a few uniformly random moves, so that the data covers a variety of
+//! positions, and is then played out with a fixed number of nodes per move, as described in
+//! `tomato::engine::selfplay`.
+//! Quiet positions from each game are labeled with the final result of the game and written in the
+//! format read by the tuner:
+//!
+//! ```text
+//! rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 "1/2-1/2";
+//! ```
+//!
+//! With `--eval`, the search's evaluation of each position is appended as an EPD `ce` (centipawn
+//! evaluation) operation, from the perspective of the player to move.
+//!
+//! Every game is seeded from `--seed` and its own index, and games are written in order, so the
+//! output depends only on the arguments and not on the number of threads.
+//! A game in which a search fails is reported and left out of the output.
+//!
+//! Usage:
+//!
+//! ```text
+//! tomato-datagen [--games N] [--nodes N] [--threads N] [--seed N] [--random-plies N]
+//!                [--hash MB] [--output FILE] [--eval]
+//! ```
+
+#![warn(clippy::pedantic)]
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::File,
+    io::{stdout, BufWriter, Write},
+    process::exit,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Sender},
+    },
+    thread::scope,
+    time::Instant,
+};
+
+use fastrand::Rng;
+use tomato::{
+    base::game::{Game, GameResult},
+    engine::{
+        selfplay::{play_out, random_opening, search, searcher},
+        thread::MainSearch,
+        SearchError,
+    },
+};
+
+/// Openings which a short search evaluates as further than this from equal are thrown out, since
+/// games from them would be decided before they begin.
+const MAX_OPENING_CP: i16 = 300;
+
+/// The usage message printed when the command line arguments are malformed.
+const USAGE: &str = "usage: tomato-datagen [--games N] [--nodes N] [--threads N] [--seed N] \
+[--random-plies N] [--hash MB] [--output FILE] [--eval]";
+
+/// Generate training data, as configured by the command line arguments.
+fn main() {
+    let settings = match Settings::parse(env::args().skip(1)) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!("{USAGE}");
+            exit(1);
+        }
+    };
+    let output: Box<dyn Write + Send> = match &settings.output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("could not create {path}: {e}");
+                exit(1);
+            }
+        },
+        None => Box::new(stdout()),
+    };
+    if let Err(e) = generate(&settings, BufWriter::new(output)) {
+        eprintln!("could not write data: {e}");
+        exit(1);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The configuration of a data generation run.
+struct Settings {
+    /// The number of games to play.
+    games: usize,
+    /// The number of nodes to search for each move.
+    nodes: u64,
+    /// The number of games to play at the same time.
+    threads: usize,
+    /// The seed from which every game's random opening is derived.
+    seed: u64,
+    /// The number of random plies played at the start of each game.
+    random_plies: usize,
+    /// The size of each thread's transposition table, in megabytes.
+    hash_mb: usize,
+    /// The file to write positions to.
+    /// If `None`, positions are written to standard output.
+    output: Option<String>,
+    /// Whether to write the evaluation of each position.
+    write_eval: bool,
+}
+
+impl Settings {
+    /// Parse the settings of a run from its command line arguments.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the arguments are malformed.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Settings, String> {
+        let mut settings = Settings {
+            games: 1000,
+            nodes: 5000,
+            threads: 1,
+            seed: 0,
+            random_plies: 8,
+            hash_mb: 16,
+            output: None,
+            write_eval: false,
+        };
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {flag}"))
+            };
+            match flag.as_str() {
+                "--games" => settings.games = parse_num(&value()?)?,
+                "--nodes" => settings.nodes = parse_num(&value()?)?,
+                "--threads" => settings.threads = parse_num(&value()?)?,
+                "--seed" => settings.seed = parse_num(&value()?)?,
+                "--random-plies" => settings.random_plies = parse_num(&value()?)?,
+                "--hash" => settings.hash_mb = parse_num(&value()?)?,
+                "--output" => settings.output = Some(value()?),
+                "--eval" => settings.write_eval = true,
+                _ => return Err(format!("unknown argument `{flag}`")),
+            }
+        }
+        if settings.threads == 0 {
+            return Err("at least one thread is required".to_string());
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Parse a numeric command line argument.
+///
+/// # Errors
+///
+/// This function will return an `Err` if `s` is not a valid number.
+fn parse_num<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("invalid number `{s}`"))
+}
+
+#[allow(clippy::cast_precision_loss)]
+/// Play all the games of a run across several threads, writing their positions to `output` in the
+/// order of the games.
+///
+/// # Errors
+///
+/// This function will return an `Err` if writing to `output` fails.
+fn generate(settings: &Settings, mut output: impl Write) -> std::io::Result<()> {
+    let next_game = AtomicUsize::new(0);
+    let (tx, rx) = channel();
+    let tic = Instant::now();
+    scope(|s| {
+        for _ in 0..settings.threads {
+            let tx = tx.clone();
+            let next_game = &next_game;
+            s.spawn(move || worker(settings, next_game, &tx));
+        }
+        drop(tx);
+
+        // games finish out of order, so hold on to them until all the earlier ones are written
+        let mut finished = BTreeMap::new();
+        let mut num_written = 0;
+        let mut num_positions = 0;
+        for (index, lines) in rx {
+            finished.insert(index, lines);
+            while let Some(lines) = finished.remove(&num_written) {
+                for line in &lines {
+                    writeln!(output, "{line}")?;
+                }
+                num_written += 1;
+                num_positions += lines.len();
+                if num_written % 100 == 0 || num_written == settings.games {
+                    eprintln!(
+                        "{num_written} games, {num_positions} positions ({:.0} positions/s)",
+                        num_positions as f64 / tic.elapsed().as_secs_f64()
+                    );
+                }
+            }
+        }
+        output.flush()
+    })
+}
+
+/// Play games until every game of the run has been taken by some worker, sending the positions
+/// from each game to `results` along with its index.
+fn worker(settings: &Settings, next_game: &AtomicUsize, results: &Sender<(usize, Vec<String>)>) {
+    let mut searcher = searcher(settings.nodes, settings.hash_mb);
+    loop {
+        let index = next_game.fetch_add(1, Ordering::Relaxed);
+        if settings.games <= index {
+            return;
+        }
+        let lines = play_game(&mut searcher, settings, index);
+        if results.send((index, lines)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Choose a random opening for a game.
+/// Openings which are too unbalanced, or which cannot be searched, are thrown out and replaced.
+fn balanced_opening(searcher: &mut MainSearch, settings: &Settings, rng: &Rng) -> Game {
+    loop {
+        let game = random_opening(rng, settings.random_plies);
+        if let Ok((_, eval)) = search(searcher, &game) {
+            if !eval.is_mate() && eval.centipawn_val().abs() <= MAX_OPENING_CP {
+                return game;
+            }
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+/// Play a single game of self-play and return the training lines for its quiet positions.
+/// If a search fails, the game is reported and no lines are returned.
+fn play_game(searcher: &mut MainSearch, settings: &Settings, index: usize) -> Vec<String> {
+    searcher.ttable.clear();
+    // spread out the seeds of neighboring games
+    let rng = Rng::with_seed(settings.seed ^ (index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    let mut game = balanced_opening(searcher, settings, &rng);
+
+    // the quiet positions seen so far, as FENs along with their evaluations from the perspective
+    // of the player to move
+    let mut positions = Vec::new();
+    let outcome = play_out(&mut game, |game| {
+        let (m, eval) = search(searcher, game)?;
+        let b = game.board();
+        // mate scores say nothing about the value of a position, so they are not recorded
+        let is_quiet = b.checkers.is_empty() && !b.is_move_capture(m) && !m.is_promotion();
+        if is_quiet && !eval.is_mate() {
+            positions.push((b.to_fen(), eval.in_perspective(b.player).centipawn_val()));
+        }
+        Ok::<_, SearchError>((m, eval))
+    });
+    if let Err(e) = outcome {
+        eprintln!("search failed in game {index} ({e:?}); leaving it out");
+        return Vec::new();
+    }
+
+    let result = match game.result() {
+        Some(GameResult::WhiteWins) => "1-0",
+        Some(GameResult::BlackWins) => "0-1",
+        Some(GameResult::Draw) | None => "1/2-1/2",
+    };
+    positions
+        .into_iter()
+        .map(|(fen, eval)| {
+            if settings.write_eval {
+                format!("{fen} \"{result}\"; ce {eval};")
+            } else {
+                format!("{fen} \"{result}\";")
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tomato::base::Board;
+
+    /// Get the settings used for fast tests.
+    fn test_settings() -> Settings {
+        Settings::parse(
+            "--games 3 --nodes 200 --threads 2 --seed 7 --hash 1 --eval"
+                .split_whitespace()
+                .map(str::to_string),
+        )
+        .unwrap()
+    }
+
+    /// Run a generation with the given settings, returning the output.
+    fn run(settings: &Settings) -> String {
+        let mut output = Vec::new();
+        generate(settings, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    /// Test that the same seed always produces the same data, no matter how many threads are
+    /// used.
+    fn deterministic() {
+        let settings = test_settings();
+        let first = run(&settings);
+        assert!(!first.is_empty());
+        assert_eq!(first, run(&settings));
+        let single = Settings {
+            threads: 1,
+            ..settings.clone()
+        };
+        assert_eq!(first, run(&single));
+        let reseeded = Settings {
+            seed: 8,
+            ..settings
+        };
+        assert_ne!(first, run(&reseeded));
+    }
+
+    #[test]
+    /// Test that every line is in the format read by the tuner, and that no position is in check.
+    fn format() {
+        for line in run(&test_settings()).lines() {
+            let mut split_line = line.split('"');
+            let b = Board::from_fen(split_line.next().unwrap()).unwrap();
+            assert!(b.checkers.is_empty());
+            assert!(["1-0", "0-1", "1/2-1/2"].contains(&split_line.next().unwrap()));
+            let rest = split_line.next().unwrap();
+            assert!(rest.starts_with("; ce ") && rest.ends_with(';'), "{line}");
+        }
+    }
+}
@@ -0,0 +1,1095 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! A match runner which plays two UCI engines against each other.
+//!
+//! Each engine is run as a child process and spoken to over UCI, while the match runner keeps the
+//! clocks, checks the legality of every move, and decides when games are over.
+//! Each opening is played twice, once with each engine as White.
+//! When the match is over, the final score is printed along with an Elo estimate and the
+//! likelihood of superiority (LOS) of the first engine, in the same format as `results.txt`.
+//! With `--sprt`, the match instead stops as soon as a sequential probability ratio test over the
+//! pairs of games reaches a decision.
+//!
+//! Usage:
+//!
+//! ```text
+//! tomato-match --engine cmd=PATH [name=NAME] [option.NAME=VALUE ...]
+//!              --engine cmd=PATH [name=NAME] [option.NAME=VALUE ...]
+//!              [--tc BASE+INC] [--games N] [--concurrency N] [--openings FILE]
+//!              [--pgnout FILE] [--timemargin MS] [--resign MOVES SCORE]
+//!              [--draw MOVENUMBER MOVES SCORE] [--maxmoves N] [--event NAME]
+//!              [--sprt ELO0 ELO1 ALPHA BETA]
+//! ```
+//!
+//! Times are given in seconds and scores in centipawns.
+//! The opening file contains one position per line, as either a FEN or an EPD record.
+
+#![warn(clippy::pedantic)]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    fs::{File, OpenOptions},
+    io::{stdout, BufRead, BufReader, Write},
+    process::{exit, Child, ChildStdin, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    },
+    thread::{scope, spawn},
+    time::{Duration, Instant},
+};
+
+use tomato::{
+    base::{
+        game::{Game, GameResult, Termination},
+        pgn::PgnGame,
+        Color, Move,
+    },
+    stats::{Pentanomial, Sprt, SprtStatus, Trinomial},
+};
+
+/// The score, in centipawns, reported for an engine which announces a forced mate.
+const MATE_SCORE: i32 = 30_000;
+
+/// The time that an engine is given to start up or acknowledge a new game.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The time that an engine is given to send its move after overstepping its clock and being told
+/// to stop.
+const STOP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The usage message printed when the command line arguments are malformed.
+const USAGE: &str = "usage: tomato-match --engine cmd=PATH [name=NAME] [option.NAME=VALUE ...] \
+--engine cmd=PATH ... [--tc BASE+INC] [--games N] [--concurrency N] [--openings FILE] \
+[--pgnout FILE] [--timemargin MS] [--resign MOVES SCORE] [--draw MOVENUMBER MOVES SCORE] \
+[--maxmoves N] [--event NAME] [--sprt ELO0 ELO1 ALPHA BETA]";
+
+/// Run a match between two engines, as configured by the command line arguments.
+fn main() {
+    let settings = match Settings::parse(env::args().skip(1)) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!("{USAGE}");
+            exit(1);
+        }
+    };
+    if let Err(e) = run_match(&settings) {
+        eprintln!("match failed: {e}");
+        exit(1);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The description of how to start and configure one engine.
+struct EngineSpec {
+    /// The command used to start the engine.
+    cmd: String,
+    /// The name of the engine in the output.
+    /// If `None`, the name reported by the engine is used instead.
+    name: Option<String>,
+    /// The UCI options to set on the engine before the match starts, as name-value pairs.
+    options: Vec<(String, String)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// An incremental time control.
+struct TimeControl {
+    /// The time on each player's clock at the start of the game.
+    base: Duration,
+    /// The time added to a player's clock after each of their moves.
+    increment: Duration,
+}
+
+impl TimeControl {
+    /// Parse a time control of the form `BASE+INC` or `BASE`, where both are given in seconds.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if `s` is not a valid time control.
+    fn parse(s: &str) -> Result<TimeControl, String> {
+        let (base, increment) = s.split_once('+').unwrap_or((s, "0"));
+        let seconds = |x: &str| {
+            x.parse::<f64>()
+                .ok()
+                .filter(|t| t.is_finite() && 0.0 <= *t)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| format!("invalid time control `{s}`"))
+        };
+        Ok(TimeControl {
+            base: seconds(base)?,
+            increment: seconds(increment)?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// The rules for ending games early based on the scores reported by the engines.
+struct Adjudication {
+    /// If set, a player loses when their score has been at or below the negation of the given
+    /// score (in centipawns) for the given number of consecutive moves.
+    resign: Option<(u32, i32)>,
+    /// If set, `(movenumber, moves, score)`: after full move `movenumber`, the game is drawn when
+    /// both players have reported a score within `score` centipawns of zero for `moves`
+    /// consecutive moves each.
+    draw: Option<(u32, u32, i32)>,
+    /// If set, the game is drawn after this many full moves.
+    max_moves: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// The full configuration of a match.
+struct Settings {
+    /// The two engines playing in the match.
+    /// Scores are reported from the perspective of the first.
+    engines: [EngineSpec; 2],
+    /// The time control for every game.
+    tc: TimeControl,
+    /// The extra time that an engine may overstep its clock by before it loses on time.
+    time_margin: Duration,
+    /// The total number of games to play.
+    games: usize,
+    /// The number of games to play at the same time.
+    concurrency: usize,
+    /// The FENs of the starting positions.
+    /// If empty, every game starts from the standard starting position.
+    openings: Vec<String>,
+    /// The file to which games are appended in PGN.
+    /// If `None`, games are written to standard output.
+    pgn_out: Option<String>,
+    /// The rules for adjudicating games.
+    adjudication: Adjudication,
+    /// The name of the event in the PGN output.
+    event: String,
+    /// If set, the match stops early once this test reaches a decision.
+    sprt: Option<Sprt>,
+}
+
+impl Settings {
+    /// Parse the settings of a match from its command line arguments.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the arguments are malformed, or if the openings file
+    /// cannot be read.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Settings, String> {
+        let mut args = args.into_iter().peekable();
+        let mut engines = Vec::new();
+        let mut tc = TimeControl {
+            base: Duration::from_secs(10),
+            increment: Duration::from_millis(100),
+        };
+        let mut time_margin = Duration::from_millis(50);
+        let mut games = 100;
+        let mut concurrency = 1;
+        let mut openings = Vec::new();
+        let mut pgn_out = None;
+        let mut adjudication = Adjudication::default();
+        let mut event = "tomato-match".to_string();
+        let mut sprt = None;
+
+        while let Some(flag) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {name}"))
+            };
+            match flag.as_str() {
+                "--engine" => {
+                    let mut spec = EngineSpec {
+                        cmd: String::new(),
+                        name: None,
+                        options: Vec::new(),
+                    };
+                    while let Some(arg) = args.next_if(|arg| !arg.starts_with("--")) {
+                        let (key, val) = arg
+                            .split_once('=')
+                            .ok_or_else(|| format!("malformed engine argument `{arg}`"))?;
+                        match key {
+                            "cmd" => spec.cmd = val.to_string(),
+                            "name" => spec.name = Some(val.to_string()),
+                            _ => match key.strip_prefix("option.") {
+                                Some(opt) => spec.options.push((opt.to_string(), val.to_string())),
+                                None => return Err(format!("unknown engine argument `{key}`")),
+                            },
+                        }
+                    }
+                    if spec.cmd.is_empty() {
+                        return Err("engine given without a command".to_string());
+                    }
+                    engines.push(spec);
+                }
+                "--tc" => tc = TimeControl::parse(&value("--tc")?)?,
+                "--timemargin" => {
+                    time_margin = Duration::from_millis(parse_num(&value("--timemargin")?)?);
+                }
+                "--games" => games = parse_num(&value("--games")?)?,
+                "--concurrency" => concurrency = parse_num(&value("--concurrency")?)?,
+                "--openings" => openings = load_openings(&value("--openings")?)?,
+                "--pgnout" => pgn_out = Some(value("--pgnout")?),
+                "--resign" => {
+                    let moves = parse_num(&value("--resign")?)?;
+                    adjudication.resign = Some((moves, parse_num(&value("--resign")?)?));
+                }
+                "--draw" => {
+                    let move_number = parse_num(&value("--draw")?)?;
+                    let moves = parse_num(&value("--draw")?)?;
+                    adjudication.draw = Some((move_number, moves, parse_num(&value("--draw")?)?));
+                }
+                "--maxmoves" => adjudication.max_moves = Some(parse_num(&value("--maxmoves")?)?),
+                "--event" => event = value("--event")?,
+                "--sprt" => {
+                    let mut params = [0.0; 4];
+                    for param in &mut params {
+                        *param = parse_num(&value("--sprt")?)?;
+                    }
+                    let [elo0, elo1, alpha, beta] = params;
+                    sprt = Some(Sprt::new(elo0, elo1, alpha, beta));
+                }
+                _ => return Err(format!("unknown argument `{flag}`")),
+            }
+        }
+
+        let engines: [EngineSpec; 2] = engines
+            .try_into()
+            .map_err(|_| "exactly two engines must be given".to_string())?;
+        if concurrency == 0 {
+            return Err("concurrency must be at least 1".to_string());
+        }
+
+        Ok(Settings {
+            engines,
+            tc,
+            time_margin,
+            games,
+            concurrency,
+            openings,
+            pgn_out,
+            adjudication,
+            event,
+            sprt,
+        })
+    }
+}
+
+/// Parse a numeric command line argument.
+///
+/// # Errors
+///
+/// This function will return an `Err` if `s` is not a valid number.
+fn parse_num<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("invalid number `{s}`"))
+}
+
+/// Load the opening positions from a file containing one FEN or EPD record per line.
+/// Blank lines are ignored.
+///
+/// # Errors
+///
+/// This function will return an `Err` if the file cannot be read or contains an invalid position.
+fn load_openings(path: &str) -> Result<Vec<String>, String> {
+    let file = File::open(path).map_err(|e| format!("could not open {path}: {e}"))?;
+    let mut openings = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("could not read {path}: {e}"))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fen = opening_fen(&line);
+        Game::from_fen(&fen).map_err(|e| format!("{path}:{}: {e}", i + 1))?;
+        openings.push(fen);
+    }
+    if openings.is_empty() {
+        return Err(format!("{path} contains no openings"));
+    }
+
+    Ok(openings)
+}
+
+/// Convert a FEN or EPD record into a FEN.
+/// An EPD record has no move counters, so its position is given a halfmove clock of 0 and a full
+/// move number of 1; any operations after the position are dropped.
+fn opening_fen(line: &str) -> String {
+    let fields: Vec<&str> = line.split_ascii_whitespace().collect();
+    let is_counter = |i: usize| fields.get(i).is_some() && fields[i].parse::<u32>().is_ok();
+    if is_counter(4) && is_counter(5) {
+        fields[..6].join(" ")
+    } else {
+        let mut fen = fields[..fields.len().min(4)].join(" ");
+        fen.push_str(" 0 1");
+        fen
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The reason a game ended.
+enum Ending {
+    /// The game ended by the rules of chess, or was adjudicated.
+    Rules(Termination),
+    /// The given player ran out of time.
+    TimeForfeit(Color),
+    /// The given player sent an illegal move.
+    IllegalMove(Color),
+    /// The given player stopped responding or exited.
+    Disconnect(Color),
+}
+
+impl Ending {
+    /// Get the final result of a game that ended this way.
+    fn result(self) -> GameResult {
+        match self {
+            Ending::Rules(termination) => termination.result(),
+            Ending::TimeForfeit(loser) | Ending::IllegalMove(loser) | Ending::Disconnect(loser) => {
+                GameResult::win_for(!loser)
+            }
+        }
+    }
+
+    /// Get the value of the PGN `Termination` tag for a game which ended this way.
+    fn pgn_termination(self) -> &'static str {
+        match self {
+            Ending::Rules(Termination::Adjudication(_) | Termination::Resignation { .. }) => {
+                "adjudication"
+            }
+            Ending::Rules(_) => "normal",
+            Ending::TimeForfeit(_) => "time forfeit",
+            Ending::IllegalMove(_) => "rules infraction",
+            Ending::Disconnect(_) => "abandoned",
+        }
+    }
+
+    /// Get a human-readable description of this ending, such as `White mates`.
+    fn describe(self) -> String {
+        let name = |color: Color| match color {
+            Color::White => "White",
+            Color::Black => "Black",
+        };
+        match self {
+            Ending::Rules(termination) => termination.to_string(),
+            Ending::TimeForfeit(loser) => format!("{} loses on time", name(loser)),
+            Ending::IllegalMove(loser) => format!("{} makes an illegal move", name(loser)),
+            Ending::Disconnect(loser) => format!("{} disconnects", name(loser)),
+        }
+    }
+}
+
+/// The ways in which waiting for a line from an engine can fail.
+enum WaitError {
+    /// The deadline passed before the line arrived.
+    Timeout,
+    /// The engine exited or closed its output.
+    Disconnected,
+}
+
+/// A UCI engine running as a child process.
+struct Engine {
+    /// The name of the engine.
+    name: String,
+    /// The child process running the engine.
+    child: Child,
+    /// The standard input of the engine, to which commands are written.
+    stdin: ChildStdin,
+    /// The lines written by the engine to its standard output, read by a helper thread.
+    lines: Receiver<String>,
+}
+
+impl Engine {
+    /// Start an engine and configure it according to its spec.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the engine cannot be started or does not complete
+    /// the UCI handshake.
+    fn start(spec: &EngineSpec) -> Result<Engine, String> {
+        let mut parts = spec.cmd.split_ascii_whitespace();
+        let program = parts.next().ok_or("empty engine command")?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("could not start `{}`: {e}", spec.cmd))?;
+        let stdin = child.stdin.take().ok_or("engine has no standard input")?;
+        let stdout = child.stdout.take().ok_or("engine has no standard output")?;
+        let (tx, lines) = channel();
+        spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Engine {
+            name: spec.cmd.clone(),
+            child,
+            stdin,
+            lines,
+        };
+        engine.send("uci")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let line = engine
+                .recv(deadline)
+                .map_err(|_| format!("`{}` did not complete the UCI handshake", spec.cmd))?;
+            if line.trim() == "uciok" {
+                break;
+            }
+            if let Some(name) = line.trim().strip_prefix("id name ") {
+                engine.name = name.to_string();
+            }
+        }
+        if let Some(name) = &spec.name {
+            engine.name.clone_from(name);
+        }
+        for (name, value) in &spec.options {
+            engine.send(&format!("setoption name {name} value {value}"))?;
+        }
+        engine.sync()?;
+
+        Ok(engine)
+    }
+
+    /// Send a single command to the engine.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the engine's input has been closed.
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|()| self.stdin.flush())
+            .map_err(|e| format!("could not write to {}: {e}", self.name))
+    }
+
+    /// Receive the next line from the engine, waiting no later than `deadline`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the deadline passes or the engine has exited.
+    fn recv(&self, deadline: Instant) -> Result<String, WaitError> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        self.lines.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => WaitError::Timeout,
+            RecvTimeoutError::Disconnected => WaitError::Disconnected,
+        })
+    }
+
+    /// Wait until the engine is ready to receive more commands.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the engine does not respond in time.
+    fn sync(&mut self) -> Result<(), String> {
+        self.send("isready")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            match self.recv(deadline) {
+                Ok(line) if line.trim() == "readyok" => return Ok(()),
+                Ok(_) => (),
+                Err(_) => return Err(format!("{} is not responding", self.name)),
+            }
+        }
+    }
+
+    /// Ask the engine for its move in `game`, with the given clocks.
+    /// Returns the move string sent by the engine, along with the last score it reported from its
+    /// own perspective.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the engine does not send a move before `deadline`,
+    /// or if the engine exits.
+    fn best_move(
+        &mut self,
+        position: &str,
+        go: &str,
+        deadline: Instant,
+    ) -> Result<(String, Option<i32>), WaitError> {
+        self.send(position)
+            .and_then(|()| self.send(go))
+            .map_err(|_| WaitError::Disconnected)?;
+        let mut score = None;
+        loop {
+            let line = match self.recv(deadline) {
+                Ok(line) => line,
+                Err(WaitError::Timeout) => {
+                    // give the engine a chance to stop so that it can be reused for the next game
+                    self.send("stop").map_err(|_| WaitError::Disconnected)?;
+                    let stop_deadline = Instant::now() + STOP_TIMEOUT;
+                    while let Ok(line) = self.recv(stop_deadline) {
+                        if line.starts_with("bestmove") {
+                            return Err(WaitError::Timeout);
+                        }
+                    }
+                    return Err(WaitError::Disconnected);
+                }
+                Err(WaitError::Disconnected) => return Err(WaitError::Disconnected),
+            };
+            let mut tokens = line.split_ascii_whitespace();
+            match tokens.next() {
+                Some("info") => score = parse_score(tokens).or(score),
+                Some("bestmove") => {
+                    return Ok((tokens.next().unwrap_or_default().to_string(), score));
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Extract the score, in centipawns, from the remaining tokens of an `info` line.
+/// Forced mates are given a score of +/- `MATE_SCORE`.
+fn parse_score<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<i32> {
+    tokens.find(|&tok| tok == "score")?;
+    let kind = tokens.next()?;
+    let value: i32 = tokens.next()?.parse().ok()?;
+    match kind {
+        "cp" => Some(value),
+        "mate" if 0 < value => Some(MATE_SCORE),
+        "mate" => Some(-MATE_SCORE),
+        _ => None,
+    }
+}
+
+/// A finished game.
+struct Played {
+    /// The index of the game in the match.
+    index: usize,
+    /// The game as it was played.
+    game: Game,
+    /// Whether the first engine played White.
+    first_is_white: bool,
+    /// How the game ended.
+    ending: Ending,
+}
+
+/// Play a single game between two engines.
+/// `opening` is the FEN of the starting position, or `None` for the standard starting position.
+fn play_game(
+    white: &mut Engine,
+    black: &mut Engine,
+    opening: Option<&str>,
+    settings: &Settings,
+) -> (Game, Ending) {
+    let mut game = match opening {
+        Some(fen) => Game::from_fen(fen).unwrap(),
+        None => Game::new(),
+    };
+    let position_prefix = match opening {
+        Some(fen) => format!("position fen {fen} moves"),
+        None => "position startpos moves".to_string(),
+    };
+    let tc = settings.tc;
+    let adjudication = settings.adjudication;
+    let mut clocks = [tc.base; 2];
+    // consecutive moves for which each player has been losing badly
+    let mut losing_streaks = [0; 2];
+    // consecutive plies for which both players have thought the position was drawn
+    let mut drawn_streak = 0;
+
+    for (engine, color) in [(&mut *white, Color::White), (&mut *black, Color::Black)] {
+        if engine
+            .send("ucinewgame")
+            .and_then(|()| engine.sync())
+            .is_err()
+        {
+            return (game, Ending::Disconnect(color));
+        }
+    }
+
+    let mut position = position_prefix;
+    loop {
+        if let Some(termination) = game.termination() {
+            return (game, Ending::Rules(termination));
+        }
+
+        let player = game.board().player;
+        let engine = match player {
+            Color::White => &mut *white,
+            Color::Black => &mut *black,
+        };
+        let go = format!(
+            "go wtime {} btime {} winc {} binc {}",
+            clocks[Color::White as usize].as_millis(),
+            clocks[Color::Black as usize].as_millis(),
+            tc.increment.as_millis(),
+            tc.increment.as_millis(),
+        );
+        let tic = Instant::now();
+        let clock = &mut clocks[player as usize];
+        let (move_str, score) =
+            match engine.best_move(&position, &go, tic + *clock + settings.time_margin) {
+                Ok(reply) => reply,
+                Err(WaitError::Timeout) => return (game, Ending::TimeForfeit(player)),
+                Err(WaitError::Disconnected) => return (game, Ending::Disconnect(player)),
+            };
+        let elapsed = tic.elapsed();
+        if *clock + settings.time_margin < elapsed {
+            return (game, Ending::TimeForfeit(player));
+        }
+        *clock = clock.saturating_sub(elapsed) + tc.increment;
+
+        let Ok(m) = Move::from_uci(&move_str, game.board()) else {
+            return (game, Ending::IllegalMove(player));
+        };
+        if game.try_move(m).is_err() {
+            return (game, Ending::IllegalMove(player));
+        }
+        position.push(' ');
+        position.push_str(&move_str);
+
+        // adjudicate based on the scores reported by the engines
+        if let Some((moves, resign_score)) = adjudication.resign {
+            let streak = &mut losing_streaks[player as usize];
+            *streak = if score.is_some() && score <= Some(-resign_score) {
+                *streak + 1
+            } else {
+                0
+            };
+            if moves <= *streak {
+                game.resign(player);
+            }
+        }
+        if let Some((move_number, moves, draw_score)) = adjudication.draw {
+            drawn_streak = match score {
+                Some(cp) if cp.abs() <= draw_score => drawn_streak + 1,
+                _ => 0,
+            };
+            if move_number <= full_moves(&game) && 2 * moves <= drawn_streak {
+                game.adjudicate(GameResult::Draw);
+            }
+        }
+        if let Some(max_moves) = adjudication.max_moves {
+            if max_moves <= full_moves(&game) && game.termination().is_none() {
+                game.adjudicate(GameResult::Draw);
+            }
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+/// Get the number of full moves which have been completed in `game`.
+fn full_moves(game: &Game) -> u32 {
+    (game.moves().len() / 2) as u32
+}
+
+/// Run a worker, which plays games on a pair of engines until the match is over or `stop` is set,
+/// sending each finished game to `results`.
+///
+/// # Errors
+///
+/// This function will return an `Err` if either engine cannot be started.
+fn worker(
+    settings: &Settings,
+    next_game: &AtomicUsize,
+    stop: &AtomicBool,
+    results: &Sender<Played>,
+) -> Result<(), String> {
+    let mut engines = [None, None];
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let index = next_game.fetch_add(1, Ordering::Relaxed);
+        if settings.games <= index {
+            return Ok(());
+        }
+        for (engine, spec) in engines.iter_mut().zip(&settings.engines) {
+            if engine.is_none() {
+                *engine = Some(Engine::start(spec)?);
+            }
+        }
+        let [Some(first), Some(second)] = &mut engines else {
+            unreachable!()
+        };
+        let opening = if settings.openings.is_empty() {
+            None
+        } else {
+            Some(settings.openings[(index / 2) % settings.openings.len()].as_str())
+        };
+        let first_is_white = index & 1 == 0;
+        let (game, ending) = if first_is_white {
+            play_game(first, second, opening, settings)
+        } else {
+            play_game(second, first, opening, settings)
+        };
+        // an engine which disconnected is restarted for the next game
+        if let Ending::Disconnect(loser) = ending {
+            engines[usize::from((loser == Color::White) != first_is_white)] = None;
+        }
+        if results
+            .send(Played {
+                index,
+                game,
+                first_is_white,
+                ending,
+            })
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// A count of wins, losses, and draws, displayed in the style of `results.txt`.
+struct Score(Trinomial);
+
+impl Score {
+    /// Add a game to this score, given whether it was a win, draw, or loss.
+    fn add(&mut self, won: Option<bool>) {
+        match won {
+            Some(true) => self.0.wins += 1,
+            Some(false) => self.0.losses += 1,
+            None => self.0.draws += 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} - {} - {}  [{:.3}] {}",
+            self.0.wins,
+            self.0.losses,
+            self.0.draws,
+            self.0.score(),
+            self.0.games()
+        )
+    }
+}
+
+/// The running totals of a match.
+struct Tally {
+    /// The score of the first engine.
+    total: Score,
+    /// The score of the first engine, split by the color it played.
+    by_color: [Score; 2],
+    /// The score of White against Black.
+    white_vs_black: Score,
+    /// For each engine, the number of times each kind of ending occurred.
+    endings: [BTreeMap<String, u32>; 2],
+    /// The score of the first engine over complete pairs of games.
+    pairs: Pentanomial,
+    /// The half-points scored by the first engine in games whose partner (the game from the same
+    /// opening with colors reversed) has not finished yet, keyed by the index of the pair.
+    unpaired: HashMap<usize, usize>,
+}
+
+impl Tally {
+    /// Add a finished game to this tally.
+    fn add(&mut self, played: &Played) {
+        let result = played.ending.result();
+        let white_won = result.winner().map(|winner| winner == Color::White);
+        let first_won = white_won.map(|won| won == played.first_is_white);
+        self.total.add(first_won);
+        let first_color = if played.first_is_white {
+            Color::White
+        } else {
+            Color::Black
+        };
+        self.by_color[first_color as usize].add(first_won);
+        self.white_vs_black.add(white_won);
+
+        let half_points = match first_won {
+            Some(true) => 2,
+            Some(false) => 0,
+            None => 1,
+        };
+        match self.unpaired.remove(&(played.index / 2)) {
+            Some(partner) => self.pairs.add(half_points + partner),
+            None => {
+                self.unpaired.insert(played.index / 2, half_points);
+            }
+        }
+
+        let description = played.ending.describe();
+        for (endings, won) in self
+            .endings
+            .iter_mut()
+            .zip([first_won, first_won.map(|w| !w)])
+        {
+            let prefix = match won {
+                Some(true) => "Win",
+                Some(false) => "Loss",
+                None => "Draw",
+            };
+            *endings
+                .entry(format!("{prefix}: {description}"))
+                .or_default() += 1;
+        }
+    }
+}
+
+/// Run a match, writing each game in PGN as it finishes and a summary at the end.
+///
+/// # Errors
+///
+/// This function will return an `Err` if an engine cannot be started or the PGN output cannot be
+/// written.
+fn run_match(settings: &Settings) -> Result<(), String> {
+    // start the engines once to learn their names and check that they work
+    let names: Vec<String> = settings
+        .engines
+        .iter()
+        .map(|spec| Engine::start(spec).map(|engine| engine.name.clone()))
+        .collect::<Result<_, _>>()?;
+    let mut pgn_out: Box<dyn Write> = match &settings.pgn_out {
+        Some(path) => Box::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("could not open {path}: {e}"))?,
+        ),
+        None => Box::new(stdout()),
+    };
+    let mut tally = Tally {
+        total: Score::default(),
+        by_color: [Score::default(); 2],
+        white_vs_black: Score::default(),
+        endings: [BTreeMap::new(), BTreeMap::new()],
+        pairs: Pentanomial::default(),
+        unpaired: HashMap::new(),
+    };
+
+    let next_game = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (tx, rx) = channel();
+    scope(|s| -> Result<(), String> {
+        let workers: Vec<_> = (0..settings.concurrency)
+            .map(|_| {
+                let tx = tx.clone();
+                let (next_game, stop) = (&next_game, &stop);
+                s.spawn(move || worker(settings, next_game, stop, &tx))
+            })
+            .collect();
+        drop(tx);
+
+        for played in rx {
+            tally.add(&played);
+            let (white, black) = if played.first_is_white {
+                (&names[0], &names[1])
+            } else {
+                (&names[1], &names[0])
+            };
+            let result = played.ending.result();
+            let description = played.ending.describe();
+            let mut pgn = PgnGame::from_game(&played.game, result.into());
+            pgn.set_tag("Event", &settings.event);
+            pgn.set_tag("Round", &(played.index + 1).to_string());
+            pgn.set_tag("White", white);
+            pgn.set_tag("Black", black);
+            pgn.set_tag("TimeControl", &tc_tag(settings.tc));
+            pgn.set_tag("Termination", played.ending.pgn_termination());
+            match pgn.moves.last_mut() {
+                Some(last) => last.comments_after.push(description.clone()),
+                None => pgn.comments.push(description.clone()),
+            }
+            writeln!(pgn_out, "{pgn}\n").map_err(|e| format!("could not write PGN: {e}"))?;
+            pgn_out
+                .flush()
+                .map_err(|e| format!("could not write PGN: {e}"))?;
+
+            eprintln!(
+                "Finished game {} ({white} vs {black}): {} {{{description}}}",
+                played.index + 1,
+                pgn.result
+            );
+            eprintln!("Score of {} vs {}: {}", names[0], names[1], tally.total);
+            if let Some(sprt) = settings.sprt {
+                eprintln!("{}", sprt_line(&sprt, &tally.pairs));
+                if sprt.status(&tally.pairs) != SprtStatus::Continue {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        for handle in workers {
+            handle
+                .join()
+                .map_err(|_| "a worker panicked".to_string())??;
+        }
+        Ok(())
+    })?;
+
+    print_summary(&tally, &names, settings);
+    Ok(())
+}
+
+/// Describe the current state of an SPRT, in the same style as cutechess-cli.
+fn sprt_line(sprt: &Sprt, pairs: &Pentanomial) -> String {
+    let llr = sprt.llr(pairs);
+    let verdict = match sprt.status(pairs) {
+        SprtStatus::Continue => "no decision yet",
+        SprtStatus::AcceptH0 => "H0 was accepted",
+        SprtStatus::AcceptH1 => "H1 was accepted",
+    };
+    format!(
+        "SPRT ({} vs {} Elo): llr {llr:.2} ({:.1}%), lbound {:.2}, ubound {:.2} - {verdict}",
+        sprt.elo0,
+        sprt.elo1,
+        100.0 * llr / sprt.upper_bound(),
+        sprt.lower_bound(),
+        sprt.upper_bound()
+    )
+}
+
+/// Get the value of the PGN `TimeControl` tag for a time control.
+fn tc_tag(tc: TimeControl) -> String {
+    if tc.increment.is_zero() {
+        tc.base.as_secs_f64().to_string()
+    } else {
+        format!("{}+{}", tc.base.as_secs_f64(), tc.increment.as_secs_f64())
+    }
+}
+
+/// Print the final summary of a match.
+fn print_summary(tally: &Tally, names: &[String], settings: &Settings) {
+    let first_as = |color: Color| tally.by_color[color as usize];
+    println!("Score of {} vs {}: {}", names[0], names[1], tally.total);
+    println!(
+        "...      {} playing White: {}",
+        names[0],
+        first_as(Color::White)
+    );
+    println!(
+        "...      {} playing Black: {}",
+        names[0],
+        first_as(Color::Black)
+    );
+    println!("...      White vs Black: {}", tally.white_vs_black);
+    let total = tally.total.0;
+    let draw_ratio = 100.0 * f64::from(total.draws) / f64::from(total.games().max(1));
+    println!(
+        "Elo difference: {}, LOS: {:.1} %, DrawRatio: {draw_ratio:.1} %",
+        total.elo(0.95),
+        100.0 * total.los()
+    );
+    if 0 < tally.pairs.pairs() {
+        let [ll, ld, dd, wd, ww] = tally.pairs.counts;
+        println!(
+            "Ptnml(0-2): {ll}, {ld}, {dd}, {wd}, {ww}; pair Elo difference: {}, LOS: {:.1} %",
+            tally.pairs.elo(0.95),
+            100.0 * tally.pairs.los()
+        );
+    }
+    if let Some(sprt) = settings.sprt {
+        println!("{}", sprt_line(&sprt, &tally.pairs));
+    }
+    println!("{} of {} games finished.", total.games(), settings.games);
+    println!();
+    for (name, endings) in names.iter().zip(&tally.endings) {
+        println!("Player: {name}");
+        for (ending, count) in endings {
+            println!("   \"{ending}\": {count}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that time controls are parsed in seconds, with an optional increment.
+    fn time_controls() {
+        assert_eq!(
+            TimeControl::parse("10+0.1"),
+            Ok(TimeControl {
+                base: Duration::from_secs(10),
+                increment: Duration::from_millis(100),
+            })
+        );
+        assert_eq!(
+            TimeControl::parse("30"),
+            Ok(TimeControl {
+                base: Duration::from_secs(30),
+                increment: Duration::ZERO,
+            })
+        );
+        assert!(TimeControl::parse("-1+0").is_err());
+        assert!(TimeControl::parse("fast").is_err());
+    }
+
+    #[test]
+    /// Test that EPD records are converted to FENs and that FENs are left alone.
+    fn openings() {
+        assert_eq!(
+            opening_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 bm e5; id \"1\";"),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        assert_eq!(
+            opening_fen("8/8/3k4/8/8/2K4R/8/8 w - - 12 40"),
+            "8/8/3k4/8/8/2K4R/8/8 w - - 12 40"
+        );
+    }
+
+    #[test]
+    /// Test that engine specs and match options are parsed from the command line.
+    fn command_line() {
+        let args = "--engine cmd=./new name=new option.Hash=16 --engine cmd=./old --tc 1+0 \
+            --games 4 --resign 3 600 --draw 40 8 10 --sprt 0 5 0.05 0.1";
+        let settings = Settings::parse(args.split_whitespace().map(str::to_string)).unwrap();
+        assert_eq!(
+            settings.engines[0],
+            EngineSpec {
+                cmd: "./new".to_string(),
+                name: Some("new".to_string()),
+                options: vec![("Hash".to_string(), "16".to_string())],
+            }
+        );
+        assert_eq!(settings.engines[1].cmd, "./old");
+        assert_eq!(settings.tc.base, Duration::from_secs(1));
+        assert_eq!(settings.games, 4);
+        assert_eq!(settings.adjudication.resign, Some((3, 600)));
+        assert_eq!(settings.adjudication.draw, Some((40, 8, 10)));
+        assert_eq!(settings.sprt, Some(Sprt::new(0.0, 5.0, 0.05, 0.1)));
+
+        assert!(Settings::parse(["--engine".to_string(), "cmd=./a".to_string()]).is_err());
+    }
+
+    #[test]
+    /// Test that scores are extracted from UCI info lines.
+    fn scores() {
+        let score = |line: &str| parse_score(line.split_whitespace());
+        assert_eq!(score("depth 5 score cp -31 nodes 100 pv e2e4"), Some(-31));
+        assert_eq!(score("depth 9 score mate 3 pv a7a8q"), Some(MATE_SCORE));
+        assert_eq!(score("depth 9 score mate -2"), Some(-MATE_SCORE));
+        assert_eq!(score("string hello"), None);
+    }
+}
@@ -0,0 +1,424 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! A tuner for the search parameters, using simultaneous perturbation stochastic approximation
+//! (SPSA).
+//!
+//! Every iteration, each tuned parameter is nudged up or down at random, giving two perturbed
+//! configurations of the search.
+//! The two configurations then play pairs of short self-play games against each other, one game
+//! with each color from the same random opening, and every parameter is moved toward the
+//! configuration which scored better.
+//! The games are played and adjudicated as described in `tomato::engine::selfplay`, and the
+//! perturbations are applied through the parameters' hidden UCI options, exactly as `setoption`
+//! would apply them.
+//! The step sizes follow the usual SPSA schedules, shrinking as the run goes on.
+//!
+//! Each parameter's perturbation size at the end of the run is a twentieth of its range, and
+//! `--r-end` sets the final learning rate, as a fraction of the squared perturbation size.
+//!
+//! After every iteration, the score of that iteration and the current value of every parameter
+//! are appended as a row to the CSV file given by `--csv`.
+//! When the run is over, the tuned values are printed as `setoption` commands for the parameters'
+//! hidden UCI options.
+//! If a search fails during a game, the run stops with an error rather than scoring the game.
+//!
+//! Usage:
+//!
+//! ```text
+//! tomato-spsa [--param NAME ...] [--iterations N] [--pairs N] [--nodes N] [--threads N]
+//!             [--seed N] [--random-plies N] [--hash MB] [--r-end F] [--csv FILE]
+//! ```
+
+#![warn(clippy::pedantic)]
+
+use std::{
+    env,
+    fs::File,
+    io::{BufWriter, Write},
+    process::exit,
+    thread::{available_parallelism, scope},
+};
+
+use fastrand::Rng;
+use tomato::{
+    base::{
+        game::{Game, GameResult},
+        Color,
+    },
+    engine::{
+        params::SearchParam,
+        selfplay::{play_out, random_opening, search, searcher},
+        thread::MainSearch,
+        SearchError,
+    },
+};
+
+/// The exponent of the decay of the learning rate over the course of a run.
+const ALPHA: f64 = 0.602;
+
+/// The exponent of the decay of the perturbation size over the course of a run.
+const GAMMA: f64 = 0.101;
+
+/// The usage message printed when the command line arguments are malformed.
+const USAGE: &str = "usage: tomato-spsa [--param NAME ...] [--iterations N] [--pairs N] \
+[--nodes N] [--threads N] [--seed N] [--random-plies N] [--hash MB] [--r-end F] [--csv FILE]";
+
+/// Run SPSA, as configured by the command line arguments.
+fn main() {
+    let settings = match Settings::parse(env::args().skip(1)) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!("{USAGE}");
+            exit(1);
+        }
+    };
+    let csv = match File::create(&settings.csv) {
+        Ok(file) => BufWriter::new(file),
+        Err(e) => {
+            eprintln!("could not create {}: {e}", settings.csv);
+            exit(1);
+        }
+    };
+    let theta = match tune(&settings, csv) {
+        Ok(theta) => theta,
+        Err(e) => {
+            eprintln!("{e}");
+            exit(1);
+        }
+    };
+
+    for (param, value) in settings.params.iter().zip(theta) {
+        println!("setoption name {} value {}", param.name(), value.round());
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// The configuration of a tuning run.
+struct Settings {
+    /// The parameters to tune.
+    params: Vec<SearchParam>,
+    /// The number of iterations to run for.
+    iterations: usize,
+    /// The number of pairs of games played in each iteration.
+    pairs: usize,
+    /// The number of nodes to search for each move.
+    nodes: u64,
+    /// The number of games to play at the same time.
+    threads: usize,
+    /// The seed from which every perturbation and opening is derived.
+    seed: u64,
+    /// The number of random plies played at the start of each opening.
+    random_plies: usize,
+    /// The size of each searcher's transposition table, in megabytes.
+    hash_mb: usize,
+    /// The learning rate at the end of the run, as a fraction of the squared perturbation size.
+    r_end: f64,
+    /// The file to log the progress of the run to.
+    csv: String,
+}
+
+impl Settings {
+    /// Parse the settings of a run from its command line arguments.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the arguments are malformed.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Settings, String> {
+        let mut settings = Settings {
+            params: Vec::new(),
+            iterations: 1000,
+            pairs: 8,
+            nodes: 2000,
+            threads: available_parallelism().map_or(1, usize::from),
+            seed: 0,
+            random_plies: 8,
+            hash_mb: 8,
+            r_end: 0.002,
+            csv: "spsa.csv".to_string(),
+        };
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {flag}"))
+            };
+            match flag.as_str() {
+                "--param" => {
+                    let name = value()?;
+                    let param = SearchParam::from_name(&name)
+                        .ok_or_else(|| format!("unknown search parameter `{name}`"))?;
+                    if !settings.params.contains(&param) {
+                        settings.params.push(param);
+                    }
+                }
+                "--iterations" => settings.iterations = parse_num(&value()?)?,
+                "--pairs" => settings.pairs = parse_num(&value()?)?,
+                "--nodes" => settings.nodes = parse_num(&value()?)?,
+                "--threads" => settings.threads = parse_num(&value()?)?,
+                "--seed" => settings.seed = parse_num(&value()?)?,
+                "--random-plies" => settings.random_plies = parse_num(&value()?)?,
+                "--hash" => settings.hash_mb = parse_num(&value()?)?,
+                "--r-end" => settings.r_end = parse_num(&value()?)?,
+                "--csv" => settings.csv = value()?,
+                _ => return Err(format!("unknown argument `{flag}`")),
+            }
+        }
+        if settings.params.is_empty() {
+            settings.params = SearchParam::ALL.to_vec();
+        }
+        if settings.threads == 0 {
+            return Err("at least one thread is required".to_string());
+        }
+        if settings.pairs == 0 {
+            return Err("at least one pair of games per iteration is required".to_string());
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Parse a numeric command line argument.
+///
+/// # Errors
+///
+/// This function will return an `Err` if `s` is not a valid number.
+fn parse_num<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("invalid number `{s}`"))
+}
+
+#[allow(clippy::cast_precision_loss)]
+/// Get the perturbation size of a parameter at the end of a run.
+fn c_end(param: SearchParam) -> f64 {
+    let (min, max) = param.bounds();
+    ((max - min) as f64 / 20.).max(1.)
+}
+
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+/// Run SPSA on the parameters, logging every iteration to `csv`, and return the final values of
+/// the parameters.
+///
+/// # Errors
+///
+/// This function will return an `Err` if writing to `csv` fails or if a search fails.
+fn tune(settings: &Settings, mut csv: impl Write) -> Result<Vec<f64>, String> {
+    let write_err = |e| format!("could not write to {}: {e}", settings.csv);
+    let params = &settings.params;
+    let mut theta: Vec<f64> = params.iter().map(|p| p.default_value() as f64).collect();
+    let n = settings.iterations as f64;
+    // the stability constant, which keeps the first steps from being too large
+    let big_a = 0.1 * n;
+
+    let names: Vec<&str> = params.iter().map(|p| p.name()).collect();
+    writeln!(csv, "iteration,wins,draws,losses,{}", names.join(",")).map_err(write_err)?;
+
+    let mut searchers: Vec<(MainSearch, MainSearch)> = (0..settings.threads)
+        .map(|_| {
+            (
+                searcher(settings.nodes, settings.hash_mb),
+                searcher(settings.nodes, settings.hash_mb),
+            )
+        })
+        .collect();
+    for k in 1..=settings.iterations {
+        let rng = Rng::with_seed(settings.seed ^ (k as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let kf = k as f64;
+        let delta: Vec<f64> = params
+            .iter()
+            .map(|_| if rng.bool() { 1. } else { -1. })
+            .collect();
+        let c_k: Vec<f64> = params
+            .iter()
+            .map(|&p| c_end(p) * (n / kf).powf(GAMMA))
+            .collect();
+
+        for (((&param, t), d), c) in params.iter().zip(&theta).zip(&delta).zip(&c_k) {
+            let (min, max) = param.bounds();
+            let plus_value = ((t + c * d).round() as i64).clamp(min, max).to_string();
+            let minus_value = ((t - c * d).round() as i64).clamp(min, max).to_string();
+            for (plus, minus) in &mut searchers {
+                SearchParam::set_option(&mut plus.config, param.name(), Some(&plus_value))?;
+                SearchParam::set_option(&mut minus.config, param.name(), Some(&minus_value))?;
+            }
+        }
+
+        let openings: Vec<Game> = (0..settings.pairs)
+            .map(|_| random_opening(&rng, settings.random_plies))
+            .collect();
+        let (wins, draws, losses) = play_pairs(&mut searchers, &openings)
+            .map_err(|e| format!("search failed in iteration {k}: {e:?}"))?;
+        // the score of the positive perturbation minus that of the negative one
+        let result = f64::from(wins) - f64::from(losses);
+
+        for (i, &param) in params.iter().enumerate() {
+            let a_end = settings.r_end * c_end(param).powi(2);
+            let a_k = a_end * (big_a + n).powf(ALPHA) / (big_a + kf).powf(ALPHA);
+            let (min, max) = param.bounds();
+            theta[i] = (theta[i] + a_k / c_k[i] * result * delta[i]).clamp(min as f64, max as f64);
+        }
+
+        let values: Vec<String> = theta.iter().map(|t| format!("{t:.3}")).collect();
+        writeln!(csv, "{k},{wins},{draws},{losses},{}", values.join(",")).map_err(write_err)?;
+        csv.flush().map_err(write_err)?;
+        eprintln!(
+            "iteration {k}: +{wins} ={draws} -{losses}; {}",
+            names
+                .iter()
+                .zip(&theta)
+                .map(|(name, t)| format!("{name} {t:.1}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(theta)
+}
+
+/// Play a pair of games from each opening between the searchers of each pair, spreading the
+/// openings across threads.
+/// Returns the number of wins, draws, and losses of the first searcher of each pair.
+///
+/// # Errors
+///
+/// This function will return an `Err` if any search fails.
+fn play_pairs(
+    searchers: &mut [(MainSearch, MainSearch)],
+    openings: &[Game],
+) -> Result<(u32, u32, u32), SearchError> {
+    let chunk_size = openings.len().div_ceil(searchers.len());
+    scope(|s| {
+        let handles: Vec<_> = searchers
+            .iter_mut()
+            .zip(openings.chunks(chunk_size))
+            .map(|((plus, minus), chunk)| {
+                s.spawn(move || {
+                    let mut wdl = (0, 0, 0);
+                    for opening in chunk {
+                        for plus_color in [Color::White, Color::Black] {
+                            match play_game(plus, minus, opening, plus_color)? {
+                                Some(c) if c == plus_color => wdl.0 += 1,
+                                Some(_) => wdl.2 += 1,
+                                None => wdl.1 += 1,
+                            }
+                        }
+                    }
+                    Ok::<_, SearchError>(wdl)
+                })
+            })
+            .collect();
+        handles.into_iter().try_fold((0, 0, 0), |acc, h| {
+            let wdl = h.join().map_err(|_| SearchError::Join)??;
+            Ok((acc.0 + wdl.0, acc.1 + wdl.1, acc.2 + wdl.2))
+        })
+    })
+}
+
+/// Play a single game from `opening` between `plus`, playing as `plus_color`, and `minus`.
+/// Returns the color of the winner, or `None` if the game was drawn.
+///
+/// # Errors
+///
+/// This function will return an `Err` if any search fails.
+fn play_game(
+    plus: &mut MainSearch,
+    minus: &mut MainSearch,
+    opening: &Game,
+    plus_color: Color,
+) -> Result<Option<Color>, SearchError> {
+    plus.ttable.clear();
+    minus.ttable.clear();
+    let mut game = opening.clone();
+    play_out(&mut game, |game| {
+        let searcher = if game.board().player == plus_color {
+            &mut *plus
+        } else {
+            &mut *minus
+        };
+        search(searcher, game)
+    })?;
+
+    Ok(match game.result() {
+        Some(GameResult::WhiteWins) => Some(Color::White),
+        Some(GameResult::BlackWins) => Some(Color::Black),
+        Some(GameResult::Draw) | None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the settings used for fast tests.
+    fn test_settings(extra: &str) -> Settings {
+        Settings::parse(
+            format!("--iterations 3 --pairs 2 --nodes 100 --threads 2 --hash 1 --seed 3 {extra}")
+                .split_whitespace()
+                .map(str::to_string),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    /// Test that parameters are chosen by name, and that malformed arguments are rejected.
+    fn parse_settings() {
+        assert_eq!(test_settings("").params, SearchParam::ALL);
+        assert_eq!(
+            test_settings("--param aspirationwide --param TtMaxAge --param AspirationWide").params,
+            [SearchParam::AspirationWide, SearchParam::TtMaxAge]
+        );
+        let parse = |s: &str| Settings::parse(s.split_whitespace().map(str::to_string));
+        assert!(parse("--param Foo").is_err());
+        assert!(parse("--threads 0").is_err());
+        assert!(parse("--pairs").is_err());
+    }
+
+    #[test]
+    /// Test that a run logs every iteration, keeps every parameter in bounds, and is determined by
+    /// its seed.
+    fn run() {
+        let settings = test_settings("--param AspirationNarrow --param LmrEarlyMoves");
+        let mut csv = Vec::new();
+        let theta = tune(&settings, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "iteration,wins,draws,losses,AspirationNarrow,LmrEarlyMoves"
+        );
+        assert_eq!(lines.len(), 4);
+        for (k, line) in lines[1..].iter().enumerate() {
+            let fields: Vec<&str> = line.split(',').collect();
+            assert_eq!(fields[0], (k + 1).to_string());
+            let games: u32 = fields[1..4].iter().map(|f| f.parse::<u32>().unwrap()).sum();
+            assert_eq!(games, 4);
+        }
+        for (param, t) in settings.params.iter().zip(&theta) {
+            let (min, max) = param.bounds();
+            #[allow(clippy::cast_precision_loss)]
+            let in_bounds = (min as f64..=max as f64).contains(t);
+            assert!(in_bounds, "{param:?}");
+        }
+
+        let single = Settings {
+            threads: 1,
+            ..settings
+        };
+        assert_eq!(tune(&single, Vec::new()).unwrap(), theta);
+    }
+}
@@ -17,11 +17,38 @@
 */
 
 //! The tuner for the Tomato chess engine.
-//! This file exists to create a binary which can be used to generate weights from an annotated EPD
-//! file.
+//! This file exists to create a binary which can be used to generate weights from files of
+//! positions labeled with the results of their games.
 //!
 //! The tuner operates by using gradient descent on logistic regression to classify the results of a
 //! given position.
+//! Before training, the scaling constant `K` of the sigmoid is fitted so that the starting weights
+//! predict the results as well as possible; it is then held fixed.
+//!
+//! Three formats of training data are accepted, and may be mixed freely:
+//!
+//! * `fen "result";` (EPD, where the result is `1-0`, `0-1`, or `1/2-1/2`), as written by
+//!   `tomato-datagen`, optionally followed by a `ce` operation giving a search evaluation in
+//!   centipawns from the perspective of the player to move (as written by `tomato-datagen --eval`)
+//! * `fen [result]`, where the result is either a result token or a score such as `0.5`
+//! * `fen | score | result`, where the score is a search evaluation in centipawns from White's
+//!   perspective
+//!
+//! After every epoch, the current weights are written to the checkpoint file, and the best weights
+//! found so far are written to a separate file, both in the format described in
+//! `tomato::engine::evaluate::weights`.
+//! The engine can then play with them by setting its `EvalWeights` option to either file.
+//! Throughout training, the midgame phase cutoff is kept at least a pawn above the endgame one, so
+//! every file written is one which the engine can load.
+//!
+//! Usage:
+//!
+//! ```text
+//! tune --data FILE [--data FILE ...] [--threads N] [--epochs N] [--patience N]
+//!      [--optimizer adam|momentum|sgd] [--learn-rate F] [--momentum F] [--k F]
+//!      [--validation F] [--lambda F] [--seed N] [--weights FILE] [--checkpoint FILE]
+//!      [--best FILE]
+//! ```
 
 #![warn(clippy::pedantic)]
 #![allow(clippy::inline_always)]
@@ -29,66 +56,266 @@
 use std::{
     env,
     fs::File,
-    io::{BufRead, BufReader},
-    ops::{MulAssign, SubAssign},
-    thread::scope,
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    ops::{AddAssign, MulAssign},
+    process::exit,
+    thread::{available_parallelism, scope},
     time::Instant,
 };
 
-use tomato::engine::evaluate::{material, pst::PST};
+use fastrand::Rng;
 use tomato::{
-    base::{Board, Color, Piece, Square},
-    engine::evaluate::{EG_LIMIT, MG_LIMIT},
+    base::{Board, Color, Piece},
+    engine::evaluate::{trace, Eval, EvalTrace, EvalWeights, Score},
 };
 
-#[allow(clippy::similar_names)]
-/// Run the main training function.
-///
-/// The first command line argument must the the path of the file containing training data.
-///
-/// # Panics
-///
-/// This function will panic if the EPD training data is not specified or does not exist.
+/// The smallest gap between the midgame and endgame phase cutoffs, in pawns, which training may
+/// leave.
+/// A narrower gap would make the blend of midgame and endgame evaluations nearly discontinuous.
+const MIN_PHASE_GAP: f32 = 1.;
+
+/// The usage message printed when the command line arguments are malformed.
+const USAGE: &str = "usage: tune --data FILE [--data FILE ...] [--threads N] [--epochs N] \
+[--patience N] [--optimizer adam|momentum|sgd] [--learn-rate F] [--momentum F] [--k F] \
+[--validation F] [--lambda F] [--seed N] [--weights FILE] [--checkpoint FILE] [--best FILE]";
+
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+/// Run the main training function, as configured by the command line arguments.
 pub fn main() {
-    let args: Vec<String> = env::args().collect();
-    // first argument is the name of the binary
-    let path_str = &args[1..].join(" ");
-    let mut weights = load_weights();
-    let mut err = f32::INFINITY;
-    let learn_rate = 0.1;
-
-    let nthreads = 14;
+    let settings = match Settings::parse(env::args().skip(1)) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!("{USAGE}");
+            exit(1);
+        }
+    };
     let tic = Instant::now();
+    let mut data = Vec::new();
+    for path in &settings.data {
+        match load_data(path) {
+            Ok(mut file_data) => data.append(&mut file_data),
+            Err(e) => {
+                eprintln!("{e}");
+                exit(1);
+            }
+        }
+    }
+    println!(
+        "extracted {} positions in {:.1} secs",
+        data.len(),
+        tic.elapsed().as_secs_f32()
+    );
+
+    // shuffle before splitting so that the validation set is not all from the end of one file
+    let rng = Rng::with_seed(settings.seed);
+    rng.shuffle(&mut data);
+    let num_validation = (data.len() as f64 * settings.validation) as usize;
+    let validation_data = data.split_off(data.len() - num_validation);
+    if data.is_empty() {
+        eprintln!("no training data");
+        exit(1);
+    }
+
+    let initial_weights = match &settings.weights {
+        Some(path) => EvalWeights::load(path).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            exit(1);
+        }),
+        None => EvalWeights::DEFAULT,
+    };
+    let mut weights = load_weights(&initial_weights);
+    let k = settings
+        .k
+        .unwrap_or_else(|| fit_k(&data, &weights, settings.threads));
+    println!("using K = {k:.4}");
+    let train = targets(data, k, settings.lambda);
+    let validation = targets(validation_data, k, settings.lambda);
+
+    let mut optimizer = Optimizer::new(settings.optimizer, &weights);
+    let mut best_err = f32::INFINITY;
+    let mut stale_epochs = 0;
+    for epoch in 1..=settings.epochs {
+        let tic = Instant::now();
+        let (grad, train_err) = gradient(&train, &weights, k, settings.threads);
+        optimizer.step(&mut weights, &grad, settings.learn_rate);
+        weights.separate_phase_cutoffs();
+        let err = if validation.is_empty() {
+            train_err
+        } else {
+            let validation_err = error(&validation, &weights, k, settings.threads);
+            print!("validation mse {validation_err:.6}, ");
+            validation_err
+        };
+        println!(
+            "epoch {epoch}: training mse {train_err:.6} ({:.0} positions/sec)",
+            train.len() as f32 / tic.elapsed().as_secs_f32()
+        );
 
-    // construct the datasets.
-    // Outer vector: each element for one datum
-    // Inner vector: each element for one feature-quantity pair
-    let input_sets = extract_epd(path_str).unwrap();
-
-    let toc = Instant::now();
-    println!("extracted data in {} secs", (toc - tic).as_secs());
-    let mut i = 0;
-    loop {
-        println!("iteration {i}...");
-        let (new_weights, new_err) = train_step(&input_sets, &weights, learn_rate, nthreads);
-        if new_err > err {
-            break;
+        if let Err(e) = save_weights(&weights, &settings.checkpoint) {
+            eprintln!("could not write checkpoint {}: {e}", settings.checkpoint);
+            exit(1);
+        }
+        if err < best_err {
+            best_err = err;
+            stale_epochs = 0;
+            if let Err(e) = save_weights(&weights, &settings.best) {
+                eprintln!("could not write best weights {}: {e}", settings.best);
+                exit(1);
+            }
+        } else {
+            stale_epochs += 1;
+            if settings.patience <= stale_epochs {
+                println!("no improvement in {stale_epochs} epochs; stopping");
+                break;
+            }
         }
-        weights = new_weights;
-        err = new_err;
-        i += 1;
     }
 
-    print_weights(&weights);
+    println!(
+        "best mse {best_err:.6}; best weights written to {}",
+        settings.best
+    );
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The choice of algorithm used to update the weights from their gradient.
+enum OptimizerKind {
+    /// Plain gradient descent.
+    Sgd,
+    /// Gradient descent with momentum, with the given decay rate for the velocity.
+    Momentum(f32),
+    /// The Adam optimizer.
+    Adam,
 }
 
-/// Extracted features from a board for gradient descent.
-struct BoardFeatures {
-    /// The total (not net) quantities of each piece type on the board.
-    piece_counts: [f32; Piece::NUM - 1],
-    /// The net rule counts for pieces on the board.
-    /// Includes material counts.
-    rules: Vec<(usize, f32)>,
+#[derive(Clone, Debug, PartialEq)]
+/// The configuration of a tuning run.
+struct Settings {
+    /// The paths of the files of training data.
+    data: Vec<String>,
+    /// The number of threads to compute gradients with.
+    threads: usize,
+    /// The maximum number of epochs to train for.
+    epochs: usize,
+    /// The number of epochs without any improvement in validation error after which training
+    /// stops.
+    patience: usize,
+    /// The algorithm used to update the weights.
+    optimizer: OptimizerKind,
+    /// The learning rate of the optimizer.
+    learn_rate: f32,
+    /// The sigmoid scaling constant.
+    /// If `None`, it is fitted to the training data before training.
+    k: Option<f32>,
+    /// The fraction of the data which is held out for validation.
+    validation: f64,
+    /// The weight given to the game result, rather than the recorded search score, when a
+    /// position has both.
+    lambda: f32,
+    /// The seed used to shuffle the data.
+    seed: u64,
+    /// The file of evaluation weights to start training from.
+    /// If `None`, training starts from the compiled-in weights.
+    weights: Option<String>,
+    /// The file to which the current weights are written after every epoch.
+    checkpoint: String,
+    /// The file to which the best weights so far are written after every epoch which improves on
+    /// them.
+    best: String,
+}
+
+impl Settings {
+    /// Parse the settings of a tuning run from its command line arguments.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the arguments are malformed.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Settings, String> {
+        let mut settings = Settings {
+            data: Vec::new(),
+            threads: available_parallelism().map_or(1, usize::from),
+            epochs: 1000,
+            patience: 10,
+            optimizer: OptimizerKind::Adam,
+            learn_rate: 0.01,
+            k: None,
+            validation: 0.1,
+            lambda: 1.0,
+            seed: 0,
+            weights: None,
+            checkpoint: "tuned_weights.txt".to_string(),
+            best: "best_weights.txt".to_string(),
+        };
+        let mut momentum = 0.9;
+        let mut optimizer_name = "adam".to_string();
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {flag}"))
+            };
+            match flag.as_str() {
+                "--data" => settings.data.push(value()?),
+                "--threads" => settings.threads = parse_num(&value()?)?,
+                "--epochs" => settings.epochs = parse_num(&value()?)?,
+                "--patience" => settings.patience = parse_num(&value()?)?,
+                "--optimizer" => optimizer_name = value()?,
+                "--learn-rate" => settings.learn_rate = parse_num(&value()?)?,
+                "--momentum" => momentum = parse_num(&value()?)?,
+                "--k" => settings.k = Some(parse_num(&value()?)?),
+                "--validation" => settings.validation = parse_num(&value()?)?,
+                "--lambda" => settings.lambda = parse_num(&value()?)?,
+                "--seed" => settings.seed = parse_num(&value()?)?,
+                "--weights" => settings.weights = Some(value()?),
+                "--checkpoint" => settings.checkpoint = value()?,
+                "--best" => settings.best = value()?,
+                _ => return Err(format!("unknown argument `{flag}`")),
+            }
+        }
+        settings.optimizer = match optimizer_name.as_str() {
+            "adam" => OptimizerKind::Adam,
+            "sgd" => OptimizerKind::Sgd,
+            "momentum" => OptimizerKind::Momentum(momentum),
+            _ => return Err(format!("unknown optimizer `{optimizer_name}`")),
+        };
+        if settings.data.is_empty() {
+            return Err("no training data given".to_string());
+        }
+        if settings.threads == 0 {
+            return Err("at least one thread is required".to_string());
+        }
+        if !(0.0..1.0).contains(&settings.validation) {
+            return Err("the validation fraction must be in [0, 1)".to_string());
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Parse a numeric command line argument.
+///
+/// # Errors
+///
+/// This function will return an `Err` if `s` is not a valid number.
+fn parse_num<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("invalid number `{s}`"))
+}
+
+/// A single labeled position of training data.
+struct Datum {
+    /// The features of the position.
+    features: EvalTrace,
+    /// The result of the game the position came from, from White's perspective: 1 for a win, 0.5
+    /// for a draw, and 0 for a loss.
+    result: f32,
+    /// The search evaluation of the position in pawns from White's perspective, if one was
+    /// recorded.
+    score: Option<f32>,
 }
 
 #[derive(Clone)]
@@ -97,97 +324,249 @@ struct BoardFeatures {
 struct Weights {
     /// The cutoffs for blending bedween midgame and endgame.
     phase_cutoffs: (f32, f32),
-    /// The values of each rule (such as square occupancy, mobility, or handmade rules).
-    /// The first five entries in `rule_values` must always be the material values.
+    /// The midgame and endgame values of each feature, indexed as numbered by `EvalWeights`.
     rule_values: Vec<(f32, f32)>,
 }
 
 /// The gradient of the error with respect to the weights has the same dimension as weights.
 type GradWeights = Weights;
 
-/// Expand an EPD file into a set of features that can be used for training.
-fn extract_epd(location: &str) -> Result<Vec<(BoardFeatures, f32)>, Box<dyn std::error::Error>> {
-    let file = File::open(location)?;
-    let reader = BufReader::new(file);
+/// Load a file of training data, in any mix of the supported formats.
+/// Blank lines are ignored.
+///
+/// # Errors
+///
+/// This function will return an `Err` if the file cannot be read or any line is malformed.
+fn load_data(location: &str) -> Result<Vec<Datum>, String> {
+    let file = File::open(location).map_err(|e| format!("could not open {location}: {e}"))?;
     let mut data = Vec::new();
 
-    for line_result in reader.lines() {
-        let line = line_result?;
-        let mut split_line = line.split('"');
-        // first part of the split is the FEN, second is the score, last is just a semicolon
-        let fen = split_line.next().ok_or("no FEN given")?;
-        let b = Board::from_fen(fen)?;
-        let features = extract(&b);
-        let score_str = split_line.next().ok_or("no result given")?;
-        let score = match score_str {
-            "1/2-1/2" => 0.5,
-            "0-1" => 0.,
-            "1-0" => 1.,
-            _ => Err("unknown score string")?,
-        };
-        data.push((features, score));
+    for (i, line_result) in BufReader::new(file).lines().enumerate() {
+        let line = line_result.map_err(|e| format!("could not read {location}: {e}"))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (b, result, score) =
+            parse_line(&line).map_err(|e| format!("{location}:{}: {e}", i + 1))?;
+        data.push(Datum {
+            features: trace(&b),
+            result,
+            score,
+        });
     }
 
     Ok(data)
 }
 
-#[allow(clippy::similar_names, clippy::cast_precision_loss)]
-#[inline(never)]
-/// Perform one epoch of gradient descent training.
+/// Parse one line of training data, returning the position, the result of its game, and its
+/// score in pawns from White's perspective, if it has one.
+///
+/// # Errors
 ///
-/// Inputs:
-/// * `inputs`: a vector containing the input vector and the expected evaluation.
-/// * `weights`: the weight vector to train on.
-/// * `learn_rate`: a coefficient on the speed at which the engine learns.
-/// * `nthreads`: the number of threads to parallelize over.
+/// This function will return an `Err` if the line is not in any of the supported formats.
+fn parse_line(line: &str) -> Result<(Board, f32, Option<f32>), String> {
+    // the score in centipawns, and whether it is from the perspective of the player to move
+    // rather than White
+    let (fen, result_str, score, relative) = if line.contains('|') {
+        // fen | score | result
+        let mut fields = line.split('|').map(str::trim);
+        let fen = fields.next().ok_or("no FEN given")?;
+        let score_str = fields.next().ok_or("no score given")?;
+        let score: f32 = score_str
+            .parse()
+            .map_err(|_| format!("invalid score `{score_str}`"))?;
+        let result = fields.next().ok_or("no result given")?;
+        (fen, result, Some(score), false)
+    } else if let Some((fen, rest)) = line.split_once('[') {
+        // fen [result]
+        let result = rest.split_once(']').ok_or("unterminated result")?.0;
+        (fen, result, None, false)
+    } else {
+        // fen "result"; followed by any other EPD operations
+        let mut split_line = line.splitn(3, '"');
+        let fen = split_line.next().ok_or("no FEN given")?;
+        let result = split_line.next().ok_or("no result given")?;
+        let score = match split_line.next() {
+            Some(ops) => parse_ce(ops)?,
+            None => None,
+        };
+        (fen, result, score, true)
+    };
+    let b = Board::from_fen(fen.trim())?;
+    let score = score.map(|cp| {
+        if relative && b.player == Color::Black {
+            -cp / 100.
+        } else {
+            cp / 100.
+        }
+    });
+    let result = match result_str.trim() {
+        "1-0" => 1.,
+        "1/2-1/2" => 0.5,
+        "0-1" => 0.,
+        s => s
+            .parse()
+            .ok()
+            .filter(|x| (0.0..=1.0).contains(x))
+            .ok_or_else(|| format!("unknown result `{s}`"))?,
+    };
+
+    Ok((b, result, score))
+}
+
+/// Find the `ce` (centipawn evaluation) operation among a list of EPD operations, and return its
+/// value.
 ///
-/// Each element of `inputs` must be the same length as `weights`.
-fn train_step(
-    inputs: &[(BoardFeatures, f32)],
+/// # Errors
+///
+/// This function will return an `Err` if the value of the `ce` operation is not a number.
+fn parse_ce(ops: &str) -> Result<Option<f32>, String> {
+    for op in ops.split(';') {
+        if let Some(cp) = op.trim().strip_prefix("ce ") {
+            let cp = cp.trim();
+            return cp
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid centipawn evaluation `{cp}`"));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Convert labeled positions into pairs of features and training targets.
+/// Positions with a recorded score are given a target which blends the result of their game with
+/// the score, weighted by `lambda`.
+fn targets(data: Vec<Datum>, k: f32, lambda: f32) -> Vec<(EvalTrace, f32)> {
+    data.into_iter()
+        .map(|datum| {
+            let target = match datum.score {
+                Some(score) => lambda * datum.result + (1. - lambda) * sigmoid(k * score),
+                None => datum.result,
+            };
+            (datum.features, target)
+        })
+        .collect()
+}
+
+#[allow(clippy::cast_precision_loss)]
+/// Find the sigmoid scaling constant `K` which minimizes the mean squared error of `weights` in
+/// predicting the results of the positions in `data`.
+fn fit_k(data: &[Datum], weights: &Weights, nthreads: usize) -> f32 {
+    let tic = Instant::now();
+    let evals: Vec<(f32, f32)> = data
+        .iter()
+        .map(|datum| (weights.evaluate(&datum.features), datum.result))
+        .collect();
+    let mse = |k: f32| {
+        parallel_sum(&evals, nthreads, |chunk| {
+            chunk
+                .iter()
+                .map(|&(eval, result)| (sigmoid(k * eval) - result).powi(2))
+                .sum::<f32>()
+        }) / evals.len() as f32
+    };
+
+    // golden-section search; the error is unimodal in K
+    let ratio = (5f32.sqrt() - 1.) / 2.;
+    let (mut lo, mut hi) = (0.01f32, 10.);
+    let mut a = hi - ratio * (hi - lo);
+    let mut b = lo + ratio * (hi - lo);
+    let (mut err_a, mut err_b) = (mse(a), mse(b));
+    while 1e-4 < hi - lo {
+        if err_a < err_b {
+            hi = b;
+            b = a;
+            err_b = err_a;
+            a = hi - ratio * (hi - lo);
+            err_a = mse(a);
+        } else {
+            lo = a;
+            a = b;
+            err_a = err_b;
+            b = lo + ratio * (hi - lo);
+            err_b = mse(b);
+        }
+    }
+    let k = f32::midpoint(lo, hi);
+    println!(
+        "fitted K in {:.1} secs: mse {:.6}",
+        tic.elapsed().as_secs_f32(),
+        mse(k)
+    );
+
+    k
+}
+
+/// Split `items` into one contiguous chunk per thread, compute `f` of each chunk in parallel, and
+/// sum the results.
+/// Every item belongs to exactly one chunk.
+fn parallel_sum<T: Sync, S: Send + std::iter::Sum<S>>(
+    items: &[T],
+    nthreads: usize,
+    f: impl Fn(&[T]) -> S + Sync,
+) -> S {
+    let chunk_size = items.len().div_ceil(nthreads).max(1);
+    let f = &f;
+    scope(|s| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| s.spawn(move || f(chunk)))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    })
+}
+
+#[allow(clippy::cast_precision_loss)]
+/// Compute the gradient of the mean squared error of `weights` over `inputs`, along with the mean
+/// squared error itself.
+fn gradient(
+    inputs: &[(EvalTrace, f32)],
     weights: &Weights,
-    learn_rate: f32,
+    k: f32,
     nthreads: usize,
-) -> (Weights, f32) {
-    let tic = Instant::now();
-    let chunk_size = inputs.len() / nthreads;
-    let mut new_weights = weights.clone();
+) -> (GradWeights, f32) {
+    let chunk_size = inputs.len().div_ceil(nthreads).max(1);
+    let mut grad = Weights::zero(weights);
     let mut sum_se = 0.;
     scope(|s| {
-        let mut grads = Vec::new();
-        for thread_id in 0..nthreads {
-            // start the parallel work
-            let start = chunk_size * thread_id;
-            grads.push(s.spawn(move || train_thread(&inputs[start..][..chunk_size], weights)));
-        }
-        for grad_handle in grads {
-            let (mut sub_grad, se) = grad_handle.join().unwrap();
+        let handles: Vec<_> = inputs
+            .chunks(chunk_size)
+            .map(|chunk| s.spawn(move || train_thread(chunk, weights, k)))
+            .collect();
+        for handle in handles {
+            let (sub_grad, se) = handle.join().unwrap();
             sum_se += se;
-            sub_grad *= learn_rate / inputs.len() as f32;
-            new_weights -= sub_grad;
+            grad += sub_grad;
         }
     });
-    let toc = Instant::now();
-    let mse = sum_se / inputs.len() as f32;
-    println!(
-        "{} nodes in {:?}: {:.0} nodes/sec; mse {}",
-        inputs.len(),
-        (toc - tic),
-        inputs.len() as f32 / (toc - tic).as_secs_f32(),
-        mse
-    );
+    grad *= 1. / inputs.len() as f32;
+
+    (grad, sum_se / inputs.len() as f32)
+}
 
-    (new_weights, mse)
+#[allow(clippy::cast_precision_loss)]
+/// Compute the mean squared error of `weights` over `inputs`.
+fn error(inputs: &[(EvalTrace, f32)], weights: &Weights, k: f32, nthreads: usize) -> f32 {
+    parallel_sum(inputs, nthreads, |chunk| {
+        chunk
+            .iter()
+            .map(|(features, expected)| {
+                (sigmoid(k * weights.evaluate(features)) - expected).powi(2)
+            })
+            .sum::<f32>()
+    }) / inputs.len() as f32
 }
 
 /// Construct the gradient vector for a subset of the input data.
 /// Returns the sum of the squared error across this epoch.
-fn train_thread(input: &[(BoardFeatures, f32)], weights: &Weights) -> (Weights, f32) {
+fn train_thread(input: &[(EvalTrace, f32)], weights: &Weights, k: f32) -> (Weights, f32) {
     let mut grad = Weights::zero(weights);
     let mut sum_se = 0.;
     for (features, sigm_expected) in input {
-        let sigm_eval = sigmoid(weights.evaluate(features));
+        let sigm_eval = sigmoid(k * weights.evaluate(features));
         let err = sigm_eval - sigm_expected;
-        let coeff = sigm_eval * (1. - sigm_eval) * err;
+        let coeff = k * sigm_eval * (1. - sigm_eval) * err;
 
         sum_se += err * err;
         weights.eval_gradient(&mut grad, coeff, features);
@@ -196,6 +575,85 @@ fn train_thread(input: &[(BoardFeatures, f32)], weights: &Weights) -> (Weights,
     (grad, sum_se)
 }
 
+/// An optimizer, which updates weights from their gradients and keeps whatever state it needs
+/// between epochs.
+enum Optimizer {
+    /// Plain gradient descent.
+    Sgd,
+    /// Gradient descent with momentum.
+    Momentum {
+        /// The decay rate of the velocity.
+        decay: f32,
+        /// The running velocity of each weight.
+        velocity: Vec<f32>,
+    },
+    /// The Adam optimizer.
+    Adam {
+        /// The running mean of each weight's gradient.
+        mean: Vec<f32>,
+        /// The running uncentered variance of each weight's gradient.
+        variance: Vec<f32>,
+        /// The number of steps taken so far.
+        t: i32,
+    },
+}
+
+impl Optimizer {
+    /// The decay rate of the running mean of the gradients in Adam.
+    const ADAM_BETA1: f32 = 0.9;
+    /// The decay rate of the running variance of the gradients in Adam.
+    const ADAM_BETA2: f32 = 0.999;
+    /// A small constant added to the denominator of Adam's updates to avoid dividing by zero.
+    const ADAM_EPSILON: f32 = 1e-8;
+
+    /// Construct a new optimizer of the given kind for weights shaped like `weights`.
+    fn new(kind: OptimizerKind, weights: &Weights) -> Optimizer {
+        let n = weights.params().count();
+        match kind {
+            OptimizerKind::Sgd => Optimizer::Sgd,
+            OptimizerKind::Momentum(decay) => Optimizer::Momentum {
+                decay,
+                velocity: vec![0.; n],
+            },
+            OptimizerKind::Adam => Optimizer::Adam {
+                mean: vec![0.; n],
+                variance: vec![0.; n],
+                t: 0,
+            },
+        }
+    }
+
+    /// Take a single step of descent on `weights`, given the gradient of the error.
+    fn step(&mut self, weights: &mut Weights, grad: &GradWeights, learn_rate: f32) {
+        let grads = grad.params();
+        match self {
+            Optimizer::Sgd => {
+                for (w, g) in weights.params_mut().zip(grads) {
+                    *w -= learn_rate * g;
+                }
+            }
+            Optimizer::Momentum { decay, velocity } => {
+                for ((w, g), v) in weights.params_mut().zip(grads).zip(velocity) {
+                    *v = *decay * *v + g;
+                    *w -= learn_rate * *v;
+                }
+            }
+            Optimizer::Adam { mean, variance, t } => {
+                *t += 1;
+                let correction1 = 1. - Self::ADAM_BETA1.powi(*t);
+                let correction2 = 1. - Self::ADAM_BETA2.powi(*t);
+                for (((w, g), m), v) in weights.params_mut().zip(grads).zip(mean).zip(variance) {
+                    *m = Self::ADAM_BETA1 * *m + (1. - Self::ADAM_BETA1) * g;
+                    *v = Self::ADAM_BETA2 * *v + (1. - Self::ADAM_BETA2) * g * g;
+                    let m_hat = *m / correction1;
+                    let v_hat = *v / correction2;
+                    *w -= learn_rate * m_hat / (v_hat.sqrt() + Self::ADAM_EPSILON);
+                }
+            }
+        }
+    }
+}
+
 #[inline(always)]
 /// Compute the  sigmoid function of a variable.
 /// `beta` is the horizontal scaling of the sigmoid.
@@ -206,129 +664,82 @@ fn sigmoid(x: f32) -> f32 {
     1. / (1. + (-x).exp())
 }
 
-/// Load the weight value constants from the ones defined in the PST evaluation.
-fn load_weights() -> Weights {
-    let mut weights = Weights {
-        phase_cutoffs: (MG_LIMIT.float_val(), EG_LIMIT.float_val()),
-        rule_values: Vec::new(),
-    };
-
-    // apply material values
-    for pt in Piece::NON_KING {
-        let val = material::value(pt);
-        weights
-            .rule_values
-            .push((val.mg.float_val(), val.eg.float_val()));
+/// Convert a set of evaluation weights into weights for gradient descent.
+fn load_weights(eval_weights: &EvalWeights) -> Weights {
+    Weights {
+        phase_cutoffs: (
+            eval_weights.mg_limit.float_val(),
+            eval_weights.eg_limit.float_val(),
+        ),
+        rule_values: (0..EvalWeights::NUM_FEATURES)
+            .map(|idx| {
+                let score = eval_weights.feature(idx);
+                (score.mg.float_val(), score.eg.float_val())
+            })
+            .collect(),
     }
+}
 
-    for pt in Piece::ALL {
-        for rank in 0..8 {
-            for file in 0..8 {
-                let sq_idx = 8 * rank + file;
-                let score = PST[pt as usize][sq_idx];
-                weights
-                    .rule_values
-                    .push((score.mg.float_val(), score.eg.float_val()));
-            }
-        }
+#[allow(clippy::cast_possible_truncation)]
+/// Round trained weights to the nearest centipawn to construct a set of evaluation weights.
+fn to_eval_weights(weights: &Weights) -> EvalWeights {
+    let cp = |x: f32| Eval::centipawns((x * 100.).round() as i16);
+    let mut eval_weights = EvalWeights {
+        mg_limit: cp(weights.phase_cutoffs.0),
+        eg_limit: cp(weights.phase_cutoffs.1),
+        ..EvalWeights::DEFAULT
+    };
+    for (idx, &(mg, eg)) in weights.rule_values.iter().enumerate() {
+        *eval_weights.feature_mut(idx) = Score::new(cp(mg), cp(eg));
     }
 
-    weights
+    eval_weights
 }
 
-#[allow(clippy::cast_possible_truncation, clippy::similar_names)]
-/// Print out a weights vector so it can be used as code.
-fn print_weights(weights: &Weights) {
-    println!(
-        "pub const MG_LIMIT: Eval = Eval::centipawns({})",
-        (weights.phase_cutoffs.0 * 100.) as i16
-    );
-
-    println!(
-        "pub const EG_LIMIT: Eval = Eval::centipawns({})",
-        (weights.phase_cutoffs.1 * 100.) as i16
-    );
+/// Write out a weights vector to the file at `path`, in the format which the engine loads through
+/// its `EvalWeights` option.
+///
+/// # Errors
+///
+/// This function will return an `Err` if the file cannot be written, or if the engine would not be
+/// able to load the weights, in which case nothing is written.
+fn save_weights(weights: &Weights, path: &str) -> std::io::Result<()> {
+    let text = to_eval_weights(weights).to_string();
+    // check that the file would load before overwriting anything
+    text.parse::<EvalWeights>()
+        .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+    let mut f = BufWriter::new(File::create(path)?);
+    f.write_all(text.as_bytes())?;
+    f.flush()
+}
 
-    let mut offset = 0;
-    // print material values
-    for pt in Piece::NON_KING {
-        let fscore = weights.rule_values[offset + pt as usize];
-        println!(
-            "Piece::{pt:?} => Score::centipawns({}, {}),",
-            (fscore.0 * 100.) as i16,
-            (fscore.1 * 100.) as i16
-        );
+impl Weights {
+    /// Iterate over every individual weight, in a fixed order.
+    fn params(&self) -> impl Iterator<Item = f32> + '_ {
+        [self.phase_cutoffs.0, self.phase_cutoffs.1]
+            .into_iter()
+            .chain(self.rule_values.iter().flat_map(|&(mg, eg)| [mg, eg]))
     }
 
-    offset += 5;
-    println!("-----");
-
-    // print PST
-    println!("pub const PST: Pst = unsafe {{ transmute([");
-    for pt in Piece::ALL {
-        println!("    [ // {pt}");
-        let pt_idx = offset + (64 * pt as usize);
-        for rank in 0..8 {
-            print!("        ");
-            for file in 0..8 {
-                let sq = Square::new(rank, file).unwrap();
-                let fscore = weights.rule_values[pt_idx + sq as usize];
-                let mg_val = (fscore.0 * 100.) as i16;
-                let eg_val = (fscore.1 * 100.) as i16;
-                print!("({mg_val}, {eg_val}), ");
-            }
-            println!();
-        }
-        println!("    ],");
+    /// Iterate mutably over every individual weight, in the same order as `params`.
+    fn params_mut(&mut self) -> impl Iterator<Item = &mut f32> {
+        [&mut self.phase_cutoffs.0, &mut self.phase_cutoffs.1]
+            .into_iter()
+            .chain(self.rule_values.iter_mut().flat_map(|(mg, eg)| [mg, eg]))
     }
-    println!("]) }};");
-}
 
-#[allow(
-    clippy::cast_possible_truncation,
-    clippy::cast_possible_wrap,
-    clippy::similar_names
-)]
-/// Extract a feature vector from a board.
-fn extract(b: &Board) -> BoardFeatures {
-    let bocc = b[Color::Black];
-    let wocc = b[Color::White];
-
-    let mut piece_counts = [0.0; Piece::NUM - 1];
-    let mut rules = Vec::new();
-    // Indices 0..8: non-king piece values
-    for pt in Piece::NON_KING {
-        let n_white = (b[pt] & wocc).len() as i8;
-        let n_black = (b[pt] & bocc).len() as i8;
-        piece_counts[pt as usize] = f32::from(n_white + n_black);
-        let net = n_white - n_black;
-        if net != 0 {
-            rules.push((pt as usize, f32::from(net)));
+    /// Push the phase cutoffs apart, if needed, so that the midgame cutoff is at least
+    /// `MIN_PHASE_GAP` above the endgame cutoff.
+    /// Both cutoffs move by the same amount, keeping their midpoint.
+    fn separate_phase_cutoffs(&mut self) {
+        let (mg, eg) = &mut self.phase_cutoffs;
+        if *mg - *eg < MIN_PHASE_GAP {
+            let mid = mg.midpoint(*eg);
+            *mg = mid + MIN_PHASE_GAP / 2.;
+            *eg = mid - MIN_PHASE_GAP / 2.;
         }
     }
-    let offset = 5;
-
-    // Get piece-square quantities
-    for pt in Piece::ALL {
-        for sq in b[pt] {
-            let alt_sq = sq.opposite();
-            let increment = match (wocc.contains(sq), bocc.contains(alt_sq)) {
-                (true, false) => 1.,
-                (false, true) => -1.,
-                _ => continue, // sparsity requires no entry
-            };
-            let idx = offset + 64 * pt as usize;
-            rules.push((idx, increment));
-        }
-    }
-
-    BoardFeatures {
-        piece_counts,
-        rules,
-    }
-}
 
-impl Weights {
     /// Construct a new zero weights with the same dimension for rules as `w`.
     fn zero(w: &Weights) -> Weights {
         Weights {
@@ -336,91 +747,80 @@ impl Weights {
             rule_values: vec![(0.0, 0.0); w.rule_values.len()],
         }
     }
-    /// Get the evaluation of a board with a given set of features.
-    fn evaluate(&self, x: &BoardFeatures) -> f32 {
-        let midgame_material = self
-            .rule_values
-            .iter()
-            .zip(&x.piece_counts)
-            .map(|(&(value, _), &count)| value * count)
+    /// Compute the blending inputs for a board with a given set of features: its phase, the
+    /// amount of midgame non-pawn material on it, and its midgame and endgame evaluations.
+    /// The phase is clamped to lie between 0 and 1, just as it is in the engine.
+    fn blend_inputs(&self, x: &EvalTrace) -> (f32, f32, (f32, f32)) {
+        let midgame_material = Piece::NON_PAWNS
+            .into_iter()
+            .map(|pt| {
+                self.rule_values[EvalWeights::material_feature(pt)].0
+                    * f32::from(x.piece_counts[pt as usize])
+            })
             .sum::<f32>();
-        let phase = (self.phase_cutoffs.1 - midgame_material)
-            / (self.phase_cutoffs.1 - self.phase_cutoffs.0);
+        let phase = ((self.phase_cutoffs.1 - midgame_material)
+            / (self.phase_cutoffs.1 - self.phase_cutoffs.0))
+            .clamp(0.0, 1.0);
 
         let rule_values = x
-            .rules
+            .features
             .iter()
-            .map(|&(idx, value)| {
+            .map(|&(idx, coeff)| {
+                let coeff = f32::from(coeff);
                 (
-                    self.rule_values[idx].0 * value,
-                    self.rule_values[idx].1 * value,
+                    self.rule_values[idx].0 * coeff,
+                    self.rule_values[idx].1 * coeff,
                 )
             })
             .fold((0.0, 0.0), |(a, b), (c, d)| (a + c, b + d));
 
+        (phase, midgame_material, rule_values)
+    }
+
+    /// Get the evaluation of a board with a given set of features.
+    fn evaluate(&self, x: &EvalTrace) -> f32 {
+        let (phase, _, rule_values) = self.blend_inputs(x);
         phase * rule_values.0 + (1.0 - phase) * rule_values.1
     }
 
     /// Compute the gradient of the evaluation at a point `x`, multiply it by `scale`, and add it
     /// to `add_to`.
-    fn eval_gradient(&self, add_to: &mut GradWeights, scale: f32, x: &BoardFeatures) {
-        let midgame_material = self
-            .rule_values
-            .iter()
-            .zip(&x.piece_counts)
-            .map(|(&(value, _), &count)| value * count)
-            .sum::<f32>();
-        let phase = (self.phase_cutoffs.1 - midgame_material)
-            / (self.phase_cutoffs.1 - self.phase_cutoffs.0);
-
+    fn eval_gradient(&self, add_to: &mut GradWeights, scale: f32, x: &EvalTrace) {
+        let (phase, midgame_material, rule_values) = self.blend_inputs(x);
         let inv_phase = 1.0 - phase;
 
-        let rule_values = x
-            .rules
-            .iter()
-            .map(|&(idx, rule)| {
-                (
-                    self.rule_values[idx].0 * rule,
-                    self.rule_values[idx].1 * rule,
-                )
-            })
-            .fold((0.0, 0.0), |(a, b), (c, d)| (a + c, b + d));
-
-        // Compute gradient with respect to phase cutoffs.
+        // The phase only depends on the weights when it is not clamped.
         if 0.0 < phase && phase < 1.0 {
+            // Compute gradient with respect to phase cutoffs.
             let mult = scale
                 * (rule_values.0 - rule_values.1)
                 * (self.phase_cutoffs.1 - self.phase_cutoffs.0).powi(-2);
             add_to.phase_cutoffs.0 += mult * (self.phase_cutoffs.1 - midgame_material);
             add_to.phase_cutoffs.1 += mult * (midgame_material - self.phase_cutoffs.0);
-        }
 
-        // Compute gradient with respect to material weights
+            // Compute gradient with respect to the midgame material values of non-pawn pieces,
+            // which determine the phase.
+            let phase_bonus = scale * (rule_values.0 - rule_values.1)
+                / (self.phase_cutoffs.0 - self.phase_cutoffs.1);
+            for pt in Piece::NON_PAWNS {
+                add_to.rule_values[EvalWeights::material_feature(pt)].0 +=
+                    phase_bonus * f32::from(x.piece_counts[pt as usize]);
+            }
+        }
 
-        // extra increase to partial with respect to material weight due to phase
-        let phase_bonus = if 0.0 < phase && phase < 1.0 {
-            (rule_values.0 - rule_values.1) / (self.phase_cutoffs.0 - self.phase_cutoffs.1)
-        } else {
-            0.0
-        };
         // Compute gradient with respect to rule weights
-        for &(rule_idx, rule_count) in &x.rules {
-            add_to.rule_values[rule_idx].0 += scale * phase * rule_count;
-            if rule_idx < 5 {
-                add_to.rule_values[rule_idx].0 += scale * phase_bonus;
-            }
-            add_to.rule_values[rule_idx].1 += scale * inv_phase * rule_count;
+        for &(idx, coeff) in &x.features {
+            let coeff = f32::from(coeff);
+            add_to.rule_values[idx].0 += scale * phase * coeff;
+            add_to.rule_values[idx].1 += scale * inv_phase * coeff;
         }
     }
 }
 
-impl SubAssign for Weights {
-    fn sub_assign(&mut self, rhs: Self) {
-        self.phase_cutoffs.0 -= rhs.phase_cutoffs.0;
-        self.phase_cutoffs.1 -= rhs.phase_cutoffs.1;
-        for (a, b) in self.rule_values.iter_mut().zip(rhs.rule_values) {
-            a.0 -= b.0;
-            a.1 -= b.1;
+impl AddAssign for Weights {
+    fn add_assign(&mut self, rhs: Self) {
+        for (a, b) in self.params_mut().zip(rhs.params()) {
+            *a += b;
         }
     }
 }
@@ -435,3 +835,135 @@ impl MulAssign<f32> for Weights {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that every supported data format is parsed to the same position and result.
+    fn data_formats() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        for line in [
+            format!("{fen} \"1-0\";"),
+            format!("{fen} [1-0]"),
+            format!("{fen} [1.0]"),
+            format!("{fen} | 35 | 1-0"),
+        ] {
+            let (b, result, _) = parse_line(&line).unwrap();
+            assert_eq!(b, Board::from_fen(fen).unwrap());
+            assert!((result - 1.).abs() < f32::EPSILON, "{line}");
+        }
+        let (_, _, score) = parse_line(&format!("{fen} | -150 | 0-1")).unwrap();
+        assert_eq!(score, Some(-1.5));
+        let (_, _, score) = parse_line(&format!("{fen} \"0-1\";")).unwrap();
+        assert_eq!(score, None);
+        assert!(parse_line(&format!("{fen} \"0-1\"; ce lots;")).is_err());
+        assert!(parse_line(&format!("{fen} [2-0]")).is_err());
+        assert!(parse_line(&format!("{fen} | 35")).is_err());
+    }
+
+    #[test]
+    /// Test that the evaluations written by `tomato-datagen --eval`, which are from the perspective
+    /// of the player to move, are read as scores from White's perspective.
+    fn datagen_scores() {
+        let white = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let black = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        let (_, result, score) = parse_line(&format!("{white} \"1/2-1/2\"; ce 35;")).unwrap();
+        assert!((result - 0.5).abs() < f32::EPSILON);
+        assert_eq!(score, Some(0.35));
+        let (_, _, score) = parse_line(&format!("{black} \"1-0\"; ce -40;")).unwrap();
+        assert_eq!(score, Some(0.4));
+    }
+
+    #[test]
+    /// Test that command line flags are parsed, and that malformed arguments are rejected.
+    fn parse_settings() {
+        let parse = |s: &str| Settings::parse(s.split_whitespace().map(str::to_string));
+        let settings = parse(
+            "--data a.epd --data b.txt --threads 3 --optimizer momentum --momentum 0.5 --k 1.2",
+        )
+        .unwrap();
+        assert_eq!(settings.data, ["a.epd", "b.txt"]);
+        assert_eq!(settings.threads, 3);
+        assert_eq!(settings.optimizer, OptimizerKind::Momentum(0.5));
+        assert_eq!(settings.k, Some(1.2));
+        assert_eq!(settings.checkpoint, "tuned_weights.txt");
+        assert_eq!(settings.best, "best_weights.txt");
+
+        assert!(parse("--threads 3").is_err());
+        assert!(parse("--data a.epd --threads").is_err());
+        assert!(parse("--data a.epd --optimizer newton").is_err());
+        assert!(parse("--data a.epd --validation 1").is_err());
+    }
+
+    #[test]
+    /// Test that converting the default weights for training and back again is lossless.
+    fn weights_round_trip() {
+        let weights = load_weights(&EvalWeights::DEFAULT);
+        assert_eq!(to_eval_weights(&weights), EvalWeights::DEFAULT);
+    }
+
+    #[test]
+    /// Test that phase cutoffs which have crossed are pushed apart, and that weights the engine
+    /// cannot load are never written.
+    fn phase_cutoffs_stay_apart() {
+        let path = env::temp_dir().join(format!("tomato-tune-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut weights = load_weights(&EvalWeights::DEFAULT);
+        weights.phase_cutoffs = (20., 20.5);
+        assert!(save_weights(&weights, path).is_err());
+        assert!(File::open(path).is_err());
+
+        weights.separate_phase_cutoffs();
+        assert_eq!(weights.phase_cutoffs, (20.75, 19.75));
+        save_weights(&weights, path).unwrap();
+        let loaded = EvalWeights::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.mg_limit, Eval::centipawns(2075));
+        assert_eq!(loaded.eg_limit, Eval::centipawns(1975));
+
+        // cutoffs which are already far enough apart are left alone
+        let mut weights = load_weights(&EvalWeights::DEFAULT);
+        weights.separate_phase_cutoffs();
+        assert_eq!(to_eval_weights(&weights), EvalWeights::DEFAULT);
+    }
+
+    #[test]
+    /// Test that the tuner's evaluation of a position agrees with the engine's, and that its
+    /// gradient agrees with finite differences.
+    fn evaluation_and_gradient() {
+        let weights = load_weights(&EvalWeights::DEFAULT);
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bq1b1r/ppp2kpp/2n5/3np3/2B5/8/PPPP1PPP/RNBQK2R w KQ - 0 7",
+            "8/5k2/6p1/8/5PPP/8/pb3P2/6K1 w - - 0 37",
+        ] {
+            let b = Board::from_fen(fen).unwrap();
+            let x = trace(&b);
+            let expected = x.evaluate(&EvalWeights::DEFAULT).float_val();
+            assert!((weights.evaluate(&x) - expected).abs() < 0.02, "{fen}");
+
+            let mut grad = Weights::zero(&weights);
+            weights.eval_gradient(&mut grad, 1.0, &x);
+            let h = 1e-2;
+            for (i, g) in grad.params().enumerate() {
+                let mut perturbed = weights.clone();
+                *perturbed.params_mut().nth(i).unwrap() += h;
+                let numeric = (perturbed.evaluate(&x) - weights.evaluate(&x)) / h;
+                assert!((numeric - g).abs() < 1e-2, "{fen}: parameter {i}");
+            }
+        }
+    }
+
+    #[test]
+    /// Test that no items are dropped when the number of items is not a multiple of the number of
+    /// threads.
+    fn parallel_sum_uses_all_items() {
+        let items: Vec<u32> = (1..=101).collect();
+        for nthreads in 1..=8 {
+            let sum: u32 = parallel_sum(&items, nthreads, |chunk| chunk.iter().sum::<u32>());
+            assert_eq!(sum, 101 * 102 / 2);
+        }
+    }
+}
@@ -30,11 +30,11 @@
 
 use crate::{
     base::{Board, Color, Move, Piece},
-    engine::evaluate::Score,
+    engine::evaluate::{EvalWeights, Score},
 };
 
 #[must_use]
-/// Get the value of one piece by its type.
+/// Get the compiled-in value of one piece by its type.
 pub const fn value(pt: Piece) -> Score {
     match pt {
         Piece::Knight => Score::centipawns(273, 250),
@@ -49,19 +49,19 @@ pub const fn value(pt: Piece) -> Score {
 #[must_use]
 /// Compute the effect that a move will have on the total material evaluation of the board it will
 /// be played on.
-pub fn delta(b: &Board, m: Move) -> Score {
+pub fn delta(b: &Board, m: Move, weights: &EvalWeights) -> Score {
     // material only ever changes value based on captures and promotions, so this is easy
     let capturee_type = if m.is_en_passant() {
         Some(Piece::Pawn)
     } else {
         b.type_at_square(m.to_square())
     };
-    let mut gain = capturee_type.map_or_else(|| Score::centipawns(0, 0), value);
+    let mut gain = capturee_type.map_or_else(|| Score::centipawns(0, 0), |pt| weights.value(pt));
 
     if let Some(promote_type) = m.promote_type() {
         // we already checked that m is a promotion, so we can trust that it has a promotion
-        gain += value(promote_type);
-        gain -= value(Piece::Pawn);
+        gain += weights.value(promote_type);
+        gain -= weights.value(Piece::Pawn);
     }
 
     // we need not put this delta in perspective, that is somebody else's job
@@ -69,11 +69,19 @@ pub fn delta(b: &Board, m: Move) -> Score {
 }
 
 #[must_use]
-#[allow(clippy::cast_possible_wrap)]
 /// Evaluate a position solely by the amount of material available.
-pub fn evaluate(b: &Board) -> Score {
+pub fn evaluate(b: &Board, weights: &EvalWeights) -> Score {
     let mut score = Score::centipawns(0, 0);
+    visit_features(b, |idx, coeff| score += weights.feature(idx) * coeff);
+
+    score
+}
 
+#[allow(clippy::cast_possible_wrap)]
+/// Call `visit` with the index and coefficient of every material feature of `b` whose coefficient
+/// is nonzero.
+/// The material evaluation of `b` is the sum of each feature's weight times its coefficient.
+pub fn visit_features(b: &Board, mut visit: impl FnMut(usize, i8)) {
     let white_occupancy = b[Color::White];
     let black_occupancy = b[Color::Black];
 
@@ -83,10 +91,10 @@ pub fn evaluate(b: &Board) -> Score {
         let pt_squares = b[pt];
         let white_diff =
             (white_occupancy & pt_squares).len() as i8 - (black_occupancy & pt_squares).len() as i8;
-        score += value(pt) * white_diff;
+        if white_diff != 0 {
+            visit(EvalWeights::material_feature(pt), white_diff);
+        }
     }
-
-    score
 }
 
 #[cfg(test)]
@@ -96,15 +104,15 @@ mod tests {
 
     fn delta_helper(fen: &str) {
         let mut g = Game::from_fen(fen).unwrap();
-        let orig_eval = evaluate(g.board());
+        let orig_eval = evaluate(g.board(), &EvalWeights::DEFAULT);
         for m in g.get_moves::<{ GenMode::All }>() {
-            let delta = delta(g.board(), m);
+            let delta = delta(g.board(), m, &EvalWeights::DEFAULT);
             let new_eval = match g.board().player {
                 Color::White => orig_eval + delta,
                 Color::Black => orig_eval - delta,
             };
             g.make_move(m);
-            assert_eq!(evaluate(g.board()), new_eval);
+            assert_eq!(evaluate(g.board(), &EvalWeights::DEFAULT), new_eval);
             g.undo().unwrap();
         }
     }
@@ -30,6 +30,10 @@
 //! at different phases of the game.
 //! To prevent sharp changes in evaluation as the phase blends, a "midgame" and "endgame" evaluation
 //! is created, and then the final evaluation is a linear combination of those two.
+//!
+//! Each rule is linear in its weights, so the evaluation can also be traced: `trace` lists the
+//! features of a position, from which the evaluation under any set of weights can be recomputed.
+//! This is what the tuner trains on.
 
 use std::{
     fmt::{Display, Formatter},
@@ -40,6 +44,9 @@ use crate::base::{game::Game, Board, Color, Piece};
 
 pub mod material;
 pub mod pst;
+pub mod weights;
+
+pub use weights::EvalWeights;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
 #[repr(C)]
@@ -83,20 +90,64 @@ pub const MG_LIMIT: Eval = Eval::centipawns(2408);
 /// The cutoff for pure endgame material.
 pub const EG_LIMIT: Eval = Eval::centipawns(1348);
 
+/// The evaluation, in centipawns, at which a position is expected to be won half of the time.
+const WDL_CENTER: f64 = 200.;
+
+/// The spread, in centipawns, of the logistic curve mapping evaluations to win rates.
+/// Smaller values make the win rate change more sharply around `WDL_CENTER`.
+const WDL_SCALE: f64 = 80.;
+
 #[must_use]
 #[allow(clippy::module_name_repetitions)]
-/// Heuristically evaluate a leaf position on a game.
-pub fn leaf_evaluate(g: &Game) -> Eval {
+/// Heuristically evaluate a leaf position on a game, using the given weights.
+pub fn leaf_evaluate(g: &Game, weights: &EvalWeights) -> Eval {
     let b = g.board();
-    let mg_npm = {
-        let mut total = Eval::DRAW;
+    let phase = phase_of(b, weights);
+    (material::evaluate(b, weights) + pst::evaluate(b, weights)).blend(phase)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The features of a position, from which its evaluation can be computed under any set of
+/// weights.
+pub struct EvalTrace {
+    /// The index and coefficient of each feature of the position, as numbered by `EvalWeights`.
+    /// The same feature may appear more than once.
+    pub features: Vec<(usize, i8)>,
+    /// The total number of pieces of each type on the board, which determine the phase of the
+    /// game.
+    pub piece_counts: [u8; Piece::NUM],
+}
+
+#[must_use]
+/// Trace the features of a position.
+/// For any weights, `trace(g.board()).evaluate(weights)` is equal to `leaf_evaluate(g, weights)`.
+pub fn trace(b: &Board) -> EvalTrace {
+    let mut features = Vec::new();
+    let mut visit = |idx, coeff| features.push((idx, coeff));
+    material::visit_features(b, &mut visit);
+    pst::visit_features(b, &mut visit);
+
+    EvalTrace {
+        features,
+        piece_counts: Piece::ALL.map(|pt| b[pt].len()),
+    }
+}
+
+impl EvalTrace {
+    #[must_use]
+    /// Compute the evaluation of the traced position under the given weights.
+    pub fn evaluate(&self, weights: &EvalWeights) -> Eval {
+        let mut score = Score::DRAW;
+        for &(idx, coeff) in &self.features {
+            score += weights.feature(idx) * coeff;
+        }
+        let mut mg_npm = Eval::DRAW;
         for pt in Piece::NON_PAWNS {
-            total += material::value(pt).mg * b[pt].len();
+            mg_npm += weights.value(pt).mg * self.piece_counts[pt as usize];
         }
-        total
-    };
-    let phase = calculate_phase(mg_npm);
-    (material::evaluate(b) + pst::evaluate(b)).blend(phase)
+
+        score.blend(calculate_phase(mg_npm, weights))
+    }
 }
 
 #[must_use]
@@ -107,30 +158,30 @@ pub fn leaf_evaluate(g: &Game) -> Eval {
 ///
 /// ```
 /// use tomato::base::Board;
-/// use tomato::engine::evaluate::phase_of;
+/// use tomato::engine::evaluate::{phase_of, EvalWeights};
 ///
-/// assert!(phase_of(&Board::new()).eq(&1.0));
+/// assert!(phase_of(&Board::new(), &EvalWeights::DEFAULT).eq(&1.0));
 /// ```
-pub fn phase_of(b: &Board) -> f32 {
+pub fn phase_of(b: &Board, weights: &EvalWeights) -> f32 {
     // amount of non-pawn material in the board, under midgame values
     let mg_npm = {
         let mut total = Eval::DRAW;
         for pt in Piece::NON_PAWNS {
-            total += material::value(pt).mg * b[pt].len();
+            total += weights.value(pt).mg * b[pt].len();
         }
         total
     };
 
-    calculate_phase(mg_npm)
+    calculate_phase(mg_npm, weights)
 }
 #[must_use]
 /// Get a blending float describing the current phase of the game.
 /// Will range from 0 (full endgame) to 1 (full midgame).
 /// `mg_npm` is the amount of midgame non-pawn material on the board.
-pub fn calculate_phase(mg_npm: Eval) -> f32 {
-    let bounded_npm = mg_npm.clamp(EG_LIMIT, MG_LIMIT);
+pub fn calculate_phase(mg_npm: Eval, weights: &EvalWeights) -> f32 {
+    let bounded_npm = mg_npm.clamp(weights.eg_limit, weights.mg_limit);
 
-    (EG_LIMIT - bounded_npm).float_val() / (EG_LIMIT - MG_LIMIT).float_val()
+    (weights.eg_limit - bounded_npm).float_val() / (weights.eg_limit - weights.mg_limit).float_val()
 }
 
 impl Eval {
@@ -311,6 +362,41 @@ impl Eval {
         f32::from(self.0) / 100.
     }
 
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    /// Estimate the chances that the player for whom this evaluation was made will win, draw, or
+    /// lose, in that order.
+    /// Each chance is given in thousandths, and the three always sum to 1000.
+    ///
+    /// The win and loss rates are modeled as logistic curves of the evaluation, centered
+    /// `WDL_CENTER` centipawns away from a draw on either side.
+    /// Mates are certain wins or losses.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tomato::engine::evaluate::Eval;
+    /// let (win, draw, loss) = Eval::centipawns(150).wdl();
+    /// assert!(loss < win);
+    /// assert_eq!(win + draw + loss, 1000);
+    /// assert_eq!(Eval::mate_in(3).wdl(), (1000, 0, 0));
+    /// ```
+    pub fn wdl(self) -> (u16, u16, u16) {
+        if self.is_mate() {
+            return if self.0 > 0 {
+                (1000, 0, 0)
+            } else {
+                (0, 0, 1000)
+            };
+        }
+        let cp = f64::from(self.0);
+        let win = (1000. / (1. + ((WDL_CENTER - cp) / WDL_SCALE).exp())).round() as u16;
+        let loss = (1000. / (1. + ((WDL_CENTER + cp) / WDL_SCALE).exp())).round() as u16;
+        // rounding could push the sum over 1000 by a hair
+        let draw = 1000u16.saturating_sub(win + loss);
+        (win, draw, 1000 - win - draw)
+    }
+
     #[inline(always)]
     #[must_use]
     /// Put this evaluation into the perspective of the given player.
@@ -510,12 +596,16 @@ impl Mul<u8> for Score {
 mod tests {
 
     use super::*;
+    use crate::base::movegen::GenMode;
 
     #[test]
     #[allow(clippy::float_cmp)]
     fn certainly_endgame() {
         assert_eq!(
-            phase_of(&Board::from_fen("8/5k2/6p1/8/5PPP/8/pb3P2/6K1 w - - 0 37").unwrap()),
+            phase_of(
+                &Board::from_fen("8/5k2/6p1/8/5PPP/8/pb3P2/6K1 w - - 0 37").unwrap(),
+                &EvalWeights::DEFAULT
+            ),
             0.0
         );
     }
@@ -523,7 +613,41 @@ mod tests {
     #[test]
     #[allow(clippy::float_cmp)]
     fn certainly_midgame() {
-        assert_eq!(phase_of(&Board::default()), 1.0);
+        assert_eq!(phase_of(&Board::default(), &EvalWeights::DEFAULT), 1.0);
+    }
+
+    #[test]
+    /// Test that evaluating a trace gives the same result as evaluating the position directly, for
+    /// random positions under random weights.
+    fn trace_matches_evaluation() {
+        let rng = fastrand::Rng::with_seed(0x7e57);
+        let mut weights = EvalWeights::DEFAULT;
+        for idx in 0..EvalWeights::NUM_FEATURES {
+            *weights.feature_mut(idx) += Score::centipawns(rng.i16(-50..=50), rng.i16(-50..=50));
+        }
+        for _ in 0..50 {
+            let mut g = Game::new();
+            for _ in 0..rng.usize(0..120) {
+                let moves = g.get_moves::<{ GenMode::All }>();
+                if moves.is_empty() {
+                    break;
+                }
+                g.make_move(moves[rng.usize(..moves.len())]);
+            }
+            let tr = trace(g.board());
+            assert_eq!(
+                tr.evaluate(&weights),
+                leaf_evaluate(&g, &weights),
+                "{}",
+                g.board()
+            );
+            assert_eq!(
+                tr.evaluate(&EvalWeights::DEFAULT),
+                leaf_evaluate(&g, &EvalWeights::DEFAULT),
+                "{}",
+                g.board()
+            );
+        }
     }
 
     #[test]
@@ -537,4 +661,22 @@ mod tests {
         assert_eq!(s1 * -2i8, Score::centipawns(578, 0));
         assert_eq!(s2 * -2i8, Score::centipawns(578, 400));
     }
+
+    #[test]
+    /// Test that win/draw/loss estimates are symmetric, sum to 1000, and improve with the
+    /// evaluation.
+    fn wdl_model() {
+        let mut prev_win = 0;
+        for cp in (-3000..=3000).step_by(25) {
+            let (win, draw, loss) = Eval::centipawns(cp).wdl();
+            assert_eq!(win + draw + loss, 1000);
+            assert_eq!(Eval::centipawns(-cp).wdl(), (loss, draw, win));
+            assert!(prev_win <= win);
+            prev_win = win;
+        }
+        let (win, draw, loss) = Eval::DRAW.wdl();
+        assert_eq!(win, loss);
+        assert!(win < draw);
+        assert_eq!((-Eval::mate_in(2)).wdl(), (0, 0, 1000));
+    }
 }
@@ -28,13 +28,13 @@ use std::intrinsics::transmute;
 
 use crate::base::{Board, Color, Move, Piece, Square};
 
-use crate::engine::evaluate::Score;
+use crate::engine::evaluate::{EvalWeights, Score};
 
 /// A lookup table for piece values.
 /// The outer index is the type of the piece (in order of Pawn, Knight, Bishop, Rook, Queen, and
 /// King) and the inner index is the square of the piece (from White's point of view), starting with
 /// A1 as the first index, then continuing on to B1, C1, and so on until H8 as index 63.
-type Pst = [[Score; 64]; Piece::NUM];
+pub type Pst = [[Score; 64]; Piece::NUM];
 
 #[must_use]
 /// Evaluate a board based on its PST value.
@@ -42,22 +42,29 @@ type Pst = [[Score; 64]; Piece::NUM];
 /// are making moves.
 /// The first value in the return type is the midgame difference, and the second is the endgame
 /// difference.
-pub fn evaluate(board: &Board) -> Score {
+pub fn evaluate(board: &Board, weights: &EvalWeights) -> Score {
     let mut score = Score::DRAW;
+    visit_features(board, |idx, coeff| score += weights.feature(idx) * coeff);
 
+    score
+}
+
+/// Call `visit` with the index and coefficient of every piece-square feature of `board`.
+/// The PST evaluation of `board` is the sum of each feature's weight times its coefficient.
+/// A feature may be visited more than once, if White and Black both have a piece on the same
+/// relative square.
+pub fn visit_features(board: &Board, mut visit: impl FnMut(usize, i8)) {
     for pt in Piece::ALL {
         for sq in board[pt] & board[Color::White] {
-            score += PST[pt as usize][sq as usize];
+            visit(EvalWeights::pst_feature(pt, sq), 1);
         }
         for sq in board[pt] & board[Color::Black] {
             // Invert the square that Black is on, since positional values are flipped (as pawns
             // move the other way, etc)
             let alt_sq = sq.opposite();
-            score -= PST[pt as usize][alt_sq as usize];
+            visit(EvalWeights::pst_feature(pt, alt_sq), -1);
         }
     }
-
-    score
 }
 
 #[must_use]
@@ -69,7 +76,8 @@ pub fn evaluate(board: &Board) -> Score {
 /// # Panics
 ///
 /// This function will panic if the given move is invalid.
-pub fn delta(board: &Board, m: Move) -> Score {
+pub fn delta(board: &Board, m: Move, weights: &EvalWeights) -> Score {
+    let pst = &weights.pst;
     let from_sq = m.from_square();
     let to_sq = m.to_square();
     let mover_type = board.type_at_square(m.from_square()).unwrap();
@@ -86,18 +94,18 @@ pub fn delta(board: &Board, m: Move) -> Score {
     let (from_idx, to_idx) = (from_alt as usize, to_alt as usize);
 
     // you always lose the value of the square you moved from
-    let mut delta = PST[end_idx][to_idx] - PST[mover_idx][from_idx];
+    let mut delta = pst[end_idx][to_idx] - pst[mover_idx][from_idx];
 
     if board[!board.player].contains(m.to_square()) {
         // conventional capture
         let to_opposite_idx = to_alt.opposite() as usize;
         let capturee_idx = board.type_at_square(to_sq).unwrap() as usize;
-        delta += PST[capturee_idx][to_opposite_idx];
+        delta += pst[capturee_idx][to_opposite_idx];
     }
 
     if m.is_en_passant() {
         let to_opposite_idx = (to_alt - Color::White.pawn_direction()).opposite() as usize;
-        delta += PST[Piece::Pawn as usize][to_opposite_idx];
+        delta += pst[Piece::Pawn as usize][to_opposite_idx];
     }
 
     if m.is_castle() {
@@ -108,14 +116,14 @@ pub fn delta(board: &Board, m: Move) -> Score {
             (Square::H1 as usize, Square::F1 as usize)
         };
 
-        delta += PST[Piece::Rook as usize][rook_to_idx] - PST[Piece::Rook as usize][rook_from_idx];
+        delta += pst[Piece::Rook as usize][rook_to_idx] - pst[Piece::Rook as usize][rook_from_idx];
     }
 
     delta
 }
 
 #[rustfmt::skip] // rustfmt likes to throw a million newlines in this
-/// The main piece-square table. 
+/// The compiled-in piece-square table. 
 /// Evaluations are paired together as (midgame, endgame) to improve cache-friendliness. 
 /// The indexing order of this table has its primary index as pieces, the secondary index as 
 /// squares, and the innermost index as 0 for midgame and 1 for endgame.
@@ -190,15 +198,15 @@ mod tests {
 
     fn delta_helper(fen: &str) {
         let mut g = Game::from_fen(fen).unwrap();
-        let orig_eval = evaluate(g.board());
+        let orig_eval = evaluate(g.board(), &EvalWeights::DEFAULT);
         for m in g.get_moves::<{ GenMode::All }>() {
             let new_eval = match g.board().player {
-                Color::White => orig_eval + delta(g.board(), m),
-                Color::Black => orig_eval - delta(g.board(), m),
+                Color::White => orig_eval + delta(g.board(), m, &EvalWeights::DEFAULT),
+                Color::Black => orig_eval - delta(g.board(), m, &EvalWeights::DEFAULT),
             };
             g.make_move(m);
             // println!("{g}");
-            assert_eq!(new_eval, evaluate(g.board()));
+            assert_eq!(new_eval, evaluate(g.board(), &EvalWeights::DEFAULT));
             g.undo().unwrap();
         }
     }
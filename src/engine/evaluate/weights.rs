@@ -0,0 +1,347 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Weights for the evaluation which can be loaded at runtime.
+//!
+//! The compiled-in weights (`MG_LIMIT`, `EG_LIMIT`, `material::value`, and `pst::PST`) are the
+//! defaults, but a different set of weights can be loaded from a file, such as one written by the
+//! tuner.
+//!
+//! # File format
+//!
+//! A weights file is plain text, with one weight per line.
+//! Blank lines and anything after a `#` are ignored.
+//! Every value is an integer number of centipawns, and scores are given as a midgame value
+//! followed by an endgame value.
+//! The lines may be in any order, and any weight which is not given keeps its default value.
+//! The cutoff for pure midgame material must be greater than the cutoff for pure endgame material.
+//!
+//! ```text
+//! # the cutoffs for pure midgame and pure endgame non-pawn material
+//! mg_limit 2408
+//! eg_limit 1348
+//! # material <piece> <mg> <eg>
+//! material N 273 250
+//! # pst <piece> <square> <mg> <eg>, where the square is from White's point of view
+//! pst N a1 -11 -14
+//! ```
+//!
+//! Pieces are named by their uppercase FEN codes.
+//!
+//! # Features
+//!
+//! Every weight other than the phase cutoffs is the weight of one *feature* of a position, and
+//! the features are numbered so that the weights can be treated as a flat vector.
+//! The material value of each piece comes first, followed by the piece-square table, in the same
+//! order as `pst::PST`.
+
+use std::{
+    fmt::{Display, Formatter},
+    fs,
+    str::FromStr,
+};
+
+use crate::base::{Piece, Square};
+
+use super::{
+    material,
+    pst::{Pst, PST},
+    Eval, Score, EG_LIMIT, MG_LIMIT,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A full set of weights for the evaluation.
+pub struct EvalWeights {
+    /// The cutoff for pure midgame material.
+    pub mg_limit: Eval,
+    /// The cutoff for pure endgame material.
+    pub eg_limit: Eval,
+    /// The value of each piece, indexed by its type.
+    pub material: [Score; Piece::NUM],
+    /// The piece-square table, in the same layout as `pst::PST`.
+    pub pst: Pst,
+}
+
+impl EvalWeights {
+    /// The number of features which are weighted.
+    pub const NUM_FEATURES: usize = Piece::NUM + 64 * Piece::NUM;
+
+    /// The compiled-in weights.
+    pub const DEFAULT: EvalWeights = EvalWeights {
+        mg_limit: MG_LIMIT,
+        eg_limit: EG_LIMIT,
+        material: [
+            material::value(Piece::Knight),
+            material::value(Piece::Bishop),
+            material::value(Piece::Rook),
+            material::value(Piece::Queen),
+            material::value(Piece::Pawn),
+            material::value(Piece::King),
+        ],
+        pst: PST,
+    };
+
+    #[must_use]
+    #[inline(always)]
+    /// Get the value of one piece by its type.
+    pub const fn value(&self, pt: Piece) -> Score {
+        self.material[pt as usize]
+    }
+
+    #[must_use]
+    #[inline(always)]
+    /// Get the piece-square value of a piece of type `pt` on `sq`, where `sq` is from the point of
+    /// view of the piece's owner.
+    pub const fn square_value(&self, pt: Piece, sq: Square) -> Score {
+        self.pst[pt as usize][sq as usize]
+    }
+
+    #[must_use]
+    #[inline(always)]
+    /// Get the index of the feature for the material value of a piece of type `pt`.
+    pub const fn material_feature(pt: Piece) -> usize {
+        pt as usize
+    }
+
+    #[must_use]
+    #[inline(always)]
+    /// Get the index of the feature for a piece of type `pt` on `sq`, where `sq` is from the point
+    /// of view of the piece's owner.
+    pub const fn pst_feature(pt: Piece, sq: Square) -> usize {
+        Piece::NUM + 64 * pt as usize + sq as usize
+    }
+
+    #[must_use]
+    #[inline(always)]
+    /// Get the weight of a feature by its index.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `idx` is not less than `EvalWeights::NUM_FEATURES`.
+    pub fn feature(&self, idx: usize) -> Score {
+        match idx.checked_sub(Piece::NUM) {
+            None => self.material[idx],
+            Some(pst_idx) => self.pst[pst_idx / 64][pst_idx % 64],
+        }
+    }
+
+    #[inline(always)]
+    /// Get a mutable reference to the weight of a feature by its index.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `idx` is not less than `EvalWeights::NUM_FEATURES`.
+    pub fn feature_mut(&mut self, idx: usize) -> &mut Score {
+        match idx.checked_sub(Piece::NUM) {
+            None => &mut self.material[idx],
+            Some(pst_idx) => &mut self.pst[pst_idx / 64][pst_idx % 64],
+        }
+    }
+
+    /// Load a set of weights from the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the file cannot be read or is malformed.
+    pub fn load(path: &str) -> Result<EvalWeights, String> {
+        fs::read_to_string(path)
+            .map_err(|e| format!("could not read weights file {path}: {e}"))?
+            .parse()
+            .map_err(|e| format!("invalid weights file {path}: {e}"))
+    }
+}
+
+impl Default for EvalWeights {
+    fn default() -> EvalWeights {
+        EvalWeights::DEFAULT
+    }
+}
+
+impl FromStr for EvalWeights {
+    type Err = String;
+
+    /// Parse a set of weights in the weights file format.
+    /// On an error in a line, the message is prefixed with the line number where the error was
+    /// found.
+    fn from_str(s: &str) -> Result<EvalWeights, String> {
+        let mut weights = EvalWeights::DEFAULT;
+        for (i, line) in s.lines().enumerate() {
+            parse_line(&mut weights, line).map_err(|e| format!("{}: {e}", i + 1))?;
+        }
+        if weights.mg_limit <= weights.eg_limit {
+            // the phase of a position would be undefined
+            return Err(format!(
+                "mg_limit ({}) must be greater than eg_limit ({})",
+                weights.mg_limit.centipawn_val(),
+                weights.eg_limit.centipawn_val()
+            ));
+        }
+
+        Ok(weights)
+    }
+}
+
+/// Parse one line of a weights file, and write the weight it describes into `weights`.
+///
+/// # Errors
+///
+/// This function will return an `Err` if the line is malformed.
+fn parse_line(weights: &mut EvalWeights, line: &str) -> Result<(), String> {
+    let content = line.split_once('#').map_or(line, |(content, _)| content);
+    let mut tokens = content.split_whitespace();
+    let Some(key) = tokens.next() else {
+        return Ok(());
+    };
+    let mut next = || {
+        tokens
+            .next()
+            .ok_or_else(|| format!("too few fields for `{key}`"))
+    };
+    let cp = |s: &str| {
+        s.parse::<i16>()
+            .map_err(|_| format!("invalid centipawn value `{s}`"))
+    };
+    let piece = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next().and_then(Piece::from_code), chars.next()) {
+            (Some(pt), None) => Ok(pt),
+            _ => Err(format!("invalid piece `{s}`")),
+        }
+    };
+
+    match key {
+        "mg_limit" => weights.mg_limit = Eval::centipawns(cp(next()?)?),
+        "eg_limit" => weights.eg_limit = Eval::centipawns(cp(next()?)?),
+        "material" => {
+            let pt = piece(next()?)?;
+            weights.material[pt as usize] = Score::centipawns(cp(next()?)?, cp(next()?)?);
+        }
+        "pst" => {
+            let pt = piece(next()?)?;
+            let sq = Square::from_algebraic(next()?)?;
+            weights.pst[pt as usize][sq as usize] = Score::centipawns(cp(next()?)?, cp(next()?)?);
+        }
+        _ => return Err(format!("unknown weight `{key}`")),
+    }
+    if let Some(extra) = tokens.next() {
+        return Err(format!("unexpected field `{extra}`"));
+    }
+
+    Ok(())
+}
+
+impl Display for EvalWeights {
+    /// Write these weights in the weights file format.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "mg_limit {}", self.mg_limit.centipawn_val())?;
+        writeln!(f, "eg_limit {}", self.eg_limit.centipawn_val())?;
+        for pt in Piece::NON_KING {
+            let score = self.value(pt);
+            writeln!(
+                f,
+                "material {pt} {} {}",
+                score.mg.centipawn_val(),
+                score.eg.centipawn_val()
+            )?;
+        }
+        for pt in Piece::ALL {
+            for rank in 0..8 {
+                for file in 0..8 {
+                    let sq = Square::new(rank, file).unwrap();
+                    let score = self.square_value(pt, sq);
+                    writeln!(
+                        f,
+                        "pst {pt} {sq} {} {}",
+                        score.mg.centipawn_val(),
+                        score.eg.centipawn_val()
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that the exported default weights reload exactly.
+    fn default_round_trip() {
+        let text = EvalWeights::DEFAULT.to_string();
+        assert_eq!(text.parse::<EvalWeights>(), Ok(EvalWeights::DEFAULT));
+    }
+
+    #[test]
+    /// Test that every feature index names a distinct weight, and that the feature accessors agree
+    /// with the named ones.
+    fn feature_indices() {
+        let mut weights = EvalWeights::DEFAULT;
+        for idx in 0..EvalWeights::NUM_FEATURES {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            let score = Score::centipawns(idx as i16, -(idx as i16));
+            *weights.feature_mut(idx) = score;
+            assert_eq!(weights.feature(idx), score);
+        }
+        for pt in Piece::ALL {
+            let idx = EvalWeights::material_feature(pt);
+            assert_eq!(weights.value(pt), weights.feature(idx));
+            for sq in [Square::A1, Square::E4, Square::H8] {
+                let idx = EvalWeights::pst_feature(pt, sq);
+                assert_eq!(weights.square_value(pt, sq), weights.feature(idx));
+            }
+        }
+    }
+
+    #[test]
+    /// Test that a partial file only overrides the weights it names.
+    fn partial_override() {
+        let weights: EvalWeights = "# a comment\n\nmaterial N 300 280 # knights are good\n\
+            pst Q d4 5 -5\neg_limit 1200"
+            .parse()
+            .unwrap();
+        let mut expected = EvalWeights::DEFAULT;
+        expected.material[Piece::Knight as usize] = Score::centipawns(300, 280);
+        expected.pst[Piece::Queen as usize][Square::D4 as usize] = Score::centipawns(5, -5);
+        expected.eg_limit = Eval::centipawns(1200);
+        assert_eq!(weights, expected);
+    }
+
+    #[test]
+    /// Test that malformed lines are rejected, and that the error names the line.
+    fn malformed() {
+        for text in [
+            "material X 1 2",
+            "material N 1",
+            "material N 1 2 3",
+            "pst N i9 1 2",
+            "mg_limit lots",
+            "bishop_pair 30 50",
+            "eg_limit 3000",
+            "mg_limit 1000\neg_limit 1000",
+        ] {
+            assert!(text.parse::<EvalWeights>().is_err(), "{text}");
+        }
+        assert!("mg_limit 1\n\nfoo"
+            .parse::<EvalWeights>()
+            .unwrap_err()
+            .starts_with("3:"));
+    }
+}
@@ -84,9 +84,9 @@ impl SearchLimit {
             .search_duration
             .lock()
             .map_err(|_| SearchError::Poison)?;
-        if let Some(dur) = *opt_duration {
-            *self.end_time.write().map_err(|_| SearchError::Poison)? = Some(Instant::now() + dur);
-        };
+        // clear out any deadline left over from a previous search
+        *self.end_time.write().map_err(|_| SearchError::Poison)? =
+            opt_duration.map(|dur| Instant::now() + dur);
         Ok(())
     }
 
@@ -135,6 +135,19 @@ impl SearchLimit {
         Ok(())
     }
 
+    /// Get the time elapsed since the search was started.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a lock was poisoned.
+    pub fn elapsed(&self) -> Result<Duration, SearchError> {
+        Ok(self
+            .start_time
+            .lock()
+            .map_err(|_| SearchError::Poison)?
+            .elapsed())
+    }
+
     #[inline(always)]
     /// Get the cumulative number of nodes searched.
     pub fn num_nodes(&self) -> u64 {
@@ -22,12 +22,15 @@
 //! Included below are tools for evaluating positions, searching trees, storing
 //! data, configuring engines, and more.
 
-use search::SearchError;
+pub use search::SearchError;
 
 pub mod evaluate;
 pub mod limit;
+pub mod params;
 mod pick;
 mod search;
+pub mod selfplay;
+pub mod skill;
 pub mod thread;
 pub mod time;
 pub mod transposition;
@@ -0,0 +1,245 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! The registry of tunable search parameters.
+//!
+//! Every numeric constant of the search which is worth tuning is described exactly once, by
+//! `SearchParam`, along with the range of values it may take.
+//! Each parameter is stored in a `SearchConfig`, and can be set through a UCI option of the same
+//! name.
+//! These options are hidden: they are accepted by `setoption`, but are not listed in response to
+//! `uci`, since they are only meant for tuning.
+
+use super::{evaluate::Eval, thread::SearchConfig};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// A tunable parameter of the search.
+pub enum SearchParam {
+    /// The number of moves at each node which are searched before late move reductions begin.
+    LmrEarlyMoves,
+    /// The highest remaining depth at which reverse futility pruning, futility pruning, and
+    /// razoring are attempted.
+    FutilityDepth,
+    /// The margin per ply for reverse futility pruning, in centipawns.
+    ReverseFutilityMargin,
+    /// The margin per ply for futility pruning, in centipawns.
+    FutilityMargin,
+    /// The margin per ply for razoring, in centipawns.
+    RazorMargin,
+    /// The initial width of the aspiration window on the side where the evaluation is not expected
+    /// to move, in centipawns.
+    AspirationNarrow,
+    /// The initial width of the aspiration window on the side where the evaluation is expected to
+    /// move, in centipawns.
+    AspirationWide,
+    /// The factor by which a failing aspiration window grows.
+    AspirationGrowth,
+    /// The age at which transposition table entries are cleared out after a search.
+    TtMaxAge,
+}
+
+impl SearchParam {
+    /// Every tunable parameter.
+    pub const ALL: [SearchParam; 9] = [
+        SearchParam::LmrEarlyMoves,
+        SearchParam::FutilityDepth,
+        SearchParam::ReverseFutilityMargin,
+        SearchParam::FutilityMargin,
+        SearchParam::RazorMargin,
+        SearchParam::AspirationNarrow,
+        SearchParam::AspirationWide,
+        SearchParam::AspirationGrowth,
+        SearchParam::TtMaxAge,
+    ];
+
+    #[must_use]
+    /// Get the name of this parameter, which is also the name of its UCI option.
+    pub const fn name(self) -> &'static str {
+        match self {
+            SearchParam::LmrEarlyMoves => "LmrEarlyMoves",
+            SearchParam::FutilityDepth => "FutilityDepth",
+            SearchParam::ReverseFutilityMargin => "ReverseFutilityMargin",
+            SearchParam::FutilityMargin => "FutilityMargin",
+            SearchParam::RazorMargin => "RazorMargin",
+            SearchParam::AspirationNarrow => "AspirationNarrow",
+            SearchParam::AspirationWide => "AspirationWide",
+            SearchParam::AspirationGrowth => "AspirationGrowth",
+            SearchParam::TtMaxAge => "TtMaxAge",
+        }
+    }
+
+    #[must_use]
+    /// Get the smallest and largest values, in that order, which this parameter may take.
+    pub const fn bounds(self) -> (i64, i64) {
+        match self {
+            SearchParam::LmrEarlyMoves => (1, 16),
+            SearchParam::FutilityDepth => (0, 8),
+            SearchParam::ReverseFutilityMargin
+            | SearchParam::FutilityMargin
+            | SearchParam::RazorMargin => (0, 1000),
+            SearchParam::AspirationNarrow | SearchParam::AspirationWide => (1, 500),
+            SearchParam::AspirationGrowth => (2, 16),
+            // this is the highest age which a transposition table entry can have
+            SearchParam::TtMaxAge => (0, 15),
+        }
+    }
+
+    #[must_use]
+    /// Get the default value of this parameter.
+    pub fn default_value(self) -> i64 {
+        self.get(&SearchConfig::new())
+    }
+
+    #[must_use]
+    /// Find the parameter with the given name.
+    /// Just like UCI options, names are not case sensitive.
+    pub fn from_name(name: &str) -> Option<SearchParam> {
+        SearchParam::ALL
+            .into_iter()
+            .find(|param| param.name().eq_ignore_ascii_case(name))
+    }
+
+    #[must_use]
+    #[allow(clippy::cast_possible_wrap)]
+    /// Get the value of this parameter in a configuration.
+    pub fn get(self, config: &SearchConfig) -> i64 {
+        match self {
+            SearchParam::LmrEarlyMoves => config.num_early_moves as i64,
+            SearchParam::FutilityDepth => i64::from(config.futility_depth),
+            SearchParam::ReverseFutilityMargin => {
+                i64::from(config.reverse_futility_margin.centipawn_val())
+            }
+            SearchParam::FutilityMargin => i64::from(config.futility_margin.centipawn_val()),
+            SearchParam::RazorMargin => i64::from(config.razor_margin.centipawn_val()),
+            SearchParam::AspirationNarrow => i64::from(config.aspiration_narrow.centipawn_val()),
+            SearchParam::AspirationWide => i64::from(config.aspiration_wide.centipawn_val()),
+            SearchParam::AspirationGrowth => i64::from(config.aspiration_growth),
+            SearchParam::TtMaxAge => i64::from(config.tt_max_age),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    /// Set the value of this parameter in a configuration.
+    /// The value is clamped to the parameter's bounds.
+    pub fn set(self, config: &mut SearchConfig, value: i64) {
+        let (min, max) = self.bounds();
+        // the bounds of every parameter fit in an `i16`, so the casts below are safe
+        let value = value.clamp(min, max);
+        let cp = Eval::centipawns(value as i16);
+        match self {
+            SearchParam::LmrEarlyMoves => config.num_early_moves = value as usize,
+            SearchParam::FutilityDepth => config.futility_depth = value as i8,
+            SearchParam::ReverseFutilityMargin => config.reverse_futility_margin = cp,
+            SearchParam::FutilityMargin => config.futility_margin = cp,
+            SearchParam::RazorMargin => config.razor_margin = cp,
+            SearchParam::AspirationNarrow => config.aspiration_narrow = cp,
+            SearchParam::AspirationWide => config.aspiration_wide = cp,
+            SearchParam::AspirationGrowth => config.aspiration_growth = value as i16,
+            SearchParam::TtMaxAge => config.tt_max_age = value as u8,
+        }
+    }
+
+    /// Set a parameter in a configuration through its UCI option, given the name and value from a
+    /// `setoption` command.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no parameter is named `name`, or if `value` is not
+    /// a valid value for the parameter.
+    pub fn set_option(
+        config: &mut SearchConfig,
+        name: &str,
+        value: Option<&str>,
+    ) -> Result<(), String> {
+        let param =
+            SearchParam::from_name(name).ok_or_else(|| format!("unknown option key `{name}`"))?;
+        param.set(config, param.parse_value(value)?);
+        Ok(())
+    }
+
+    /// Check a value given by the GUI for this parameter.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `value` is missing, is not an integer, or is out of
+    /// bounds.
+    pub fn parse_value(self, value: Option<&str>) -> Result<i64, String> {
+        let name = self.name();
+        let (min, max) = self.bounds();
+        let v = value.ok_or_else(|| format!("no value given for option `{name}`"))?;
+        match v.parse::<i64>() {
+            Ok(x) if (min..=max).contains(&x) => Ok(x),
+            _ => Err(format!(
+                "value `{v}` for option `{name}` is not an integer from {min} to {max}"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that every parameter's default is within its bounds, and that setting a parameter
+    /// changes only that parameter.
+    fn get_and_set() {
+        for param in SearchParam::ALL {
+            let (min, max) = param.bounds();
+            assert!((min..=max).contains(&param.default_value()), "{param:?}");
+            assert_eq!(SearchParam::from_name(param.name()), Some(param));
+
+            let mut config = SearchConfig::new();
+            let value = if param.default_value() == max {
+                min
+            } else {
+                max
+            };
+            param.set(&mut config, value);
+            assert_eq!(param.get(&config), value);
+            for other in SearchParam::ALL {
+                if other != param {
+                    assert_eq!(other.get(&config), other.default_value(), "{other:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    /// Test that values are bounds-checked when parsed and clamped when set.
+    fn bounds() {
+        let param = SearchParam::AspirationNarrow;
+        assert_eq!(param.parse_value(Some("20")), Ok(20));
+        assert!(param.parse_value(Some("0")).is_err());
+        assert!(param.parse_value(Some("ten")).is_err());
+        assert!(param.parse_value(None).is_err());
+
+        let mut config = SearchConfig::new();
+        param.set(&mut config, 10_000);
+        assert_eq!(param.get(&config), 500);
+
+        assert_eq!(
+            SearchParam::set_option(&mut config, "aspirationnarrow", Some("20")),
+            Ok(())
+        );
+        assert_eq!(param.get(&config), 20);
+        assert!(SearchParam::set_option(&mut config, "AspirationNarrow", Some("0")).is_err());
+        assert!(SearchParam::set_option(&mut config, "Foo", Some("20")).is_err());
+        assert_eq!(param.get(&config), 20);
+    }
+}
@@ -24,7 +24,7 @@
 
 use crate::base::{Board, Move};
 
-use super::evaluate::{material, Eval, Score};
+use super::evaluate::{Eval, EvalWeights, Score};
 
 /// Create an estimate for how good a move is.
 /// `delta` is the PST difference created by this move.
@@ -33,12 +33,114 @@ use super::evaluate::{material, Eval, Score};
 /// # Panics
 ///
 /// This function may panic if the given move is illegal.
-pub fn candidacy(b: &Board, m: Move, delta: Score, phase: f32) -> Eval {
+pub fn candidacy(b: &Board, m: Move, delta: Score, phase: f32, weights: &EvalWeights) -> Eval {
     let mover_type = b.type_at_square(m.from_square()).unwrap();
 
     // Worst case, we don't keep the piece we captured
     let mut worst_case_delta = delta;
-    let mover_value = material::value(mover_type);
+    let mover_value = weights.value(mover_type);
     worst_case_delta -= mover_value;
     worst_case_delta.blend(phase)
 }
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A history table, which records how often each quiet move (indexed by its from- and to-squares)
+/// has caused a beta cutoff, and how often it failed to cause one when a later quiet move did.
+/// Moves with a high history score are likely to be good in sibling positions as well, while moves
+/// with a negative score are likely to be bad.
+pub struct HistoryTable(Box<[[i16; 64]; 64]>);
+
+impl HistoryTable {
+    /// The highest magnitude that a history score can have.
+    pub const MAX: i16 = 16_384;
+
+    #[must_use]
+    /// Construct a new, empty history table.
+    pub fn new() -> HistoryTable {
+        HistoryTable(Box::new([[0; 64]; 64]))
+    }
+
+    #[must_use]
+    /// Get the history score of a move.
+    pub fn score(&self, m: Move) -> i16 {
+        self.0[m.from_square() as usize][m.to_square() as usize]
+    }
+
+    /// Reward a quiet move for causing a beta cutoff at a node with `depth_to_go` plies remaining.
+    pub fn reward(&mut self, m: Move, depth_to_go: i8) {
+        self.update(m, HistoryTable::bonus(depth_to_go));
+    }
+
+    /// Punish a quiet move for failing to cause a beta cutoff at a node with `depth_to_go` plies
+    /// remaining, where a later quiet move did cause one.
+    pub fn punish(&mut self, m: Move, depth_to_go: i8) {
+        self.update(m, -HistoryTable::bonus(depth_to_go));
+    }
+
+    /// Get the size of a reward or punishment at a node with `depth_to_go` plies remaining.
+    fn bonus(depth_to_go: i8) -> i32 {
+        let bonus = i32::from(depth_to_go) * i32::from(depth_to_go);
+        bonus.min(i32::from(HistoryTable::MAX))
+    }
+
+    /// Add `bonus` to the history score of a move.
+    /// Scores decay as they approach `HistoryTable::MAX` or its negation, so they never overflow.
+    fn update(&mut self, m: Move, bonus: i32) {
+        let entry = &mut self.0[m.from_square() as usize][m.to_square() as usize];
+        let old = i32::from(*entry);
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            *entry = (old + bonus - old * bonus.abs() / i32::from(HistoryTable::MAX)) as i16;
+        }
+    }
+}
+
+impl Default for HistoryTable {
+    fn default() -> Self {
+        HistoryTable::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::Square;
+
+    #[test]
+    /// Test that rewarding a move raises its history score, without affecting other moves.
+    fn history_reward() {
+        let mut history = HistoryTable::new();
+        let m = Move::normal(Square::G1, Square::F3);
+        history.reward(m, 4);
+        assert_eq!(history.score(m), 16);
+        assert_eq!(history.score(Move::normal(Square::B1, Square::C3)), 0);
+    }
+
+    #[test]
+    /// Test that punishing a move lowers its history score below zero.
+    fn history_punish() {
+        let mut history = HistoryTable::new();
+        let m = Move::normal(Square::G1, Square::F3);
+        history.reward(m, 2);
+        history.punish(m, 3);
+        assert_eq!(history.score(m), -5);
+    }
+
+    #[test]
+    /// Test that history scores saturate instead of overflowing.
+    fn history_saturates() {
+        let mut history = HistoryTable::new();
+        let m = Move::normal(Square::G1, Square::F3);
+        for _ in 0..10_000 {
+            history.reward(m, 99);
+        }
+        assert!(history.score(m) <= HistoryTable::MAX);
+        assert!(history.score(m) > HistoryTable::MAX / 2);
+
+        for _ in 0..10_000 {
+            history.punish(m, 99);
+        }
+        assert!(history.score(m) >= -HistoryTable::MAX);
+        assert!(history.score(m) < -HistoryTable::MAX / 2);
+    }
+}
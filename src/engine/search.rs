@@ -36,21 +36,44 @@ use crate::{
     },
     engine::{
         evaluate::{material, phase_of, pst},
-        pick::candidacy,
+        pick::{candidacy, HistoryTable},
+        uci::{EngineInfo, InfoSink},
     },
 };
 
 use super::{
     evaluate::Eval,
-    transposition::{TTEntry, TTEntryGuard},
+    transposition::{Bound, TTEntry, TTEntryGuard},
 };
 
 use super::{
     evaluate::leaf_evaluate, limit::SearchLimit, thread::SearchConfig, transposition::TTable,
 };
 
+use once_cell::sync::Lazy;
 use std::{cmp::max, sync::PoisonError};
 
+/// The number of rows and columns in the late move reduction table.
+/// Depths and move numbers past the end of the table use its last entries.
+const LMR_TABLE_LEN: usize = 64;
+
+/// The history score which is worth one ply of reduction in late move reductions.
+const LMR_HISTORY_DIVISOR: i16 = HistoryTable::MAX / 2;
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+/// The late move reduction table.
+/// `LMR_TABLE[d][m]` is the number of plies by which to reduce the `m`th move searched at a node
+/// with `d` plies remaining, growing with log(d) * log(m).
+static LMR_TABLE: Lazy<[[i8; LMR_TABLE_LEN]; LMR_TABLE_LEN]> = Lazy::new(|| {
+    let mut table = [[0; LMR_TABLE_LEN]; LMR_TABLE_LEN];
+    for (depth, row) in table.iter_mut().enumerate().skip(1) {
+        for (move_count, reduction) in row.iter_mut().enumerate().skip(1) {
+            *reduction = (0.75 + (depth as f32).ln() * (move_count as f32).ln() / 2.25) as i8;
+        }
+    }
+    table
+});
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[allow(clippy::module_name_repetitions)]
 /// The types of errors which can occur during a search.
@@ -87,25 +110,37 @@ pub type SearchResult = Result<SearchInfo, SearchError>;
 /// * `config`: the configuration of this search.
 /// * `limit`:the search limiter, which will be interiorly mutated by this
 ///     function.
+/// * `info`: the sink to which progress at the root will be reported.
+/// * `history`: the history table, which should be kept between iterations of deepening.
 /// * `alpha`: a lower bound on the evaluation.
 ///     This is primarily intended to be used for aspiration windowing, and in
 ///     most cases will be set to `Eval::MIN`.
 /// * `beta`: is an upper bound on the evaluation.
 ///     This is primarily intended to be used for aspiration windowing, and in
 ///     most cases will be set to `Eval::MAX`.
+///
+/// If the search times out after at least one root move has been fully searched and raised alpha,
+/// the result of the search will contain the best such move, along with its evaluation.
 pub fn search(
     g: Game,
     depth: u8,
     ttable: &TTable,
     config: &SearchConfig,
     limit: &SearchLimit,
+    info: &dyn InfoSink,
+    history: &mut HistoryTable,
     alpha: Eval,
     beta: Eval,
 ) -> SearchResult {
-    let mut searcher = PVSearch::new(g, ttable, config, limit);
+    let mut searcher = PVSearch::new(g, ttable, config, limit, info, history);
     let mut pv = Vec::new();
 
-    let eval = searcher.pvs::<true, true, true>(depth as i8, 0, alpha, beta, &mut pv)?;
+    let eval = match searcher.pvs::<true, true, true>(depth as i8, 0, alpha, beta, &mut pv) {
+        Ok(eval) => eval,
+        // the search was cut short, but the root wrote a line which is better than alpha
+        Err(SearchError::Timeout) if !pv.is_empty() => searcher.root_eval,
+        Err(e) => return Err(e),
+    };
 
     Ok(SearchInfo {
         pv,
@@ -149,7 +184,6 @@ impl SearchInfo {
     }
 }
 
-#[derive(Clone, Debug)]
 /// A structure containing data which is shared across function calls to a principal variation
 /// search.
 struct PVSearch<'a> {
@@ -165,12 +199,19 @@ struct PVSearch<'a> {
     config: &'a SearchConfig,
     /// The limit to this search.
     limit: &'a SearchLimit,
+    /// The sink for reports on the progress of the search.
+    info: &'a dyn InfoSink,
     /// The highest depth to which any line was searched.
     selective_depth: u8,
+    /// The history of which quiet moves have caused beta cutoffs.
+    history: &'a mut HistoryTable,
+    /// The evaluation of the line most recently written at the root.
+    root_eval: Eval,
 }
 
 impl<'a> PVSearch<'a> {
-    /// Construct a new `PVSearch` using a given transposition table, configuration, and limit.
+    /// Construct a new `PVSearch` using a given transposition table, configuration, limit, info
+    /// sink, and history table.
     ///
     /// `is_main` is whether the thread is a main search, responsible for certain synchronization
     /// activities.
@@ -179,6 +220,8 @@ impl<'a> PVSearch<'a> {
         ttable: &'a TTable,
         config: &'a SearchConfig,
         limit: &'a SearchLimit,
+        info: &'a dyn InfoSink,
+        history: &'a mut HistoryTable,
     ) -> PVSearch<'a> {
         PVSearch {
             game,
@@ -187,7 +230,10 @@ impl<'a> PVSearch<'a> {
             nodes_since_limit_update: 0,
             config,
             limit,
+            info,
             selective_depth: 0,
+            history,
+            root_eval: Eval::MIN,
         }
     }
 
@@ -287,19 +333,21 @@ impl<'a> PVSearch<'a> {
             }
             // required so that movepicker only needs to know about current position, and not about
             // history
-            return Ok(Eval::DRAW);
+            return Ok(self.draw_score(depth_so_far));
         }
 
         // Retrieve transposition data and use it to improve our estimate on the position
         let mut tt_move = None;
+        let mut tt_static_eval = Eval::MIN;
         let mut tt_guard = self.ttable.get(self.game.board().hash);
         if let Some(entry) = tt_guard.entry() {
+            tt_static_eval = entry.static_eval;
             let m = entry.best_move;
             if is_legal(m, self.game.board()) {
                 tt_move = Some(m);
                 // check if we can cutoff due to transposition table
                 if entry.depth >= depth_to_go {
-                    let upper_bound = entry.upper_bound.step_back_by(depth_so_far);
+                    let upper_bound = entry.upper_bound().step_back_by(depth_so_far);
                     if upper_bound <= alpha {
                         if PV {
                             line.clear();
@@ -307,7 +355,7 @@ impl<'a> PVSearch<'a> {
                         }
                         return Ok(upper_bound);
                     }
-                    let lower_bound = entry.lower_bound.step_back_by(depth_so_far);
+                    let lower_bound = entry.lower_bound().step_back_by(depth_so_far);
                     if beta <= lower_bound {
                         if PV {
                             line.clear();
@@ -319,14 +367,52 @@ impl<'a> PVSearch<'a> {
             }
         }
 
+        // the static evaluation is meaningless in check, so don't bother computing it
+        let in_check = !self.game.board().checkers.is_empty();
+        let static_eval = if in_check {
+            Eval::MIN
+        } else if tt_static_eval == Eval::MIN {
+            leaf_evaluate(&self.game, &self.config.weights).in_perspective(self.game.board().player)
+        } else {
+            tt_static_eval
+        };
+
+        // Whether this node is shallow and quiet enough for the static evaluation to be trusted
+        // for pruning.
+        let can_futility_prune = !PV
+            && !in_check
+            && depth_to_go <= self.config.futility_depth
+            && !static_eval.is_mate()
+            && !alpha.is_mate()
+            && !beta.is_mate();
+
+        if can_futility_prune {
+            // Reverse futility pruning: our position is so good that even after giving up a margin,
+            // our opponent would never let us get here.
+            if beta <= static_eval - self.config.reverse_futility_margin * depth_to_go {
+                return Ok(static_eval);
+            }
+
+            // Razoring: our position is so bad that only a tactic could save it, so check whether
+            // quiescence search can find one.
+            if static_eval + self.config.razor_margin * depth_to_go <= alpha {
+                let score = self.quiesce::<false>(depth_so_far, alpha, beta, line)?;
+                if score <= alpha {
+                    return Ok(score);
+                }
+            }
+        }
+
         let mut moves_iter = self.game.get_moves::<{ GenMode::All }>();
         let b = self.game.board();
-        let phase = phase_of(b);
+        let weights = &*self.config.weights;
+        let phase = phase_of(b, weights);
         moves_iter.sort_by_cached_key(|&m| {
             if Some(m) == tt_move {
                 Eval::MIN
             } else {
-                -candidacy(b, m, pst::delta(b, m) + material::delta(b, m), phase)
+                let delta = pst::delta(b, m, weights) + material::delta(b, m, weights);
+                -candidacy(b, m, delta, phase, weights)
             }
         });
         let mut best_move = Move::BAD_MOVE;
@@ -339,25 +425,64 @@ impl<'a> PVSearch<'a> {
         let mut overwrote_alpha = false;
         // The principal variation line, following the best move.
         let mut child_line = Vec::new();
+        // The quiet moves which have been searched without causing a beta cutoff.
+        let mut quiets_searched = Vec::new();
         for m in moves_iter {
             move_count += 1;
+            if ROOT && self.config.currmove_delay <= self.limit.elapsed()? {
+                // let the GUI know we're still alive on a long search
+                self.info.info(&[
+                    EngineInfo::CurrMove(m),
+                    EngineInfo::CurrMoveNumber(u8::try_from(move_count).unwrap_or(u8::MAX)),
+                ]);
+            }
+            let is_quiet = !self.game.board().is_move_capture(m) && m.promote_type().is_none();
             self.game.make_move(m);
+            let gives_check = !self.game.board().checkers.is_empty();
+
+            // Futility pruning: this quiet move cannot hope to raise our evaluation up to alpha.
+            // Always search at least one move so that we have a score to return.
+            if can_futility_prune
+                && move_count > 1
+                && is_quiet
+                && !gives_check
+                && static_eval + self.config.futility_margin * depth_to_go <= alpha
+            {
+                let undo_result = self.game.undo();
+                debug_assert!(undo_result.is_ok());
+                continue;
+            }
+
             let mut score = Eval::MIN;
 
             if !PV || move_count > 1 {
                 // For moves which are not the first move searched at a PV node, or for moves which
                 // are not in a PV node, perform a zero-window search of the position.
 
-                // LMR: Start reducing search depth after some moves
-                if (move_count > 3){
-                    score = -self.pvs::<false, false, REDUCE>(
-                        depth_to_go - 2,
-                        depth_so_far + 1,
-                        -alpha - Eval::centipawns(1),
-                        -alpha,
-                        &mut child_line,
-                    )?;
+                // LMR: search late quiet moves to a reduced depth, since good moves are likely to
+                // have been ordered earlier
+                let reduction = if REDUCE
+                    && move_count > self.config.num_early_moves
+                    && !in_check
+                    && is_quiet
+                    && !gives_check
+                {
+                    self.reduction::<PV>(depth_to_go, move_count, m)
                 } else {
+                    0
+                };
+
+                score = -self.pvs::<false, false, REDUCE>(
+                    depth_to_go - 1 - reduction,
+                    depth_so_far + 1,
+                    -alpha - Eval::centipawns(1),
+                    -alpha,
+                    &mut child_line,
+                )?;
+
+                if reduction > 0 && alpha < score {
+                    // the reduced search failed high, so this move may be better than we thought.
+                    // verify with a full-depth search.
                     score = -self.pvs::<false, false, REDUCE>(
                         depth_to_go - 1,
                         depth_so_far + 1,
@@ -392,6 +517,9 @@ impl<'a> PVSearch<'a> {
                     // principal variation
                     if PV {
                         write_line(line, m, &child_line);
+                        if ROOT {
+                            self.root_eval = score;
+                        }
                     }
 
                     if beta <= score {
@@ -399,6 +527,12 @@ impl<'a> PVSearch<'a> {
                         // never have let us play it in the first place.
                         // Therefore, we need not consider the other moves, since we wouldn't be
                         // allowed to play them either.
+                        if is_quiet {
+                            self.history.reward(m, depth_to_go);
+                            for &quiet in &quiets_searched {
+                                self.history.punish(quiet, depth_to_go);
+                            }
+                        }
                         break;
                     }
 
@@ -407,6 +541,10 @@ impl<'a> PVSearch<'a> {
                     alpha = score;
                 }
             }
+
+            if is_quiet {
+                quiets_searched.push(m);
+            }
         }
 
         debug_assert!((move_count == 0) ^ has_moves(self.game.board()));
@@ -416,7 +554,7 @@ impl<'a> PVSearch<'a> {
             line.clear();
             best_score = if self.game.board().checkers.is_empty() {
                 // stalemated
-                Eval::DRAW
+                self.draw_score(depth_so_far)
             } else {
                 // mated
                 lower_bound
@@ -433,6 +571,8 @@ impl<'a> PVSearch<'a> {
             beta,
             best_score,
             best_move,
+            static_eval,
+            PV,
         );
 
         Ok(best_score)
@@ -463,7 +603,7 @@ impl<'a> PVSearch<'a> {
             let score = if mated {
                 -Eval::mate_in(depth_so_far)
             } else {
-                Eval::DRAW
+                self.draw_score(depth_so_far)
             };
 
             if PV {
@@ -475,11 +615,13 @@ impl<'a> PVSearch<'a> {
 
         let player = self.game.board().player;
 
+        let mut tt_static_eval = Eval::MIN;
         let mut tt_guard = self.ttable.get(self.game.board().hash);
         if let Some(entry) = tt_guard.entry() {
+            tt_static_eval = entry.static_eval;
             if entry.depth >= TTEntry::DEPTH_CAPTURES {
                 // this was a deeper search, just use it
-                let upper_bound = entry.upper_bound.step_back_by(depth_so_far);
+                let upper_bound = entry.upper_bound().step_back_by(depth_so_far);
                 if upper_bound <= alpha {
                     if PV {
                         line.clear();
@@ -487,7 +629,7 @@ impl<'a> PVSearch<'a> {
                     }
                     return Ok(upper_bound);
                 }
-                let lower_bound = entry.lower_bound.step_back_by(depth_so_far);
+                let lower_bound = entry.lower_bound().step_back_by(depth_so_far);
                 if beta <= lower_bound {
                     if PV {
                         line.clear();
@@ -498,7 +640,13 @@ impl<'a> PVSearch<'a> {
             }
         }
         // capturing is unforced, so we can stop here if the player to move doesn't want to capture.
-        let mut score = leaf_evaluate(&self.game).in_perspective(player);
+        // reuse the static evaluation from the transposition table if we have it
+        let static_eval = if tt_static_eval == Eval::MIN {
+            leaf_evaluate(&self.game, &self.config.weights).in_perspective(player)
+        } else {
+            tt_static_eval
+        };
+        let mut score = static_eval;
         // println!("{g}: {score}");
 
         // Whether alpha was overwritten by any move at this depth.
@@ -521,6 +669,8 @@ impl<'a> PVSearch<'a> {
                     beta,
                     score,
                     Move::BAD_MOVE,
+                    static_eval,
+                    PV,
                 );
                 // beta cutoff, this line would not be selected because there is a better option
                 // somewhere else
@@ -535,9 +685,11 @@ impl<'a> PVSearch<'a> {
         let mut moves = self.game.get_moves::<{ GenMode::Captures }>();
 
         let b = self.game.board();
-        let phase = phase_of(b);
+        let weights = &*self.config.weights;
+        let phase = phase_of(b, weights);
         moves.sort_by_cached_key(|&m| {
-            -candidacy(b, m, pst::delta(b, m) + material::delta(b, m), phase)
+            let delta = pst::delta(b, m, weights) + material::delta(b, m, weights);
+            -candidacy(b, m, delta, phase, weights)
         });
         let mut child_line = Vec::new();
 
@@ -586,10 +738,54 @@ impl<'a> PVSearch<'a> {
             beta,
             best_score,
             Move::BAD_MOVE,
+            static_eval,
+            PV,
         );
         Ok(best_score)
     }
 
+    /// Compute the number of extra plies by which to reduce the search of a late quiet move `m`,
+    /// which is the `move_count`th move searched at a node with `depth_to_go` plies remaining.
+    /// The reduced search will always have at least one ply remaining.
+    fn reduction<const PV: bool>(&self, depth_to_go: i8, move_count: usize, m: Move) -> i8 {
+        #[allow(clippy::cast_sign_loss)]
+        let depth_idx = (depth_to_go as usize).min(LMR_TABLE_LEN - 1);
+        let mut reduction = LMR_TABLE[depth_idx][move_count.min(LMR_TABLE_LEN - 1)];
+
+        if PV {
+            // PV nodes are more important, so search them more thoroughly
+            reduction -= 1;
+        }
+
+        // reduce moves with a good history less, and moves with a bad history more
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            reduction -= (self.history.score(m) / LMR_HISTORY_DIVISOR) as i8;
+        }
+
+        reduction.clamp(0, max(depth_to_go - 2, 0))
+    }
+
+    #[inline(always)]
+    /// Get the score of a drawn position `depth_so_far` plies from the root, in perspective of the
+    /// player to move.
+    /// Contempt is given from the perspective of the player to move at the root, so the sign of
+    /// the draw score flips on every ply.
+    /// If draws are randomized, the score is nudged by one centipawn in either direction, so that
+    /// the search does not blindly steer into the first repetition it finds.
+    fn draw_score(&self, depth_so_far: u8) -> Eval {
+        let score = if depth_so_far & 1 == 0 {
+            -self.config.contempt
+        } else {
+            self.config.contempt
+        };
+        match (self.config.randomize_draws, self.num_nodes_evaluated & 1) {
+            (false, _) => score,
+            (true, 0) => score - Eval::centipawns(1),
+            (true, _) => score + Eval::centipawns(1),
+        }
+    }
+
     #[inline(always)]
     /// Increment the number of nodes searched, copying over the value into the search limit if it
     /// is too high.
@@ -620,9 +816,12 @@ fn write_line(parent_line: &mut Vec<Move>, m: Move, line: &[Move]) {
     parent_line.extend(line);
 }
 
+#[allow(clippy::too_many_arguments)]
 /// Store data in the transposition table.
 /// `score` is the best score of the position as evaluated, while `alpha` and `beta` are the upper
 /// and lower bounds on the overall position due to alpha-beta pruning in the game.
+/// `static_eval` is the static evaluation of the position (or `Eval::MIN` if unknown), and `is_pv`
+/// is whether the position was searched as a PV node.
 fn ttable_store(
     guard: &mut TTEntryGuard,
     depth_so_far: u8,
@@ -631,17 +830,24 @@ fn ttable_store(
     beta: Eval,
     score: Eval,
     best_move: Move,
+    static_eval: Eval,
+    is_pv: bool,
 ) {
     let true_score = score.step_forward_by(depth_so_far);
-    let upper_bound = if score < beta { true_score } else { Eval::MAX };
-    let lower_bound = if alpha < score { true_score } else { Eval::MIN };
-    guard.save(depth_to_go, best_move, lower_bound, upper_bound);
+    let bound = match (alpha < score, score < beta) {
+        (true, true) => Bound::Exact,
+        (true, false) => Bound::Lower,
+        (false, true) => Bound::Upper,
+        (false, false) => Bound::None,
+    };
+    guard.save(depth_to_go, best_move, true_score, bound, static_eval, is_pv);
 }
 #[cfg(test)]
 pub mod tests {
 
     use super::*;
     use crate::base::{Move, Square};
+    use crate::engine::uci::NullSink;
 
     /// Helper function to search a position at a given depth.
     ///
@@ -660,6 +866,8 @@ pub mod tests {
             &TTable::with_size(1000),
             &config,
             &SearchLimit::default(),
+            &NullSink,
+            &mut HistoryTable::new(),
             Eval::MIN,
             Eval::MAX,
         )
@@ -692,6 +900,118 @@ pub mod tests {
         println!("best move: {} [{}]", info.pv[0], info.eval);
     }
 
+    /// Construct a game from the starting position in which the knights have shuffled back and
+    /// forth, so that Black can claim a threefold repetition by playing Ng8.
+    fn repetition_game() -> Game {
+        let mut g = Game::new();
+        for (from, to) in [
+            (Square::G1, Square::F3),
+            (Square::G8, Square::F6),
+            (Square::F3, Square::G1),
+            (Square::F6, Square::G8),
+            (Square::G1, Square::F3),
+            (Square::G8, Square::F6),
+            (Square::F3, Square::G1),
+        ] {
+            g.make_move(Move::normal(from, to));
+        }
+        g
+    }
+
+    /// Search a game with the given contempt, in centipawns.
+    fn contempt_search(g: &Game, contempt: i16, depth: u8) -> SearchInfo {
+        let config = SearchConfig {
+            depth,
+            contempt: Eval::centipawns(contempt),
+            ..Default::default()
+        };
+        search(
+            g.clone(),
+            depth,
+            &TTable::with_size(1000),
+            &config,
+            &SearchLimit::default(),
+            &NullSink,
+            &mut HistoryTable::new(),
+            Eval::MIN,
+            Eval::MAX,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    /// Test that a player who has contempt for their opponent avoids a repetition, and that a
+    /// player with negative contempt seeks one out.
+    fn contempt_and_repetition() {
+        let g = repetition_game();
+        let repeat = Move::normal(Square::F6, Square::G8);
+
+        let info = contempt_search(&g, -500, 4);
+        assert_eq!(info.pv[0], repeat);
+        assert_eq!(info.eval, Eval::centipawns(500));
+
+        let info = contempt_search(&g, 500, 4);
+        assert_ne!(info.pv[0], repeat);
+        assert!(Eval::centipawns(-500) < info.eval);
+    }
+
+    #[test]
+    /// Test that contempt is counted against the root player at even plies and in their favor at
+    /// odd plies.
+    fn contempt_flips_at_odd_plies() {
+        let config = SearchConfig {
+            contempt: Eval::centipawns(30),
+            ..Default::default()
+        };
+        let ttable = TTable::with_size(0);
+        let limit = SearchLimit::default();
+        let mut history = HistoryTable::new();
+        let searcher = PVSearch::new(
+            Game::new(),
+            &ttable,
+            &config,
+            &limit,
+            &NullSink,
+            &mut history,
+        );
+        for depth_so_far in [0, 2, 10] {
+            assert_eq!(searcher.draw_score(depth_so_far), Eval::centipawns(-30));
+        }
+        for depth_so_far in [1, 3, 11] {
+            assert_eq!(searcher.draw_score(depth_so_far), Eval::centipawns(30));
+        }
+    }
+
+    #[test]
+    /// Test that randomized draw scores stay within a centipawn of the unrandomized score, and
+    /// that they are not always the same.
+    fn randomized_draws() {
+        let config = SearchConfig {
+            contempt: Eval::centipawns(10),
+            randomize_draws: true,
+            ..Default::default()
+        };
+        let ttable = TTable::with_size(0);
+        let limit = SearchLimit::default();
+        let mut history = HistoryTable::new();
+        let mut searcher = PVSearch::new(
+            Game::new(),
+            &ttable,
+            &config,
+            &limit,
+            &NullSink,
+            &mut history,
+        );
+        let mut scores = Vec::new();
+        for num_nodes in 0..4 {
+            searcher.num_nodes_evaluated = num_nodes;
+            let score = searcher.draw_score(1);
+            assert!(Eval::centipawns(9) <= score && score <= Eval::centipawns(11));
+            scores.push(score);
+        }
+        assert!(scores.iter().any(|&s| s != scores[0]));
+    }
+
     #[test]
     /// A test on the evaluation of the game in the fried liver position.
     /// The only winning move for White is Qd3+.
@@ -740,6 +1060,52 @@ pub mod tests {
         );
     }
 
+    #[test]
+    /// Test that futility pruning, reverse futility pruning, and razoring reduce the number of nodes
+    /// needed to search a quiet position, and do not hide a mate.
+    fn futility_prunes_nodes() {
+        let search_nodes = |fen: &str, futility_depth: i8| {
+            let config = SearchConfig {
+                depth: 6,
+                futility_depth,
+                ..Default::default()
+            };
+            search(
+                Game::from_fen(fen).unwrap(),
+                6,
+                &TTable::with_size(16),
+                &config,
+                &SearchLimit::new(),
+                &NullSink,
+                &mut HistoryTable::new(),
+                Eval::MIN,
+                Eval::MAX,
+            )
+            .unwrap()
+        };
+        let quiet = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        assert!(
+            search_nodes(quiet, 3).num_nodes_evaluated < search_nodes(quiet, 0).num_nodes_evaluated
+        );
+
+        let mate = "3k4/R7/8/5K2/3R4/8/8/8 b - - 0 1";
+        assert_eq!(search_nodes(mate, 3).eval, -Eval::mate_in(4));
+    }
+
+    #[test]
+    /// Test that the late move reduction table never reduces the first move, and grows with both
+    /// depth and move number.
+    fn lmr_table_monotone() {
+        for depth in 1..LMR_TABLE_LEN {
+            assert_eq!(LMR_TABLE[depth][1], 0);
+            for move_count in 1..LMR_TABLE_LEN {
+                assert!(LMR_TABLE[depth - 1][move_count] <= LMR_TABLE[depth][move_count]);
+                assert!(LMR_TABLE[depth][move_count - 1] <= LMR_TABLE[depth][move_count]);
+            }
+        }
+        assert!(LMR_TABLE[LMR_TABLE_LEN - 1][LMR_TABLE_LEN - 1] > 0);
+    }
+
     #[test]
     /// Test that the transposition table contains an entry for the root node of the search.
     fn ttable_populated() {
@@ -756,6 +1122,8 @@ pub mod tests {
                 ..Default::default()
             },
             &SearchLimit::new(),
+            &NullSink,
+            &mut HistoryTable::new(),
             Eval::MIN,
             Eval::MAX,
         )
@@ -767,6 +1135,7 @@ pub mod tests {
         // println!("{search_info:?}");
         assert_eq!(entry.depth, i8::try_from(depth).unwrap());
         assert_eq!(entry.best_move, search_info.pv[0]);
-        assert_eq!(entry.lower_bound, entry.upper_bound);
+        assert_eq!(entry.bound(), Bound::Exact);
+        assert!(entry.is_pv());
     }
 }
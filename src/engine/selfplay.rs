@@ -0,0 +1,210 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Self-play games, as played by the training data generator and the search parameter tuner.
+//!
+//! Each game starts from a few uniformly random moves and is then played out with a fixed number
+//! of nodes per move.
+//! To save time, games are adjudicated as soon as their result is clear from the evaluations of
+//! the searches.
+
+use std::sync::Arc;
+
+use fastrand::Rng;
+
+use crate::base::{
+    game::{Game, GameResult},
+    movegen::{get_moves, GenMode},
+    Move,
+};
+
+use super::{
+    evaluate::Eval, thread::MainSearch, transposition::TTable, uci::NullSink, SearchError,
+};
+
+/// A game is adjudicated as a win once the evaluation has been at least this many centipawns in
+/// the same player's favor for `ADJUDICATE_WIN_PLIES` consecutive plies.
+const ADJUDICATE_WIN_CP: i16 = 1000;
+
+/// The number of consecutive plies needed to adjudicate a win.
+const ADJUDICATE_WIN_PLIES: u32 = 6;
+
+/// After this many plies, a game is adjudicated as a draw once the evaluation has been within
+/// `ADJUDICATE_DRAW_CP` of zero for `ADJUDICATE_DRAW_PLIES` consecutive plies.
+const ADJUDICATE_DRAW_START: usize = 80;
+
+/// The largest evaluation, in centipawns, which counts toward draw adjudication.
+const ADJUDICATE_DRAW_CP: i16 = 10;
+
+/// The number of consecutive plies needed to adjudicate a draw.
+const ADJUDICATE_DRAW_PLIES: u32 = 12;
+
+#[must_use]
+/// Construct a searcher for self-play games, which searches `nodes` nodes for every move and has a
+/// transposition table of `hash_mb` megabytes.
+///
+/// # Panics
+///
+/// This function will panic if the searcher's limit is poisoned, which cannot happen to a new
+/// searcher.
+pub fn searcher(nodes: u64, hash_mb: usize) -> MainSearch {
+    let mut searcher = MainSearch::new();
+    searcher.ttable = TTable::with_size(hash_mb);
+    searcher.info_sink = Arc::new(NullSink);
+    // the node cap is what ends each search
+    searcher.config.depth = 99;
+    *searcher.limit.nodes_cap.write().unwrap() = Some(nodes);
+    searcher
+}
+
+/// Search the current position of `game` with the searcher's node limit, returning the best move
+/// and the evaluation from White's perspective.
+/// Afterward, the searcher's transposition table is aged up, just as it would be between moves
+/// of a game over UCI.
+///
+/// # Errors
+///
+/// This function will return an `Err` if the search fails.
+pub fn search(searcher: &mut MainSearch, game: &Game) -> Result<(Move, Eval), SearchError> {
+    searcher.limit.start()?;
+    let info = searcher.evaluate(game)?;
+    searcher.ttable.age_up(searcher.config.tt_max_age);
+    // a search of a position which is not over always finds a move to play
+    let m = *info.pv.first().ok_or(SearchError::Timeout)?;
+    Ok((m, info.eval))
+}
+
+#[must_use]
+/// Choose a random opening, playing `random_plies` uniformly random moves from the starting
+/// position.
+/// Openings which are already over are thrown out and replaced.
+pub fn random_opening(rng: &Rng, random_plies: usize) -> Game {
+    'retry: loop {
+        let mut game = Game::new();
+        for _ in 0..random_plies {
+            let moves = get_moves::<{ GenMode::All }>(game.board());
+            if moves.is_empty() || game.termination().is_some() {
+                continue 'retry;
+            }
+            game.make_move(moves[rng.usize(..moves.len())]);
+        }
+        if game.termination().is_none() {
+            return game;
+        }
+    }
+}
+
+/// Play out `game` until it is over or its result is clear enough to be adjudicated.
+/// Every move is chosen by `play`, which returns the move along with the evaluation of the
+/// position from White's perspective.
+///
+/// # Errors
+///
+/// This function will return the first error returned by `play`, leaving `game` unfinished.
+pub fn play_out<E>(
+    game: &mut Game,
+    mut play: impl FnMut(&Game) -> Result<(Move, Eval), E>,
+) -> Result<(), E> {
+    let mut win_streak = 0;
+    let mut draw_streak = 0;
+    let mut last_sign = 0;
+    while game.termination().is_none() {
+        let (m, eval) = play(game)?;
+        let eval = eval.centipawn_val();
+
+        // adjudicate games whose results are already clear
+        if ADJUDICATE_WIN_CP <= eval.abs() {
+            win_streak = if eval.signum() == last_sign {
+                win_streak + 1
+            } else {
+                1
+            };
+            last_sign = eval.signum();
+        } else {
+            win_streak = 0;
+        }
+        if eval.abs() <= ADJUDICATE_DRAW_CP {
+            draw_streak += 1;
+        } else {
+            draw_streak = 0;
+        }
+
+        game.make_move(m);
+        if ADJUDICATE_WIN_PLIES <= win_streak {
+            game.adjudicate(if 0 < last_sign {
+                GameResult::WhiteWins
+            } else {
+                GameResult::BlackWins
+            });
+        } else if ADJUDICATE_DRAW_START <= game.moves().len()
+            && ADJUDICATE_DRAW_PLIES <= draw_streak
+        {
+            game.adjudicate(GameResult::Draw);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::params::SearchParam;
+
+    #[test]
+    /// Test that a random opening has the requested number of moves, and is determined by its
+    /// seed.
+    fn opening() {
+        let game = random_opening(&Rng::with_seed(5), 8);
+        assert_eq!(game.moves().len(), 8);
+        assert!(game.termination().is_none());
+        assert_eq!(game.board(), random_opening(&Rng::with_seed(5), 8).board());
+    }
+
+    #[test]
+    /// Test that a lopsided game is adjudicated as a win for the stronger side.
+    fn adjudicate_win() {
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/QQQQK3 w - - 0 1").unwrap();
+        let mut searcher = searcher(500, 1);
+        play_out(&mut game, |g| search(&mut searcher, g)).unwrap();
+        assert_eq!(game.result(), Some(GameResult::WhiteWins));
+    }
+
+    #[test]
+    /// Test that the maximum age of transposition table entries matters to a self-play game, so
+    /// that it can be tuned.
+    fn tt_max_age_changes_game() {
+        let opening = random_opening(&Rng::with_seed(1), 8);
+        let play = |max_age| {
+            let mut searcher = searcher(2000, 1);
+            SearchParam::TtMaxAge.set(&mut searcher.config, max_age);
+            let mut game = opening.clone();
+            play_out(&mut game, |g| search(&mut searcher, g)).unwrap();
+            game.moves().to_vec()
+        };
+        assert_ne!(play(0), play(15));
+    }
+
+    #[test]
+    /// Test that an error while choosing a move stops the game.
+    fn error_stops_game() {
+        let mut game = Game::new();
+        assert_eq!(play_out(&mut game, |_| Err("no move")), Err("no move"));
+        assert!(game.moves().is_empty());
+    }
+}
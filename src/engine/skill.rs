@@ -0,0 +1,267 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Deliberate weakening of the engine.
+//!
+//! A full-strength engine is no fun to play against for most people.
+//! A `Skill` describes how much weaker than its best the engine should play: a weakened search
+//! is capped in depth and nodes, and instead of always playing its best move, it picks at random
+//! among its best few moves, preferring those which lose the least.
+//! The lower the skill level, the more evaluation the engine is willing to give away.
+
+use fastrand::Rng;
+
+use crate::base::Move;
+
+use super::evaluate::Eval;
+
+/// The highest skill level, at which the engine plays at full strength.
+pub const MAX_LEVEL: u8 = 20;
+
+/// The rating corresponding to the lowest skill level.
+pub const MIN_ELO: u16 = 800;
+
+/// The rating corresponding to the highest skill level.
+pub const MAX_ELO: u16 = 2400;
+
+/// The largest evaluation, in centipawns, which the engine may give away per skill level below
+/// the maximum.
+const LOSS_PER_LEVEL: i16 = 15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A strength at which the engine plays.
+pub struct Skill {
+    /// The skill level, from 0 to `MAX_LEVEL`.
+    level: u8,
+}
+
+impl Skill {
+    /// The skill of the engine at full strength.
+    pub const FULL: Skill = Skill { level: MAX_LEVEL };
+
+    #[must_use]
+    /// Construct a skill with the given level.
+    /// Levels above `MAX_LEVEL` are treated as full strength.
+    pub fn new(level: u8) -> Skill {
+        Skill {
+            level: level.min(MAX_LEVEL),
+        }
+    }
+
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    /// Construct the skill whose strength is closest to the given rating.
+    /// Ratings from `MIN_ELO` to `MAX_ELO` are mapped linearly onto the skill levels, and
+    /// ratings outside that range are clamped to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tomato::engine::skill::{Skill, MAX_ELO, MIN_ELO};
+    ///
+    /// assert_eq!(Skill::from_elo(MIN_ELO), Skill::new(0));
+    /// assert_eq!(Skill::from_elo(1600), Skill::new(10));
+    /// assert_eq!(Skill::from_elo(MAX_ELO), Skill::FULL);
+    /// ```
+    pub fn from_elo(elo: u16) -> Skill {
+        let above_min = u32::from(elo.clamp(MIN_ELO, MAX_ELO) - MIN_ELO);
+        let range = u32::from(MAX_ELO - MIN_ELO);
+        // round to the nearest level
+        Skill::new(((above_min * u32::from(MAX_LEVEL) + range / 2) / range) as u8)
+    }
+
+    #[must_use]
+    /// Get the level of this skill.
+    pub const fn level(self) -> u8 {
+        self.level
+    }
+
+    #[must_use]
+    /// Determine whether this skill is weaker than full strength.
+    pub const fn is_limited(self) -> bool {
+        self.level < MAX_LEVEL
+    }
+
+    #[must_use]
+    /// Get the deepest search which this skill may perform.
+    pub const fn depth(self) -> u8 {
+        1 + self.level / 2
+    }
+
+    #[must_use]
+    /// Get the largest number of nodes which this skill may search on each move.
+    pub const fn nodes(self) -> u64 {
+        1_000 << (self.level / 2)
+    }
+
+    #[must_use]
+    /// Get the number of best moves from which this skill chooses the move to play.
+    pub const fn multi_pv(self) -> usize {
+        4 + (MAX_LEVEL - self.level) as usize / 4
+    }
+
+    #[must_use]
+    /// Get the largest evaluation which this skill may give away by playing a move other than the
+    /// best one.
+    pub fn max_loss(self) -> Eval {
+        Eval::centipawns(i16::from(MAX_LEVEL - self.level) * LOSS_PER_LEVEL)
+    }
+
+    #[must_use]
+    /// Choose a move to play from a list of candidates and their evaluations, sorted from best to
+    /// worst from the perspective of the player to move.
+    /// Only the best `self.multi_pv()` candidates which lose no more than `self.max_loss()`
+    /// relative to the best are considered, and each of them is chosen with a probability that
+    /// decays exponentially with the evaluation it gives away.
+    ///
+    /// The choice depends only on the candidates and the state of `rng`, so a seeded generator
+    /// gives a reproducible choice.
+    /// At full strength, the best candidate is always chosen.
+    /// Returns `None` if there are no candidates.
+    pub fn pick(self, candidates: &[(Move, Eval)], rng: &Rng) -> Option<(Move, Eval)> {
+        let &(best_move, best_eval) = candidates.first()?;
+        if !self.is_limited() {
+            return Some((best_move, best_eval));
+        }
+        let max_loss = i32::from(self.max_loss().centipawn_val());
+        // compute in `i32` so that differences between mate evaluations cannot overflow
+        let loss =
+            |eval: Eval| i32::from(best_eval.centipawn_val()) - i32::from(eval.centipawn_val());
+        let eligible: Vec<(Move, Eval)> = candidates
+            .iter()
+            .take(self.multi_pv())
+            .copied()
+            .filter(|&(_, eval)| loss(eval) <= max_loss)
+            .collect();
+
+        let temperature = f64::from(max_loss.max(2)) / 2.;
+        let weights: Vec<f64> = eligible
+            .iter()
+            .map(|&(_, eval)| (-f64::from(loss(eval)) / temperature).exp())
+            .collect();
+        let mut remaining = rng.f64() * weights.iter().sum::<f64>();
+        for (&candidate, &weight) in eligible.iter().zip(&weights) {
+            if remaining < weight {
+                return Some(candidate);
+            }
+            remaining -= weight;
+        }
+
+        // rounding error left us past the end of the list
+        eligible.last().copied()
+    }
+}
+
+impl Default for Skill {
+    fn default() -> Skill {
+        Skill::FULL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base::Square;
+
+    use super::*;
+
+    /// Construct a list of candidate moves with the given evaluations, in centipawns.
+    fn candidates(evals: &[i16]) -> Vec<(Move, Eval)> {
+        (0..)
+            .zip(evals)
+            .map(|(file, &cp)| {
+                let from = Square::new(1, file).unwrap();
+                let to = Square::new(2, file).unwrap();
+                (Move::normal(from, to), Eval::centipawns(cp))
+            })
+            .collect()
+    }
+
+    #[test]
+    /// Test that the same seed always results in the same choice.
+    fn pick_is_deterministic() {
+        let cands = candidates(&[50, 40, 20, 0, -30]);
+        for level in 0..MAX_LEVEL {
+            let skill = Skill::new(level);
+            for seed in 0..50 {
+                assert_eq!(
+                    skill.pick(&cands, &Rng::with_seed(seed)),
+                    skill.pick(&cands, &Rng::with_seed(seed))
+                );
+            }
+        }
+    }
+
+    #[test]
+    /// Test that a weakened skill never gives away more than its maximum loss, and that it
+    /// sometimes plays a move other than the best one.
+    fn pick_within_max_loss() {
+        let cands = candidates(&[100, 90, 60, 0, -500, -900]);
+        let skill = Skill::new(10);
+        let rng = Rng::with_seed(2718);
+        let mut n_suboptimal = 0;
+        for _ in 0..1000 {
+            let (_, eval) = skill.pick(&cands, &rng).unwrap();
+            assert!(Eval::centipawns(100) - eval <= skill.max_loss());
+            if eval != Eval::centipawns(100) {
+                n_suboptimal += 1;
+            }
+        }
+        assert!(0 < n_suboptimal);
+    }
+
+    #[test]
+    /// Test that a forced mate is never given away for a normal evaluation.
+    fn pick_keeps_mate() {
+        let cands = vec![
+            (candidates(&[0])[0].0, Eval::mate_in(3)),
+            (candidates(&[0, 0])[1].0, Eval::centipawns(2000)),
+        ];
+        let rng = Rng::with_seed(1);
+        for _ in 0..100 {
+            assert_eq!(Skill::new(0).pick(&cands, &rng), Some(cands[0]));
+        }
+    }
+
+    #[test]
+    /// Test that full strength always plays the best move, and that there is nothing to pick from
+    /// an empty list.
+    fn full_strength() {
+        assert!(!Skill::FULL.is_limited());
+        assert_eq!(Skill::new(200), Skill::FULL);
+        let cands = candidates(&[10, 10, 10]);
+        let rng = Rng::with_seed(99);
+        for _ in 0..100 {
+            assert_eq!(Skill::FULL.pick(&cands, &rng), Some(cands[0]));
+        }
+        assert_eq!(Skill::new(0).pick(&[], &rng), None);
+    }
+
+    #[test]
+    /// Test that ratings map onto skill levels monotonically and are clamped to the supported
+    /// range.
+    fn elo_mapping() {
+        assert_eq!(Skill::from_elo(0), Skill::new(0));
+        assert_eq!(Skill::from_elo(u16::MAX), Skill::FULL);
+        let mut prev = Skill::from_elo(MIN_ELO);
+        for elo in MIN_ELO..=MAX_ELO {
+            let skill = Skill::from_elo(elo);
+            assert!(prev <= skill);
+            prev = skill;
+        }
+    }
+}
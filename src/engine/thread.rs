@@ -23,23 +23,42 @@
 //! The main search also collects all of the output from each individual search and composes it into
 //! a single easily-used structure for consumption in the main process.
 
-use std::time::Instant;
+use std::{
+    cmp::Reverse,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError},
+        Arc,
+    },
+    thread::scope,
+    time::{Duration, Instant},
+};
 
-use crate::base::game::Game;
+use crate::base::{
+    game::Game,
+    movegen::{has_moves, is_legal, GenMode},
+    Move,
+};
 
 use super::{
-    evaluate::Eval,
-    uci::{EngineInfo, Message},
+    evaluate::{leaf_evaluate, Eval, EvalWeights},
+    skill::Skill,
+    uci::{EngineInfo, InfoSink, NullSink, StdoutSink},
 };
 
 use super::{
     limit::SearchLimit,
-    search::{search, SearchResult},
+    pick::HistoryTable,
+    search::{search, SearchInfo, SearchResult},
+    time::TimeManager,
     transposition::TTable,
     SearchError,
 };
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The largest distance from an evaluation to the edge of an aspiration window before the window
+/// is opened completely on that side.
+const MAX_ASPIRATION_DELTA: Eval = Eval::centipawns(1000);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 /// Configuration options for a search.
 pub struct SearchConfig {
     /// The depth at which this algorithm will evaluate a position.
@@ -50,6 +69,56 @@ pub struct SearchConfig {
     /// The number of nodes which have to be searched before it is worthwhile to update the search
     /// limit with this information.
     pub limit_update_increment: u64,
+    /// The highest remaining depth at which reverse futility pruning, futility pruning, and
+    /// razoring will be attempted.
+    pub futility_depth: i8,
+    /// The margin per ply of remaining depth by which the static evaluation must exceed beta for a
+    /// node to be pruned by reverse futility pruning.
+    pub reverse_futility_margin: Eval,
+    /// The margin per ply of remaining depth which the static evaluation must be able to gain to
+    /// reach alpha for a quiet move to be searched.
+    pub futility_margin: Eval,
+    /// The margin per ply of remaining depth by which the static evaluation must fall short of
+    /// alpha for a node to drop directly into quiescence search.
+    pub razor_margin: Eval,
+    /// The time after the start of a search after which the root will report which move it is
+    /// currently searching.
+    pub currmove_delay: Duration,
+    /// The interval at which the main search will report its progress while searching.
+    pub report_interval: Duration,
+    /// The initial width of the aspiration window on the side where the evaluation is not expected
+    /// to move.
+    pub aspiration_narrow: Eval,
+    /// The initial width of the aspiration window on the side where the evaluation is expected to
+    /// move.
+    pub aspiration_wide: Eval,
+    /// The factor by which the width of an aspiration window grows on its failing side after each
+    /// failed search.
+    pub aspiration_growth: i16,
+    /// The number of helper threads which search alongside the main thread, sharing their
+    /// findings through the transposition table.
+    pub n_helpers: usize,
+    /// Whether the search is being used to analyse a position, rather than to play a game.
+    pub analyse_mode: bool,
+    /// Whether to report win/draw/loss estimates along with evaluations.
+    pub show_wdl: bool,
+    /// The strength at which to play.
+    /// If the skill is limited, the search will deliberately choose weaker moves.
+    pub skill: Skill,
+    /// The seed for any random choices made by the search, such as those made by a limited
+    /// skill.
+    pub seed: u64,
+    /// How much worse than an even position a draw is for the player to move at the root.
+    /// Positive contempt makes the search avoid draws; negative contempt makes it seek them.
+    pub contempt: Eval,
+    /// Whether to randomly perturb the scores of drawn positions by a centipawn.
+    pub randomize_draws: bool,
+    /// The weights used to evaluate positions.
+    /// They are shared so that copies of a configuration do not need to copy the weights.
+    pub weights: Arc<EvalWeights>,
+    /// The age at which transposition table entries are cleared out after a search which is not
+    /// for analysis.
+    pub tt_max_age: u8,
 }
 
 impl SearchConfig {
@@ -59,6 +128,24 @@ impl SearchConfig {
             depth: 10,
             num_early_moves: 4,
             limit_update_increment: 100,
+            futility_depth: 3,
+            reverse_futility_margin: Eval::centipawns(90),
+            futility_margin: Eval::centipawns(120),
+            razor_margin: Eval::centipawns(250),
+            currmove_delay: Duration::from_secs(2),
+            report_interval: Duration::from_secs(1),
+            aspiration_narrow: Eval::centipawns(10),
+            aspiration_wide: Eval::centipawns(100),
+            aspiration_growth: 4,
+            n_helpers: 0,
+            analyse_mode: false,
+            show_wdl: false,
+            skill: Skill::FULL,
+            seed: 0,
+            contempt: Eval::DRAW,
+            randomize_draws: false,
+            weights: Arc::new(EvalWeights::DEFAULT),
+            tt_max_age: 3,
         }
     }
 }
@@ -69,7 +156,6 @@ impl Default for SearchConfig {
     }
 }
 
-#[derive(Debug)]
 /// A convenient wrapper for searching a game.
 pub struct MainSearch {
     /// The configuration of the search, controlling the search parameters.
@@ -77,7 +163,14 @@ pub struct MainSearch {
     /// The transposition table.
     pub ttable: TTable,
     /// The limit to the search.
-    pub limit: SearchLimit,
+    /// It is shared so that other threads may stop the search while it is running.
+    pub limit: Arc<SearchLimit>,
+    /// The time manager, which decides when to stop iterative deepening.
+    /// The hard deadline of the time manager should be given to `limit` before searching.
+    pub time_manager: TimeManager,
+    /// The destination for all information reported during the search.
+    /// By default, information is printed to standard output.
+    pub info_sink: Arc<dyn InfoSink>,
 }
 
 impl MainSearch {
@@ -87,7 +180,9 @@ impl MainSearch {
         MainSearch {
             config: SearchConfig::new(),
             ttable: TTable::with_size(0),
-            limit: SearchLimit::new(),
+            limit: Arc::new(SearchLimit::new()),
+            time_manager: TimeManager::default(),
+            info_sink: Arc::new(StdoutSink),
         }
     }
 
@@ -101,17 +196,110 @@ impl MainSearch {
     /// OS interrupt.
     /// However, a timeout error is most likely if the search times out before it can do any
     /// computation.
+    ///
+    /// If `g` is already over by checkmate or stalemate, the result will have an empty principal
+    /// variation, and nothing will be searched.
     pub fn evaluate(&self, g: &Game) -> SearchResult {
+        if !has_moves(g.board()) {
+            // there is nothing to search, and iterating would only repeat the same empty result
+            let eval = if g.board().checkers.is_empty() {
+                Eval::DRAW
+            } else {
+                -Eval::mate_in(0)
+            };
+            return Ok(SearchInfo {
+                pv: Vec::new(),
+                eval: eval.in_perspective(g.board().player),
+                num_nodes_evaluated: 0,
+                depth: 0,
+                selective_depth: 0,
+            });
+        }
+
         let tic = Instant::now();
-        let mut best_result = Err(SearchError::Timeout);
+        let (stop_reporting, stop_rx) = channel();
+        // each helper gets its own limit, so that it can be stopped as soon as the main thread is
+        // done
+        let helper_limits: Vec<SearchLimit> = (0..self.config.n_helpers)
+            .map(|_| SearchLimit::new())
+            .collect();
+        scope(|s| {
+            s.spawn(move || self.report_progress(&stop_rx, tic));
+            for (id, helper_limit) in helper_limits.iter().enumerate() {
+                s.spawn(move || self.help(g, helper_limit, id));
+            }
+            let result = if self.config.skill.is_limited() {
+                self.iterate_weakened(g, tic)
+            } else {
+                self.iterate(g, tic)
+            };
+            for helper_limit in &helper_limits {
+                helper_limit.stop();
+            }
+            // hanging up the channel stops the reporter
+            drop(stop_reporting);
+            result
+        })
+    }
+
+    /// Search a position as a helper to the main thread until `limit` is stopped.
+    /// Helpers communicate with the main thread only through the transposition table.
+    /// Every other helper searches one ply deeper, so that the helpers do not all duplicate the
+    /// main thread's work.
+    fn help(&self, g: &Game, limit: &SearchLimit, id: usize) {
+        let offset = u8::from(id % 2 == 1);
+        let mut history = HistoryTable::new();
+        for depth in 1..=self.config.depth {
+            let res = search(
+                g.clone(),
+                depth.saturating_add(offset),
+                &self.ttable,
+                &self.config,
+                limit,
+                &NullSink,
+                &mut history,
+                Eval::MIN,
+                Eval::MAX,
+            );
+            if res.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Perform iterative deepening on a position, sending information on each completed depth to
+    /// the info sink.
+    /// `tic` is the time at which the search started.
+    fn iterate(&self, g: &Game, tic: Instant) -> SearchResult {
+        let mut best_result: SearchResult = Err(SearchError::Timeout);
+        let mut time_manager = self.time_manager;
 
         // The previous iteration's evaluation, used for windowing
         let mut prev_eval = None;
+        // The history table is kept across iterations, so that each one can learn from the last
+        let mut history = HistoryTable::new();
         for depth in 1..=self.config.depth {
             // now it's our turn to think
-            let sub_result = self.aspiration_search(g, depth, prev_eval);
+            let sub_result = self.aspiration_search(g, depth, prev_eval, &mut history);
+
+            if let Ok(ref sub_info) = sub_result {
+                if self.limit.is_over() {
+                    // This iteration was cut short, so its result only covers some of the root
+                    // moves.
+                    // Only use it if it agrees with or beats the previous iteration.
+                    let improves = match best_result {
+                        Ok(ref prev_info) => {
+                            sub_info.pv.first() == prev_info.pv.first()
+                                || prev_info.eval < sub_info.eval
+                        }
+                        Err(_) => true,
+                    };
+                    if improves && !sub_info.pv.is_empty() {
+                        best_result = sub_result;
+                    }
+                    break;
+                }
 
-            if sub_result.is_ok() {
                 // update best result and inform GUI
                 best_result = sub_result;
                 let elapsed = tic.elapsed();
@@ -119,31 +307,51 @@ impl MainSearch {
                     prev_eval = Some(best_info.eval);
                     #[allow(clippy::cast_possible_truncation)]
                     {
-                        println!(
-                            "{}",
-                            Message::Info(&[
-                                EngineInfo::Depth(best_info.depth),
-                                EngineInfo::Score {
-                                    eval: best_info.eval,
-                                    is_lower_bound: false,
-                                    is_upper_bound: false
-                                },
-                                EngineInfo::Nodes(best_info.num_nodes_evaluated),
-                                EngineInfo::NodeSpeed(
-                                    1000 * best_info.num_nodes_evaluated
-                                        / (elapsed.as_millis() + 1) as u64
-                                ),
-                                EngineInfo::Time(elapsed),
-                                EngineInfo::Pv(&best_info.pv),
-                                EngineInfo::HashFull(self.ttable.fill_rate_permill()),
-                                EngineInfo::SelDepth(best_info.selective_depth),
-                            ])
-                        );
+                        let mut infos = vec![
+                            EngineInfo::Depth(best_info.depth),
+                            EngineInfo::Score {
+                                eval: best_info.eval,
+                                is_lower_bound: false,
+                                is_upper_bound: false,
+                            },
+                        ];
+                        if self.config.show_wdl {
+                            let (win, draw, loss) = best_info.eval.wdl();
+                            infos.push(EngineInfo::Wdl { win, draw, loss });
+                        }
+                        infos.extend([
+                            EngineInfo::Nodes(best_info.num_nodes_evaluated),
+                            EngineInfo::NodeSpeed(
+                                1000 * best_info.num_nodes_evaluated
+                                    / (elapsed.as_millis() + 1) as u64,
+                            ),
+                            EngineInfo::Time(elapsed),
+                            EngineInfo::Pv(&best_info.pv),
+                            EngineInfo::HashFull(self.ttable.fill_rate_permill()),
+                            EngineInfo::SelDepth(best_info.selective_depth),
+                        ]);
+                        self.info_sink.info(&infos);
+                    }
+
+                    if let Some(&best_move) = best_info.pv.first() {
+                        time_manager.update(best_move, best_info.eval);
+                    }
+                    if time_manager.should_stop(tic.elapsed()) {
+                        // there probably isn't enough time to finish another iteration
+                        break;
                     }
                 }
+            } else {
+                // we ran out of time, or something went horribly wrong
+                break;
             }
         }
 
+        if let Err(SearchError::Timeout) = best_result {
+            // we didn't even finish one root move, but we have to play something
+            best_result = self.fallback(g);
+        }
+
         if let Ok(ref mut info) = best_result {
             // normalize evaluation to be in absolute terms
             info.eval = info.eval.in_perspective(g.board().player);
@@ -151,53 +359,268 @@ impl MainSearch {
         best_result
     }
 
-    fn aspiration_search(&self, g: &Game, depth: u8, prev_eval: Option<Eval>) -> SearchResult {
-        if let Some(ev) = prev_eval {
-            // we have a previous score we can use to window this search
-            let (alpha, beta) = if ev.is_mate() {
+    /// Perform iterative deepening on a position at a limited skill, evaluating every root move
+    /// separately so that the skill can choose among them.
+    /// Only the evaluations from the deepest fully-completed iteration are used.
+    /// `tic` is the time at which the search started.
+    fn iterate_weakened(&self, g: &Game, tic: Instant) -> SearchResult {
+        let moves = g.get_moves::<{ GenMode::All }>();
+        // each root move is searched from the opponent's point of view, so contempt must be too
+        let child_config = SearchConfig {
+            contempt: -self.config.contempt,
+            ..self.config.clone()
+        };
+        let mut scored: Vec<(Move, Eval)> = Vec::new();
+        let mut num_nodes = 0;
+        let mut completed_depth = 0;
+        let mut history = HistoryTable::new();
+
+        'deepen: for depth in 1..=self.config.depth {
+            let mut candidates = Vec::with_capacity(moves.len());
+            for &m in &moves {
+                let mut child = g.clone();
+                child.make_move(m);
+                let Ok(child_info) = search(
+                    child,
+                    depth - 1,
+                    &self.ttable,
+                    &child_config,
+                    &self.limit,
+                    &NullSink,
+                    &mut history,
+                    Eval::MIN,
+                    Eval::MAX,
+                ) else {
+                    break 'deepen;
+                };
+                if self.limit.is_over() {
+                    // this move's evaluation is incomplete, so the whole iteration is unusable
+                    break 'deepen;
+                }
+                num_nodes += child_info.num_nodes_evaluated;
+                candidates.push((m, (-child_info.eval).step_back_by(1)));
+            }
+            // sort from best to worst; the sort is stable so that ties are broken consistently
+            candidates.sort_by_key(|&(_, eval)| Reverse(eval));
+            scored = candidates;
+            completed_depth = depth;
+            if self.time_manager.should_stop(tic.elapsed()) {
+                break;
+            }
+        }
+
+        let Some((m, eval)) = self
+            .config
+            .skill
+            .pick(&scored, &fastrand::Rng::with_seed(self.config.seed))
+        else {
+            // we didn't finish a single iteration, but we have to play something
+            return self.fallback(g).map(|mut info| {
+                info.eval = info.eval.in_perspective(g.board().player);
+                info
+            });
+        };
+
+        let pv = vec![m];
+        self.info_sink.info(&[
+            EngineInfo::Depth(completed_depth),
+            EngineInfo::Score {
+                eval,
+                is_lower_bound: false,
+                is_upper_bound: false,
+            },
+            EngineInfo::Nodes(num_nodes),
+            EngineInfo::Time(tic.elapsed()),
+            EngineInfo::Pv(&pv),
+        ]);
+
+        Ok(SearchInfo {
+            pv,
+            // normalize evaluation to be in absolute terms
+            eval: eval.in_perspective(g.board().player),
+            num_nodes_evaluated: num_nodes,
+            depth: completed_depth,
+            selective_depth: completed_depth,
+        })
+    }
+
+    /// Search a position to a given depth, using the previous iteration's evaluation to narrow the
+    /// search window.
+    /// `history` is the history table shared by every iteration of iterative deepening.
+    /// Whenever the search fails outside of the window, the window is widened on the failing side
+    /// and the search is repeated.
+    ///
+    /// If the search times out after failing high, the failed-high result is returned, since its
+    /// best move is better than anything found in the previous iteration.
+    fn aspiration_search(
+        &self,
+        g: &Game,
+        depth: u8,
+        prev_eval: Option<Eval>,
+        history: &mut HistoryTable,
+    ) -> SearchResult {
+        // the current window, and the distances from the previous evaluation to each edge of it
+        let (mut alpha, mut beta, mut delta_low, mut delta_high) = match prev_eval {
+            None => (Eval::MIN, Eval::MAX, Eval::MIN, Eval::MAX),
+            Some(ev) if ev.is_mate() => {
                 if ev < Eval::DRAW {
                     // we are getting mated.
                     // search for ways we can get mated faster
-                    (Eval::MIN, ev + Eval::centipawns(1))
+                    (Eval::MIN, ev + Eval::centipawns(1), Eval::MIN, Eval::MAX)
                 } else {
                     // we are matting, search for faster wins
-                    (ev - Eval::centipawns(1), Eval::MAX)
+                    (ev - Eval::centipawns(1), Eval::MAX, Eval::MIN, Eval::MAX)
                 }
-            } else {
-                match depth & 0x1u8 {
+            }
+            Some(ev) => {
+                let (low, high) = match depth & 0x1u8 {
                     // even depth means that we expect the evaluation to decrease
-                    0 => (ev - Eval::centipawns(100), ev + Eval::centipawns(10)),
+                    0 => (self.config.aspiration_wide, self.config.aspiration_narrow),
                     // odd depth means that we expect the evaluation to increase
-                    1 => (ev - Eval::centipawns(10), ev + Eval::centipawns(100)),
+                    1 => (self.config.aspiration_narrow, self.config.aspiration_wide),
                     _ => unreachable!(),
-                }
-            };
-            let window_result = search(
+                };
+                (ev - low, ev + high, -low, high)
+            }
+        };
+
+        // the most recent search which failed high, if any
+        let mut partial = None;
+        loop {
+            let res = match search(
                 g.clone(),
                 depth,
                 &self.ttable,
                 &self.config,
                 &self.limit,
+                self.info_sink.as_ref(),
+                history,
                 alpha,
                 beta,
-            );
+            ) {
+                Ok(res) => res,
+                Err(e) => return partial.ok_or(e),
+            };
 
-            if let Ok(ref res) = window_result {
-                if alpha < res.eval && res.eval < beta {
-                    return window_result;
-                }
+            if self.limit.is_over() {
+                // the search was cut short, so there's no time to widen the window.
+                // use whichever partial result is better.
+                return Ok(match partial {
+                    Some(p) if res.eval <= p.eval => p,
+                    _ => res,
+                });
+            }
+
+            if res.eval <= alpha && alpha != Eval::MIN {
+                // failed low: widen the window downward
+                self.report_bound(&res, false)?;
+                delta_low = self.widen(delta_low);
+                alpha = if delta_low == Eval::MIN || res.eval.is_mate() {
+                    Eval::MIN
+                } else {
+                    res.eval + delta_low
+                };
+            } else if beta <= res.eval && beta != Eval::MAX {
+                // failed high: widen the window upward
+                self.report_bound(&res, true)?;
+                delta_high = self.widen(delta_high);
+                beta = if delta_high == Eval::MAX || res.eval.is_mate() {
+                    Eval::MAX
+                } else {
+                    res.eval + delta_high
+                };
+                partial = Some(res);
+            } else {
+                return Ok(res);
             }
         }
+    }
 
-        search(
-            g.clone(),
-            depth,
-            &self.ttable,
-            &self.config,
-            &self.limit,
-            Eval::MIN,
-            Eval::MAX,
-        )
+    /// Select a move to play without searching, for when a search runs out of time before it can
+    /// fully search a single move.
+    /// The move from the transposition table will be used if there is one; otherwise, any legal
+    /// move will do.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `SearchError::Timeout` if there are no legal moves.
+    fn fallback(&self, g: &Game) -> SearchResult {
+        let tt_move = self
+            .ttable
+            .get(g.board().hash)
+            .entry()
+            .map(|entry| entry.best_move)
+            .filter(|&m| is_legal(m, g.board()));
+        let m = tt_move
+            .or_else(|| g.get_moves::<{ GenMode::All }>().first().copied())
+            .ok_or(SearchError::Timeout)?;
+
+        Ok(SearchInfo {
+            pv: vec![m],
+            eval: leaf_evaluate(g, &self.config.weights).in_perspective(g.board().player),
+            num_nodes_evaluated: 0,
+            depth: 0,
+            selective_depth: 0,
+        })
+    }
+
+    /// Grow the distance from an evaluation to one edge of an aspiration window.
+    /// `delta` is negative for the lower edge and positive for the upper edge.
+    /// Once the window is wide enough, it will be opened completely, and the returned value will
+    /// be `Eval::MIN` or `Eval::MAX`.
+    fn widen(&self, delta: Eval) -> Eval {
+        if delta == Eval::MIN || delta == Eval::MAX {
+            return delta;
+        }
+        let magnitude = if delta < Eval::DRAW { -delta } else { delta };
+        if MAX_ASPIRATION_DELTA < magnitude * self.config.aspiration_growth {
+            if delta < Eval::DRAW {
+                Eval::MIN
+            } else {
+                Eval::MAX
+            }
+        } else {
+            delta * self.config.aspiration_growth
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    /// Report the search's progress to the GUI every `config.report_interval` until a message is
+    /// sent through (or the sender hangs up on) `stop`.
+    /// `tic` is the time at which the search started.
+    fn report_progress(&self, stop: &Receiver<()>, tic: Instant) {
+        while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(self.config.report_interval) {
+            let elapsed = tic.elapsed();
+            let nodes = self.limit.num_nodes();
+            self.info_sink.info(&[
+                EngineInfo::Time(elapsed),
+                EngineInfo::Nodes(nodes),
+                EngineInfo::NodeSpeed(1000 * nodes / (elapsed.as_millis() + 1) as u64),
+                EngineInfo::HashFull(self.ttable.fill_rate_permill()),
+            ]);
+        }
+    }
+
+    /// Report the result of a search whose aspiration window failed.
+    /// `failed_high` is whether the evaluation was a lower bound (i.e. it was at least beta) as
+    /// opposed to an upper bound.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the limit's lock was poisoned.
+    fn report_bound(&self, info: &SearchInfo, failed_high: bool) -> Result<(), SearchError> {
+        self.info_sink.info(&[
+            EngineInfo::Depth(info.depth),
+            EngineInfo::Score {
+                eval: info.eval,
+                is_lower_bound: failed_high,
+                is_upper_bound: !failed_high,
+            },
+            EngineInfo::Nodes(self.limit.num_nodes()),
+            EngineInfo::Time(self.limit.elapsed()?),
+            EngineInfo::Pv(&info.pv),
+        ]);
+        Ok(())
     }
 }
 
@@ -210,7 +633,10 @@ impl Default for MainSearch {
 #[cfg(any(test, bench))]
 mod tests {
 
+    use std::sync::Mutex;
+
     use crate::base::movegen::is_legal;
+    use crate::engine::uci::Message;
 
     use super::*;
 
@@ -234,6 +660,196 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Test that reporting progress as often as possible does not disturb the search, and that
+    /// every kind of progress report is sent.
+    fn search_with_progress_reports() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink_reports = reports.clone();
+        let g = Game::new();
+        let mut main = MainSearch::new();
+        main.config.depth = 6;
+        main.config.currmove_delay = Duration::ZERO;
+        main.config.report_interval = Duration::from_millis(1);
+        // a window this narrow is sure to fail
+        main.config.aspiration_narrow = Eval::centipawns(1);
+        main.config.aspiration_wide = Eval::centipawns(1);
+        main.ttable.resize(16);
+        main.info_sink = Arc::new(move |infos: &[EngineInfo]| {
+            sink_reports
+                .lock()
+                .unwrap()
+                .push(format!("{}", Message::Info(infos)));
+        });
+        let info = main.evaluate(&g).unwrap();
+        assert!(is_legal(info.pv[0], g.board()));
+
+        let reports = reports.lock().unwrap();
+        for pattern in [
+            " currmove ",
+            " currmovenumber ",
+            " hashfull ",
+            " lowerbound",
+            " upperbound",
+        ] {
+            assert!(reports.iter().any(|r| r.contains(pattern)), "{pattern}");
+        }
+        // periodic reports are the only ones without a depth
+        assert!(reports
+            .iter()
+            .any(|r| r.starts_with("info ") && !r.contains(" depth ") && r.contains(" nps ")));
+    }
+
+    #[test]
+    /// Test that helper threads do not disturb the main search.
+    fn search_with_helpers() {
+        let g = Game::new();
+        let mut main = MainSearch::new();
+        main.config.depth = 6;
+        main.config.n_helpers = 3;
+        main.ttable.resize(16);
+        let info = main.evaluate(&g).unwrap();
+        assert_eq!(info.depth, 6);
+        assert!(is_legal(info.pv[0], g.board()));
+    }
+
+    #[test]
+    /// Test that a search sends its reports to its info sink.
+    fn search_reports_to_sink() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink_reports = reports.clone();
+        let mut main = MainSearch::new();
+        main.config.depth = 3;
+        main.ttable.resize(16);
+        main.info_sink = Arc::new(move |infos: &[EngineInfo]| {
+            sink_reports
+                .lock()
+                .unwrap()
+                .push(format!("{}", Message::Info(infos)));
+        });
+        main.evaluate(&Game::new()).unwrap();
+
+        let reports = reports.lock().unwrap();
+        for depth in 1..=3 {
+            let prefix = format!("info depth {depth} ");
+            assert!(reports.iter().any(|r| r.starts_with(&prefix)));
+        }
+    }
+
+    /// Search a position at the given skill and seed with a fresh searcher.
+    fn weakened_search(fen: &str, skill: Skill, seed: u64) -> SearchInfo {
+        let g = Game::from_fen(fen).unwrap();
+        let mut main = MainSearch::new();
+        main.config.depth = 3;
+        main.config.skill = skill;
+        main.config.seed = seed;
+        main.ttable.resize(16);
+        main.info_sink = Arc::new(NullSink);
+        let info = main.evaluate(&g).unwrap();
+        assert_eq!(info.pv.len(), 1);
+        assert!(is_legal(info.pv[0], g.board()));
+        info
+    }
+
+    #[test]
+    /// Test that a weakened search with a fixed seed always chooses the same move, and that
+    /// different seeds do not always agree.
+    fn weakened_search_is_deterministic() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let moves: Vec<Move> = (0..8)
+            .map(|seed| weakened_search(fen, Skill::new(0), seed).pv[0])
+            .collect();
+        for (seed, &m) in (0..8).zip(&moves) {
+            assert_eq!(weakened_search(fen, Skill::new(0), seed).pv[0], m);
+        }
+        assert!(moves.iter().any(|&m| m != moves[0]));
+    }
+
+    #[test]
+    /// Test that even the weakest skill never gives away a forced mate.
+    fn weakened_search_keeps_mate() {
+        let fen = "3k4/R7/1R6/5K2/8/8/8/8 w - - 0 1";
+        for seed in 0..8 {
+            let info = weakened_search(fen, Skill::new(0), seed);
+            assert!(info.eval.is_mate());
+            assert!(Eval::DRAW < info.eval);
+        }
+    }
+
+    #[test]
+    /// Test that aspiration windows grow exponentially until they are opened completely.
+    fn aspiration_widening() {
+        let main = MainSearch::new();
+        let growth = main.config.aspiration_growth;
+        let delta = Eval::centipawns(10);
+        assert_eq!(main.widen(delta), delta * growth);
+        assert_eq!(main.widen(-delta), -delta * growth);
+        assert_eq!(main.widen(MAX_ASPIRATION_DELTA), Eval::MAX);
+        assert_eq!(main.widen(-MAX_ASPIRATION_DELTA), Eval::MIN);
+        assert_eq!(main.widen(Eval::MIN), Eval::MIN);
+    }
+
+    #[test]
+    /// Test that a tiny aspiration window, which is sure to fail repeatedly, still gets the same
+    /// evaluation as a full-width search.
+    fn aspiration_matches_full_window() {
+        let g =
+            Game::from_fen("r1bq1b1r/ppp2kpp/2n5/3np3/2B5/8/PPPP1PPP/RNBQK2R w KQ - 0 7").unwrap();
+        let mut main = MainSearch::new();
+        main.config.aspiration_narrow = Eval::centipawns(1);
+        main.config.aspiration_wide = Eval::centipawns(1);
+        main.config.aspiration_growth = 2;
+        main.ttable.resize(16);
+
+        let full = search(
+            g.clone(),
+            4,
+            &main.ttable,
+            &main.config,
+            &main.limit,
+            &NullSink,
+            &mut HistoryTable::new(),
+            Eval::MIN,
+            Eval::MAX,
+        )
+        .unwrap();
+        main.ttable.clear();
+        let windowed = main
+            .aspiration_search(
+                &g,
+                4,
+                Some(full.eval + Eval::centipawns(300)),
+                &mut HistoryTable::new(),
+            )
+            .unwrap();
+        assert_eq!(windowed.eval, full.eval);
+    }
+
+    /// Search the start position with the given time budget, and verify that the best move is
+    /// legal.
+    fn budget_helper(budget: Duration) {
+        let g = Game::new();
+        let mut main = MainSearch::new();
+        main.config.depth = 99;
+        main.ttable.resize(16);
+        *main.limit.search_duration.lock().unwrap() = Some(budget);
+        main.limit.start().unwrap();
+        let info = main.evaluate(&g).unwrap();
+        assert!(is_legal(info.pv[0], g.board()));
+    }
+
+    #[test]
+    /// Test that a search with a 1 ms budget still gives a legal move.
+    fn one_ms_budget() {
+        budget_helper(Duration::from_millis(1));
+    }
+
+    #[test]
+    /// Test that a search which times out before doing anything still gives a legal move.
+    fn zero_budget() {
+        budget_helper(Duration::ZERO);
+    }
+
     #[test]
     fn search_fried_liver() {
         search_helper(
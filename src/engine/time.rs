@@ -20,38 +20,307 @@
 //!
 //! In a match, a chess engine is usually given a budget of time for the entire game, and it is the
 //! engine's duty to decide how much to use when making each move.
-//! More sophisticated engines do an analysis of the position and guess its complexity, giving
-//! themselves more time in positions which are more complex.
-//! For now, Tomato is not so intelligent, and instead rations time to itself indiscriminately.
-
-use std::cmp::min;
-
-#[must_use]
-#[allow(
-    clippy::module_name_repetitions,
-    clippy::cast_sign_loss,
-    clippy::cast_possible_truncation,
-    clippy::cast_precision_loss
-)]
-/// Decide how much time to search a position, given UCI information about the time remaining.
-///
-/// Inputs:
-/// * `movestogo`: the number of moves remaining until the next increment.
-/// *`increment`: the time increment that each player will get after they play a move, measured in
-///     milliseconds.
-/// * `remaining`: the remaining time that we have, measured in milliseconds.
-pub fn get_search_time(movestogo: Option<u8>, increment: u32, remaining: u32) -> u32 {
-    // for now, simply try to exhaust our remaining time to the increment, with a little buffer
-    // time.
-
-    let rem_float = remaining as f32;
-    if let Some(moves) = movestogo {
-        min(
-            800 * remaining / (1000 * u32::from(moves)) + increment,
-            (0.85 * rem_float) as u32,
-        )
-    } else {
-        // use a fraction of our remaining time.
-        min(remaining / 80 + increment, (0.9 * rem_float) as u32)
+//!
+//! Tomato splits its budget for each move into two deadlines.
+//! The hard deadline is enforced by the search limit, and will abort a search partway through an
+//! iteration.
+//! The soft deadline is only checked between iterations of iterative deepening: once it has
+//! passed, there is probably not enough time to finish another iteration, so we stop early.
+//! The soft deadline shrinks when the best move has been stable for several iterations (such as
+//! when there is an obvious recapture) and grows when the evaluation drops, since that is a sign
+//! that we are in trouble and need to think harder.
+
+use std::time::Duration;
+
+use crate::base::Move;
+
+use super::evaluate::Eval;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(clippy::module_name_repetitions)]
+/// The time control under which a move must be made, as described by a UCI `go` command.
+pub enum TimeControl {
+    /// There is no time limit, and the search should only stop when told to.
+    Infinite,
+    /// Exactly the given amount of time should be spent on the move.
+    MoveTime(Duration),
+    /// The remaining time must be used for the rest of the game (sudden death), with a (possibly
+    /// zero) increment added after every move.
+    Incremental {
+        /// The time remaining on our clock.
+        remaining: Duration,
+        /// The time added to our clock after each move.
+        increment: Duration,
+    },
+    /// The remaining time must last until the next time control, a given number of moves away.
+    Cyclic {
+        /// The time remaining on our clock.
+        remaining: Duration,
+        /// The time added to our clock after each move.
+        increment: Duration,
+        /// The number of moves to be made before the next time control.
+        moves_to_go: u8,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(clippy::module_name_repetitions)]
+/// A manager for the time spent searching a single move.
+pub struct TimeManager {
+    /// The amount of time after which no new iteration should be started, before scaling for
+    /// stability.
+    /// Will be `None` if the search is untimed or must use its entire budget.
+    soft: Option<Duration>,
+    /// The amount of time after which the search must be stopped immediately.
+    /// Will be `None` if the search is untimed.
+    hard: Option<Duration>,
+    /// The best move found in the previous iteration.
+    prev_best_move: Option<Move>,
+    /// The evaluation found in the previous iteration.
+    prev_eval: Option<Eval>,
+    /// The number of consecutive iterations for which the best move has stayed the same.
+    stability: u8,
+    /// The factor by which to multiply the soft deadline, updated after each iteration.
+    scale: f32,
+}
+
+/// The number of stable iterations after which the soft deadline stops shrinking.
+const MAX_STABILITY: u8 = 5;
+
+/// The fraction of the soft deadline removed for each iteration the best move stays stable.
+const STABILITY_DISCOUNT: f32 = 0.1;
+
+/// The soft deadline scaling used when the best move has just changed.
+const UNSTABLE_SCALE: f32 = 1.25;
+
+/// The evaluation drop, in centipawns, which will double the soft deadline.
+const DROP_DOUBLING_CP: f32 = 100.0;
+
+/// The largest multiple of the soft deadline that the hard deadline may be.
+const HARD_TO_SOFT_RATIO: u32 = 4;
+
+/// The shortest time which may be given as a deadline.
+const MIN_DEADLINE: Duration = Duration::from_millis(1);
+
+impl TimeManager {
+    #[must_use]
+    /// Construct a new time manager for a move under the given time control.
+    /// `overhead` is the amount of time to reserve for communication lag between the engine and
+    /// the GUI, which is removed from any timed budget.
+    pub fn new(control: TimeControl, overhead: Duration) -> TimeManager {
+        let (soft, hard) = match control {
+            TimeControl::Infinite => (None, None),
+            TimeControl::MoveTime(time) => (None, Some(time.saturating_sub(overhead))),
+            TimeControl::Incremental {
+                remaining,
+                increment,
+            } => {
+                // ration our remaining time over a typical number of remaining moves
+                let available = remaining.saturating_sub(overhead);
+                let soft = available / 40 + increment * 3 / 4;
+                let hard = (soft * HARD_TO_SOFT_RATIO).min(available * 3 / 4);
+                (Some(soft.min(hard)), Some(hard))
+            }
+            TimeControl::Cyclic {
+                remaining,
+                increment,
+                moves_to_go,
+            } => {
+                // leave a little time in reserve, since the last move before the time control
+                // would otherwise have nothing to spare
+                let available = remaining.saturating_sub(overhead);
+                let moves_to_go = u32::from(moves_to_go.max(1));
+                let soft = available * 4 / (5 * moves_to_go) + increment * 3 / 4;
+                let hard = (soft * HARD_TO_SOFT_RATIO).min(available * 9 / 10);
+                (Some(soft.min(hard)), Some(hard))
+            }
+        };
+
+        TimeManager {
+            soft: soft.map(|t| t.max(MIN_DEADLINE)),
+            hard: hard.map(|t| t.max(MIN_DEADLINE)),
+            prev_best_move: None,
+            prev_eval: None,
+            stability: 0,
+            scale: 1.0,
+        }
+    }
+
+    #[must_use]
+    /// Get the hard deadline, after which the search must stop immediately.
+    /// Returns `None` if there is no time limit.
+    pub fn hard_limit(&self) -> Option<Duration> {
+        self.hard
+    }
+
+    #[must_use]
+    /// Get the soft deadline, scaled by the stability of the search so far, after which no new
+    /// iterations should be started.
+    /// Returns `None` if there is no soft deadline.
+    /// The soft deadline will never be later than the hard deadline.
+    pub fn soft_limit(&self) -> Option<Duration> {
+        self.soft.map(|soft| {
+            let scaled = soft.mul_f32(self.scale);
+            self.hard.map_or(scaled, |hard| scaled.min(hard))
+        })
+    }
+
+    /// Inform the time manager of the result of a completed iteration of iterative deepening.
+    /// `eval` must be from the perspective of the player to move.
+    pub fn update(&mut self, best_move: Move, eval: Eval) {
+        if self.prev_best_move == Some(best_move) {
+            self.stability = (self.stability + 1).min(MAX_STABILITY);
+        } else {
+            self.stability = 0;
+        }
+
+        self.scale = if self.stability == 0 {
+            UNSTABLE_SCALE
+        } else {
+            1.0 - STABILITY_DISCOUNT * f32::from(self.stability)
+        };
+
+        if let Some(prev_eval) = self.prev_eval {
+            if !prev_eval.is_mate() && !eval.is_mate() && eval < prev_eval {
+                // our evaluation dropped, so spend more time looking for an escape
+                let drop = f32::from((prev_eval - eval).centipawn_val());
+                self.scale *= 1.0 + (drop / DROP_DOUBLING_CP).min(1.0);
+            }
+        }
+
+        self.prev_best_move = Some(best_move);
+        self.prev_eval = Some(eval);
+    }
+
+    #[must_use]
+    /// Determine whether the search should stop instead of beginning a new iteration, given the
+    /// time elapsed since the search started.
+    pub fn should_stop(&self, elapsed: Duration) -> bool {
+        matches!(self.soft_limit(), Some(soft) if soft <= elapsed)
+    }
+}
+
+impl Default for TimeManager {
+    /// Construct a time manager for an untimed search.
+    fn default() -> Self {
+        TimeManager::new(TimeControl::Infinite, Duration::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::Square;
+
+    /// The move overhead used for testing.
+    const OVERHEAD: Duration = Duration::from_millis(50);
+
+    #[test]
+    /// Test that an infinite search never stops.
+    fn infinite() {
+        let tm = TimeManager::new(TimeControl::Infinite, OVERHEAD);
+        assert_eq!(tm.hard_limit(), None);
+        assert_eq!(tm.soft_limit(), None);
+        assert!(!tm.should_stop(Duration::from_secs(1_000_000)));
+    }
+
+    #[test]
+    /// Test that a fixed move time uses the whole budget, less the overhead.
+    fn move_time() {
+        let tm = TimeManager::new(TimeControl::MoveTime(Duration::from_secs(1)), OVERHEAD);
+        assert_eq!(tm.hard_limit(), Some(Duration::from_millis(950)));
+        assert_eq!(tm.soft_limit(), None);
+        assert!(!tm.should_stop(Duration::from_millis(900)));
+    }
+
+    #[test]
+    /// Test that a sudden-death time control rations its time, and stays within the clock.
+    fn incremental() {
+        let remaining = Duration::from_secs(90);
+        let tm = TimeManager::new(
+            TimeControl::Incremental {
+                remaining,
+                increment: Duration::from_secs(1),
+            },
+            OVERHEAD,
+        );
+        let soft = tm.soft_limit().unwrap();
+        let hard = tm.hard_limit().unwrap();
+        assert!(soft < hard);
+        assert!(hard < remaining);
+        assert!(soft > Duration::from_secs(1));
+        assert!(tm.should_stop(soft));
+    }
+
+    #[test]
+    /// Test that a cyclic time control spends more time when the next control is near.
+    fn cyclic() {
+        let remaining = Duration::from_secs(90);
+        let with_moves = |moves_to_go| {
+            TimeManager::new(
+                TimeControl::Cyclic {
+                    remaining,
+                    increment: Duration::ZERO,
+                    moves_to_go,
+                },
+                OVERHEAD,
+            )
+        };
+        let far = with_moves(30);
+        let near = with_moves(2);
+        assert!(far.soft_limit().unwrap() < near.soft_limit().unwrap());
+        assert!(near.hard_limit().unwrap() < remaining);
+
+        // with one move to go, we may use almost all of our time, but never all of it
+        let last = with_moves(1);
+        assert!(last.hard_limit().unwrap() < remaining);
+        assert!(last.soft_limit().unwrap() <= last.hard_limit().unwrap());
+    }
+
+    #[test]
+    /// Test that a nearly-empty clock still gives a nonzero deadline.
+    fn time_trouble() {
+        let tm = TimeManager::new(
+            TimeControl::Incremental {
+                remaining: Duration::from_millis(10),
+                increment: Duration::ZERO,
+            },
+            OVERHEAD,
+        );
+        assert_eq!(tm.hard_limit(), Some(MIN_DEADLINE));
+        assert_eq!(tm.soft_limit(), Some(MIN_DEADLINE));
+    }
+
+    #[test]
+    /// Test that a stable best move shrinks the soft deadline, and a change in best move or a drop
+    /// in evaluation extends it.
+    fn stability_scaling() {
+        let mut tm = TimeManager::new(
+            TimeControl::Incremental {
+                remaining: Duration::from_secs(90),
+                increment: Duration::ZERO,
+            },
+            OVERHEAD,
+        );
+        let base = tm.soft_limit().unwrap();
+        let e4 = Move::normal(Square::E2, Square::E4);
+        let d4 = Move::normal(Square::D2, Square::D4);
+
+        tm.update(e4, Eval::centipawns(20));
+        assert!(tm.soft_limit().unwrap() > base);
+        for _ in 0..MAX_STABILITY {
+            tm.update(e4, Eval::centipawns(20));
+        }
+        let stable = tm.soft_limit().unwrap();
+        assert!(stable < base);
+
+        // the evaluation dropped, so think harder
+        tm.update(e4, Eval::centipawns(-80));
+        assert!(tm.soft_limit().unwrap() > stable);
+
+        // the best move changed, so think harder
+        tm.update(d4, Eval::centipawns(-80));
+        assert!(tm.soft_limit().unwrap() > base);
+        assert!(tm.soft_limit().unwrap() <= tm.hard_limit().unwrap());
     }
 }
@@ -29,12 +29,24 @@
 //! same entries.
 //! We require that the retrieved move from a transposition table be checked for legality before it
 //! is played.
+//!
+//! Large tables are expensive to set up: simply faulting in tens of gigabytes of zeroed pages can
+//! take seconds, and a table spread over 4 KiB pages thrashes the TLB during search.
+//! To mitigate this, big tables are aligned to huge-page boundaries (and, on Linux, the kernel is
+//! advised to back them with transparent huge pages) and then zeroed in parallel.
+//! Since each thread first touches its own slice of the table, the pages end up distributed across
+//! the memory of every NUMA node the zeroing threads ran on.
 
 use std::{
-    alloc::{alloc_zeroed, dealloc, realloc, Layout},
+    alloc::{alloc, alloc_zeroed, dealloc, Layout},
+    fmt::{Display, Formatter},
     marker::PhantomData,
-    mem::{size_of, transmute},
-    ptr::null,
+    mem::{size_of, transmute, MaybeUninit},
+    num::NonZeroUsize,
+    ptr::{self, copy_nonoverlapping, null, null_mut},
+    slice,
+    sync::atomic::{AtomicU64, Ordering},
+    thread::{available_parallelism, scope},
 };
 
 use crate::base::Move;
@@ -55,6 +67,86 @@ pub struct TTable {
     /// The mask for retrieving entries from the table.
     /// Should always be 0 if `entries` is null.
     mask: u64,
+    /// The manner in which the memory for `buckets` was allocated.
+    alloc_mode: AllocMode,
+    /// The policy for choosing which entry in a bucket to evict.
+    policy: ReplacementPolicy,
+    /// Running statistics on the usage of this table.
+    counters: Counters,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// The rule by which a `TTable` decides which entry of a full bucket to replace on a probe miss.
+pub enum ReplacementPolicy {
+    #[default]
+    /// Always replace the oldest entry in the bucket, regardless of its depth.
+    Oldest,
+    /// Replace the entry with the lowest depth, discounting entries from older searches.
+    /// An entry from the current search is never overwritten by a shallower search of the same
+    /// position.
+    DepthPreferred,
+    /// Split each bucket into two tiers.
+    /// The first tier only accepts entries at least as deep as the one they replace (or entries
+    /// replacing one from an older search), while the second tier always accepts new entries,
+    /// evicting its oldest one.
+    TwoTier,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A coarse classification of the depth of a transposition table entry.
+pub enum DepthClass {
+    /// Entries from quiescence search.
+    Captures = 0,
+    /// Entries searched to depth 1 through 3.
+    Shallow,
+    /// Entries searched to depth 4 through 7.
+    Medium,
+    /// Entries searched to depth 8 or greater.
+    Deep,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A snapshot of the statistics on the usage of a transposition table.
+pub struct TTStats {
+    /// The number of probes made into the table.
+    pub probes: u64,
+    /// The number of probes which found an entry for their position.
+    pub hits: u64,
+    /// The number of probes which missed and found every entry in their bucket occupied.
+    pub collisions: u64,
+    /// The number of occupied entries which were evicted by an entry for another position, indexed
+    /// by the `DepthClass` of the evicted entry.
+    pub overwrites: [u64; DepthClass::NUM],
+    /// The number of occupied entries in the table, indexed by age.
+    pub occupancy_by_age: [u64; MAX_AGE as usize + 1],
+}
+
+#[derive(Debug)]
+/// Internal, concurrently-updated counters backing `TTStats`.
+struct Counters {
+    /// The number of probes made into the table.
+    probes: AtomicU64,
+    /// The number of probes which found a matching entry.
+    hits: AtomicU64,
+    /// The number of probes which missed on a full bucket.
+    collisions: AtomicU64,
+    /// The number of overwritten entries by depth class.
+    overwrites: [AtomicU64; DepthClass::NUM],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The strategy which was used to allocate the memory backing a `TTable`.
+pub enum AllocMode {
+    /// No memory has been allocated for the table.
+    Empty,
+    /// The table was allocated through the global allocator with ordinary pages.
+    Normal,
+    /// The table was aligned to huge-page boundaries, but the OS could not be advised to use huge
+    /// pages for it.
+    Aligned,
+    /// The table was aligned to huge-page boundaries and the OS was advised to back it with
+    /// transparent huge pages.
+    HugePages,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -65,8 +157,18 @@ pub struct TTEntryGuard<'a> {
     valid: bool,
     /// The hash which created the reference in the table.
     hash: u64,
+    /// A pointer to the bucket containing `entry`, whose key extensions must be updated on a save.
+    bucket: *mut Bucket,
     /// A pointer to the entry in the transposition table.
+    /// On a miss, this is the preferred entry to replace.
     entry: *mut TTEntry,
+    /// The entry to replace on a miss if the new entry is not worth storing in `entry`.
+    /// Only differs from `entry` under `ReplacementPolicy::TwoTier`.
+    fallback: *mut TTEntry,
+    /// The replacement policy of the table.
+    policy: ReplacementPolicy,
+    /// The usage counters of the table.
+    counters: *const Counters,
     /// Ensures that the guard does not outlive the table it points to.
     _phantom: PhantomData<&'a TTable>,
 }
@@ -76,6 +178,31 @@ pub struct TTEntryGuard<'a> {
 const LINE_SIZE: usize = 64;
 /// The number of entries in a single bucket.
 const BUCKET_LEN: usize = LINE_SIZE / size_of::<TTEntry>();
+/// The size (in bytes) of a huge page on the target architecture.
+/// Tables at least this large are aligned to it so that they can be backed by huge pages.
+const HUGE_PAGE_SIZE: usize = 2 << 20;
+/// The minimum number of bytes of table which each zeroing thread is responsible for.
+/// Spawning threads is not worthwhile for smaller tables.
+const MIN_ZERO_CHUNK: usize = 64 << 20;
+/// The number of entries at the start of each bucket which make up the depth-preferred tier under
+/// `ReplacementPolicy::TwoTier`.
+const DEPTH_TIER_LEN: usize = BUCKET_LEN / 2;
+/// The highest age an entry can have.
+const MAX_AGE: u8 = TAG_AGE_MASK;
+/// The bits of an entry's tag which contain its age.
+const TAG_AGE_MASK: u8 = 0x0F;
+/// The bits of an entry's tag which contain its bound type.
+const TAG_BOUND_MASK: u8 = 0x30;
+/// The offset of the bound type bits in an entry's tag.
+const TAG_BOUND_SHIFT: u8 = 4;
+/// The bit of an entry's tag which is set if the entry came from a PV node.
+const TAG_PV_BIT: u8 = 0x40;
+/// The bit of an entry's tag which contains its liveness.
+const TAG_LIVENESS_MASK: u8 = 0x80;
+/// The number of bits of each entry's key extension, which are stored in its bucket.
+const KEY_EXT_BITS: u32 = 5;
+/// The mask for a single entry's key extension.
+const KEY_EXT_MASK: u32 = (1 << KEY_EXT_BITS) - 1;
 
 #[repr(C)]
 #[repr(align(64))]
@@ -86,30 +213,55 @@ const BUCKET_LEN: usize = LINE_SIZE / size_of::<TTEntry>();
 struct Bucket {
     /// A block of entries.
     pub entries: [TTEntry; BUCKET_LEN],
-    /// Padding bits to make a bucket exactly the size of a cache line.
-    _pad: [u8; LINE_SIZE - BUCKET_LEN * size_of::<TTEntry>()],
+    /// The key extensions of the entries, which fill out the bucket to the size of a cache line.
+    /// Entry `i` owns bits `KEY_EXT_BITS * i` and up, which hold bits 16 and up of its hash key.
+    key_ext: u32,
 }
 
+#[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 /// An entry in the transposition table.
 pub struct TTEntry {
-    /// A packed tag containing the age of the entry in the lower 7 bits and a bit to determine
-    /// whether this entry is unused in the highest bit.
-    tag: u8, // 1 byte
-    /// The lower 16 bits hash key of the entry.
+    /// The lower 16 bits of the hash key of the entry.
+    /// The bucket index is taken from the upper bits, so these bits are independent of it.
+    /// The next few bits of the key are kept in the bucket's `key_ext`.
     key_low16: u16, // 2 bytes
-    /// The depth to which this entry was searched.
-    /// If the depth is negative, this means that it was a special type of search.
-    pub depth: i8, // 1 byte
     /// The best move in the position when this entry was searched.
     /// Will be `Move::BAD_MOVE` when there are no moves or the best move is unknown.
     pub best_move: Move, // 2 bytes
-    /// The lower bound on the evaluation of the position.
-    pub lower_bound: Eval, // 2 bytes
-    /// The upper bound on the evaluation of the position.
-    pub upper_bound: Eval, // 2 bytes
+    /// The evaluation of the position found by the search.
+    /// Whether it is exact or only a bound is given by `bound()`.
+    pub value: Eval, // 2 bytes
+    /// The static evaluation of the position, from the perspective of the player to move.
+    /// Will be `Eval::MIN` if the position was not statically evaluated (such as when in check).
+    pub static_eval: Eval, // 2 bytes
+    /// The depth to which this entry was searched.
+    /// If the depth is negative, this means that it was a special type of search.
+    pub depth: i8, // 1 byte
+    /// A packed tag.
+    /// From MSB to LSB, it contains:
+    /// * 1 bit: the liveness of the entry
+    /// * 1 bit: whether the entry was written by a PV node
+    /// * 2 bits: the bound type of `value`
+    /// * 4 bits: the age of the entry
+    tag: u8, // 1 byte
+
+             /* total size: 10 bytes */
+}
 
-                           /* total size: 10 bytes */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+/// The type of bound that the value of a transposition table entry gives on the true evaluation of
+/// its position.
+pub enum Bound {
+    /// The value is neither an upper nor a lower bound.
+    None = 0,
+    /// The true evaluation is at most the value (the search failed low).
+    Upper = 1,
+    /// The true evaluation is at least the value (the search failed high).
+    Lower = 2,
+    /// The value is the true evaluation.
+    Exact = 3,
 }
 
 impl TTable {
@@ -119,6 +271,9 @@ impl TTable {
         TTable {
             buckets: null::<Bucket>() as *mut Bucket,
             mask: 0,
+            alloc_mode: AllocMode::Empty,
+            policy: ReplacementPolicy::Oldest,
+            counters: Counters::new(),
         }
     }
 
@@ -139,6 +294,15 @@ impl TTable {
         TTable::with_capacity(new_size.trailing_zeros() as usize)
     }
 
+    #[must_use]
+    /// Construct a `TTable` with a given size, in megabytes, which replaces entries according to
+    /// `policy`.
+    pub fn with_policy(size_mb: usize, policy: ReplacementPolicy) -> TTable {
+        let mut table = TTable::with_size(size_mb);
+        table.policy = policy;
+        table
+    }
+
     #[must_use]
     #[allow(clippy::cast_ptr_alignment)]
     /// Create a transposition table with a fixed capacity.
@@ -146,19 +310,68 @@ impl TTable {
     ///
     /// # Panics
     ///
-    /// This function will panic if `capacity_log2` is large enough to cause overflow.
+    /// This function will panic if `capacity_log2` is large enough to cause overflow, or if the
+    /// allocation fails.
     fn with_capacity(capacity_log2: usize) -> TTable {
-        let layout = Layout::array::<Bucket>(1 << capacity_log2).unwrap();
+        let num_buckets = 1usize << capacity_log2;
+        let layout = TTable::layout_for(num_buckets);
+        if layout.align() < HUGE_PAGE_SIZE {
+            // small table: the allocator's zeroed pages are good enough
+            let buckets = unsafe { alloc_zeroed(layout).cast::<Bucket>() };
+            assert!(!buckets.is_null(), "failed to allocate transposition table");
+            return TTable {
+                buckets,
+                mask: (num_buckets - 1) as u64,
+                alloc_mode: AllocMode::Normal,
+                policy: ReplacementPolicy::Oldest,
+                counters: Counters::new(),
+            };
+        }
+
+        let buckets = unsafe { alloc(layout).cast::<Bucket>() };
+        assert!(!buckets.is_null(), "failed to allocate transposition table");
+
+        // advise the kernel before any page is touched, so that the first faults can already be
+        // served with huge pages
+        let alloc_mode = if advise_huge_pages(buckets.cast::<u8>(), layout.size()) {
+            AllocMode::HugePages
+        } else {
+            AllocMode::Aligned
+        };
+
+        // SAFETY: `buckets` points to a fresh allocation of exactly `num_buckets` buckets.
+        // The slice is of `MaybeUninit` since the memory has not yet been initialized.
+        let uninit = unsafe {
+            slice::from_raw_parts_mut(buckets.cast::<MaybeUninit<Bucket>>(), num_buckets)
+        };
+        zero_parallel(uninit);
+
         TTable {
-            buckets: unsafe { alloc_zeroed(layout).cast::<Bucket>() },
-            mask: ((1usize << capacity_log2) - 1usize) as u64,
+            buckets,
+            mask: (num_buckets - 1) as u64,
+            alloc_mode,
+            policy: ReplacementPolicy::Oldest,
+            counters: Counters::new(),
+        }
+    }
+
+    #[allow(clippy::missing_panics_doc)]
+    /// Get the memory layout for a table containing `num_buckets` buckets.
+    /// Tables which are at least as large as a huge page are aligned to a huge page boundary.
+    fn layout_for(num_buckets: usize) -> Layout {
+        let layout = Layout::array::<Bucket>(num_buckets).unwrap();
+        if layout.size() < HUGE_PAGE_SIZE {
+            layout
+        } else {
+            layout.align_to(HUGE_PAGE_SIZE).unwrap()
         }
     }
 
     #[inline(always)]
     /// Compute the index for an entry with a given key.
+    /// The index comes from the upper 32 bits of the key, leaving the lower bits for verification.
     fn index_for(&self, hash_key: u64) -> usize {
-        ((hash_key >> 16) & self.mask) as usize
+        ((hash_key >> 32) & self.mask) as usize
     }
 
     #[must_use]
@@ -169,65 +382,106 @@ impl TTable {
     )]
     /// Get the evaluation data stored by this table for a given key, if it exists.
     /// Returns `None` if no data corresponding to the key exists.
-    pub fn get<'a>(&self, hash_key: u64) -> TTEntryGuard<'a> {
+    pub fn get(&self, hash_key: u64) -> TTEntryGuard<'_> {
         if self.buckets.is_null() {
             // cannot index into empty table.
             return TTEntryGuard {
                 valid: false,
                 hash: 0,
-                entry: null::<TTEntry>() as *mut TTEntry,
+                bucket: null_mut::<Bucket>(),
+                entry: null_mut::<TTEntry>(),
+                fallback: null_mut::<TTEntry>(),
+                policy: self.policy,
+                counters: ptr::from_ref(&self.counters),
                 _phantom: PhantomData,
             };
         }
+        self.counters.probes.fetch_add(1, Ordering::Relaxed);
         let idx = self.index_for(hash_key);
         let bucket = unsafe { self.buckets.add(idx) };
+        let key_exts = unsafe { (*bucket).key_ext };
 
         // pointer to entry currently being reviewed
         let mut entry_ptr = bucket.cast::<TTEntry>();
 
         // pointer to entry which will get overwritten if we don't find a match
         let mut replace_ptr = entry_ptr;
+        // replacement candidate in the always-replace tier, for two-tier buckets
+        let mut fallback_ptr = unsafe { entry_ptr.add(DEPTH_TIER_LEN) };
+
+        // worth of the least valuable entries we've seen so far in each tier
+        let mut replace_worth = i16::MAX;
+        let mut fallback_worth = i16::MAX;
 
-        // age of oldest entry we've seen so far
-        let mut eldest_age = 0u8;
+        // whether an unused entry was found in the bucket
+        let mut found_unused = false;
 
         // first, see if we can find a match in the bucket
-        for _ in 0..BUCKET_LEN {
+        for i in 0..BUCKET_LEN {
             let entry_ref = unsafe { entry_ptr.as_ref().unwrap() };
 
             if entry_ref.liveness() != Liveness::Occupied {
                 // if we encounter an entry which is unused, mark it for replacement if we don't
                 // find a matching entry.
-                if eldest_age < 255 {
-                    eldest_age = 255;
+                if !found_unused {
+                    found_unused = true;
                     replace_ptr = entry_ptr;
+                    fallback_ptr = entry_ptr;
                 }
-            } else if entry_ref.key_low16 == hash_key as u16 {
+            } else if entry_ref.key_low16 == hash_key as u16
+                && (key_exts >> (KEY_EXT_BITS * i as u32)) & KEY_EXT_MASK == key_ext(hash_key)
+            {
                 // found a matching entry
+                self.counters.hits.fetch_add(1, Ordering::Relaxed);
                 return TTEntryGuard {
                     valid: true,
                     hash: hash_key,
+                    bucket,
                     entry: entry_ptr,
+                    fallback: entry_ptr,
+                    policy: self.policy,
+                    counters: ptr::from_ref(&self.counters),
                     _phantom: PhantomData,
                 };
-            } else {
+            } else if !found_unused {
                 // check if we can use this entry to overwrite if we don't find a match
-                let age = entry_ref.age();
-                if eldest_age < age {
+                let in_fallback_tier =
+                    self.policy == ReplacementPolicy::TwoTier && DEPTH_TIER_LEN <= i;
+                let worth = if in_fallback_tier {
+                    -i16::from(entry_ref.age())
+                } else {
+                    self.policy.worth(entry_ref)
+                };
+                if in_fallback_tier {
+                    if worth < fallback_worth {
+                        fallback_ptr = entry_ptr;
+                        fallback_worth = worth;
+                    }
+                } else if worth < replace_worth {
                     replace_ptr = entry_ptr;
-                    eldest_age = age;
+                    replace_worth = worth;
                 }
             }
 
             entry_ptr = unsafe { entry_ptr.add(1) }
         }
 
-        // no match found. pick the oldest entry to replace
+        // no match found. pick the least valuable entry to replace
+        if !found_unused {
+            self.counters.collisions.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.policy != ReplacementPolicy::TwoTier {
+            fallback_ptr = replace_ptr;
+        }
 
         TTEntryGuard {
             valid: false,
             hash: hash_key,
+            bucket,
             entry: replace_ptr,
+            fallback: fallback_ptr,
+            policy: self.policy,
+            counters: ptr::from_ref(&self.counters),
             _phantom: PhantomData,
         }
     }
@@ -267,13 +521,13 @@ impl TTable {
     /// Age up all the entries in this table, and for any slot which is at least as old as the max
     /// age, evict it.
     ///
-    /// `max_age` must be less than or equal to 0x3F.
+    /// `max_age` must be less than or equal to 15.
     ///
     /// # Panics
     ///
-    /// This function will panic in debug mode if `max_age` is greater than or equal to 0x3f.
+    /// This function will panic in debug mode if `max_age` is greater than 15.
     pub fn age_up(&mut self, max_age: u8) {
-        debug_assert!(max_age <= 0x3F);
+        debug_assert!(max_age <= MAX_AGE);
         if !self.buckets.is_null() {
             for idx in 0..=self.mask {
                 #[allow(clippy::cast_possible_truncation)]
@@ -291,7 +545,7 @@ impl TTable {
                     if entry.liveness() == Liveness::Occupied {
                         // only age up the occupied entries
                         if max_age <= entry.age() {
-                            entry.tag = Liveness::Deleted as u8;
+                            entry.tag = Liveness::Empty as u8;
                         } else {
                             entry.tag += 1;
                         }
@@ -301,7 +555,7 @@ impl TTable {
         }
     }
 
-    #[allow(clippy::cast_possible_truncation, clippy::cast_ptr_alignment)]
+    #[allow(clippy::cast_possible_truncation)]
     /// Resize the hash table to use no more than `size_mb` megabytes.
     ///
     /// # Panics
@@ -316,22 +570,14 @@ impl TTable {
             max_num_entries.next_power_of_two() >> 1
         };
 
-        let old_buckets = self.buckets;
         let mut old_size = self.mask as usize + 1;
-        if old_buckets.is_null() {
+        if self.buckets.is_null() {
             old_size = 0;
         }
+        let policy = self.policy;
         if new_size == 0 {
-            if !old_buckets.is_null() {
-                unsafe {
-                    dealloc(
-                        old_buckets.cast::<u8>(),
-                        Layout::array::<Bucket>(old_size).unwrap(),
-                    );
-                };
-            }
-            self.buckets = null::<Bucket>() as *mut Bucket;
-            self.mask = 0;
+            // dropping the old table deallocates it
+            *self = TTable::new();
         } else if new_size < old_size {
             // move entries down if possible
             let new_mask = new_size - 1;
@@ -344,24 +590,16 @@ impl TTable {
                 let new_bucket_slot = unsafe { self.buckets.add(new_idx).as_mut().unwrap() };
                 *new_bucket_slot = bucket;
             }
-            // realloc to shrink this
-            self.buckets = unsafe {
-                realloc(
-                    self.buckets.cast::<u8>(),
-                    Layout::array::<Bucket>(old_size).unwrap(),
-                    new_size * size_of::<Bucket>(),
-                )
-                .cast::<Bucket>()
-            };
-            self.mask = new_mask as u64;
-        } else {
+            // move the surviving buckets into a freshly allocated table, since the new table may
+            // need a different alignment than the old one
+            let new_table = TTable::with_capacity(new_size.trailing_zeros() as usize);
+            unsafe { copy_nonoverlapping(self.buckets, new_table.buckets, new_size) };
+            *self = new_table;
+        } else if new_size > old_size {
             // the table is growing
-            self.buckets = unsafe {
-                alloc_zeroed(Layout::array::<Bucket>(new_size).unwrap()).cast::<Bucket>()
-            };
-
-            self.mask = (new_size - 1) as u64;
+            *self = TTable::with_capacity(new_size.trailing_zeros() as usize);
         }
+        self.policy = policy;
     }
 
     #[must_use]
@@ -383,7 +621,205 @@ impl TTable {
         if !self.buckets.is_null() {
             let n_entries = self.mask as usize + 1;
             // fill the whole table with zeros
-            unsafe { self.buckets.write_bytes(0, n_entries) };
+            // SAFETY: we have exclusive access to the table, which has exactly `n_entries`
+            // buckets.
+            let uninit = unsafe {
+                slice::from_raw_parts_mut(self.buckets.cast::<MaybeUninit<Bucket>>(), n_entries)
+            };
+            zero_parallel(uninit);
+        }
+    }
+
+    #[must_use]
+    /// Get the strategy which was used to allocate the memory for this table.
+    pub fn alloc_mode(&self) -> AllocMode {
+        self.alloc_mode
+    }
+
+    #[must_use]
+    /// Get the policy by which this table replaces entries.
+    pub fn policy(&self) -> ReplacementPolicy {
+        self.policy
+    }
+
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    /// Collect statistics on the usage of this table since it was created or since the last call
+    /// to `reset_stats`.
+    ///
+    /// Computing the occupancy requires a scan of the whole table, so this is slow for large
+    /// tables.
+    pub fn stats(&self) -> TTStats {
+        let mut occupancy_by_age = [0; MAX_AGE as usize + 1];
+        if !self.buckets.is_null() {
+            for idx in 0..=self.mask {
+                #[allow(clippy::cast_possible_truncation)]
+                let bucket = unsafe { self.buckets.add(idx as usize).as_ref().unwrap() };
+                for entry in &bucket.entries {
+                    if entry.liveness() == Liveness::Occupied {
+                        occupancy_by_age[entry.age() as usize] += 1;
+                    }
+                }
+            }
+        }
+
+        TTStats {
+            probes: self.counters.probes.load(Ordering::Relaxed),
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            collisions: self.counters.collisions.load(Ordering::Relaxed),
+            overwrites: self
+                .counters
+                .overwrites
+                .each_ref()
+                .map(|n| n.load(Ordering::Relaxed)),
+            occupancy_by_age,
+        }
+    }
+
+    /// Reset the usage counters of this table to zero.
+    pub fn reset_stats(&mut self) {
+        self.counters = Counters::new();
+    }
+}
+
+impl ReplacementPolicy {
+    /// Get the worth of keeping an occupied entry under this policy.
+    /// On a miss, the entry with the lowest worth will be replaced.
+    fn worth(self, entry: &TTEntry) -> i16 {
+        match self {
+            ReplacementPolicy::Oldest => -i16::from(entry.age()),
+            ReplacementPolicy::DepthPreferred | ReplacementPolicy::TwoTier => {
+                i16::from(entry.depth) - 4 * i16::from(entry.age())
+            }
+        }
+    }
+}
+
+impl DepthClass {
+    /// The number of depth classes.
+    pub const NUM: usize = 4;
+
+    #[must_use]
+    /// Get the depth class of an entry searched to `depth`.
+    pub const fn of(depth: i8) -> DepthClass {
+        match depth {
+            i8::MIN..=0 => DepthClass::Captures,
+            1..=3 => DepthClass::Shallow,
+            4..=7 => DepthClass::Medium,
+            _ => DepthClass::Deep,
+        }
+    }
+}
+
+impl TTStats {
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    /// Get the proportion of probes which were hits.
+    pub fn hit_rate(&self) -> f64 {
+        if self.probes == 0 {
+            0.
+        } else {
+            self.hits as f64 / self.probes as f64
+        }
+    }
+}
+
+impl Display for TTStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "probes {} hits {} ({:.1}%) collisions {} overwrites [captures {}, shallow {}, medium {}, deep {}] occupancy by age",
+            self.probes,
+            self.hits,
+            100. * self.hit_rate(),
+            self.collisions,
+            self.overwrites[DepthClass::Captures as usize],
+            self.overwrites[DepthClass::Shallow as usize],
+            self.overwrites[DepthClass::Medium as usize],
+            self.overwrites[DepthClass::Deep as usize],
+        )?;
+        // trailing ages with no entries are omitted
+        let n_ages = self
+            .occupancy_by_age
+            .iter()
+            .rposition(|&n| n != 0)
+            .map_or(1, |i| i + 1);
+        for n in &self.occupancy_by_age[..n_ages] {
+            write!(f, " {n}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Counters {
+    /// Construct a new set of counters, all zero.
+    const fn new() -> Counters {
+        Counters {
+            probes: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            collisions: AtomicU64::new(0),
+            overwrites: [
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+            ],
+        }
+    }
+}
+
+impl Clone for Counters {
+    fn clone(&self) -> Counters {
+        let copy = |n: &AtomicU64| AtomicU64::new(n.load(Ordering::Relaxed));
+        Counters {
+            probes: copy(&self.probes),
+            hits: copy(&self.hits),
+            collisions: copy(&self.collisions),
+            overwrites: self.overwrites.each_ref().map(copy),
+        }
+    }
+}
+
+/// Fill `buckets` with zeros, splitting the work across every available thread if the table is
+/// large enough to merit it.
+fn zero_parallel(buckets: &mut [MaybeUninit<Bucket>]) {
+    let max_threads = available_parallelism().map_or(1, NonZeroUsize::get);
+    let nthreads = (size_of::<Bucket>() * buckets.len() / MIN_ZERO_CHUNK).clamp(1, max_threads);
+    if nthreads == 1 {
+        unsafe { buckets.as_mut_ptr().write_bytes(0, buckets.len()) };
+        return;
+    }
+
+    let chunk_len = buckets.len().div_ceil(nthreads);
+    scope(|s| {
+        for chunk in buckets.chunks_mut(chunk_len) {
+            s.spawn(move || unsafe { chunk.as_mut_ptr().write_bytes(0, chunk.len()) });
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+/// Advise the kernel to back the `len` bytes starting at `ptr` with transparent huge pages.
+/// Returns whether the advice was accepted.
+fn advise_huge_pages(ptr: *mut u8, len: usize) -> bool {
+    // SAFETY: `madvise` does not access the memory; it only marks the range, which the caller
+    // guarantees is a live allocation aligned to a page boundary.
+    unsafe { libc::madvise(ptr.cast::<libc::c_void>(), len, libc::MADV_HUGEPAGE) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+/// Huge page advice is only supported on Linux, so this always fails.
+fn advise_huge_pages(_: *mut u8, _: usize) -> bool {
+    false
+}
+
+impl Display for AllocMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AllocMode::Empty => write!(f, "unallocated"),
+            AllocMode::Normal => write!(f, "normal pages"),
+            AllocMode::Aligned => write!(f, "huge-page aligned, no huge pages"),
+            AllocMode::HugePages => write!(f, "transparent huge pages"),
         }
     }
 }
@@ -391,24 +827,75 @@ impl TTable {
 #[derive(Clone, Copy, PartialEq, Eq)]
 /// The liveness of a transposition table entry.
 enum Liveness {
-    #[allow(unused)]
-    /// An empty entry, with no occupied or deleted entries following it.
+    /// An empty or deleted entry, which may have extra data inside it.
     Empty = 0,
     /// An occupied entry, with data inside.
-    Occupied = 1 << 6,
-    /// A deleted entry, which may have extra data inside it.
-    Deleted = 2 << 6,
+    Occupied = 1 << 7,
 }
 
 impl TTEntry {
     /// Get the age of this entry.
     const fn age(&self) -> u8 {
-        self.tag & 0x3f
+        self.tag & TAG_AGE_MASK
     }
 
     /// Get the liveness of this entry.
     const fn liveness(&self) -> Liveness {
-        unsafe { transmute(self.tag & 0xc0) }
+        unsafe { transmute(self.tag & TAG_LIVENESS_MASK) }
+    }
+
+    #[must_use]
+    /// Get the type of bound that `value` gives on the evaluation of this entry's position.
+    pub const fn bound(&self) -> Bound {
+        unsafe { transmute((self.tag & TAG_BOUND_MASK) >> TAG_BOUND_SHIFT) }
+    }
+
+    #[must_use]
+    /// Determine whether this entry was written by a principal variation node.
+    pub const fn is_pv(&self) -> bool {
+        self.tag & TAG_PV_BIT != 0
+    }
+
+    #[must_use]
+    /// Get the lower bound on the evaluation of this entry's position.
+    /// Will be `Eval::MIN` if the value is not a lower bound.
+    pub fn lower_bound(&self) -> Eval {
+        if self.bound().is_lower() {
+            self.value
+        } else {
+            Eval::MIN
+        }
+    }
+
+    #[must_use]
+    /// Get the upper bound on the evaluation of this entry's position.
+    /// Will be `Eval::MAX` if the value is not an upper bound.
+    pub fn upper_bound(&self) -> Eval {
+        if self.bound().is_upper() {
+            self.value
+        } else {
+            Eval::MAX
+        }
+    }
+
+    /// Construct a packed tag for a live entry of age 0.
+    const fn make_tag(bound: Bound, is_pv: bool) -> u8 {
+        let pv_bit = if is_pv { TAG_PV_BIT } else { 0 };
+        Liveness::Occupied as u8 | pv_bit | ((bound as u8) << TAG_BOUND_SHIFT)
+    }
+}
+
+impl Bound {
+    #[must_use]
+    /// Determine whether a value with this bound is a lower bound on the true evaluation.
+    pub const fn is_lower(self) -> bool {
+        self as u8 & Bound::Lower as u8 != 0
+    }
+
+    #[must_use]
+    /// Determine whether a value with this bound is an upper bound on the true evaluation.
+    pub const fn is_upper(self) -> bool {
+        self as u8 & Bound::Upper as u8 != 0
     }
 }
 
@@ -422,10 +909,7 @@ impl Drop for TTable {
             let size = self.mask as usize + 1;
             // memory was allocated, need to deallocate
             unsafe {
-                dealloc(
-                    self.buckets.cast::<u8>(),
-                    Layout::array::<Bucket>(size).unwrap(),
-                );
+                dealloc(self.buckets.cast::<u8>(), TTable::layout_for(size));
             }
         }
         // if the size is zero, no allocation was performed
@@ -449,24 +933,78 @@ impl<'a> TTEntryGuard<'a> {
         }
     }
 
-    #[allow(clippy::cast_possible_truncation)]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::missing_panics_doc
+    )]
     /// Save the value pointed to by this entry guard.
-    pub fn save(&mut self, depth: i8, best_move: Move, lower_bound: Eval, upper_bound: Eval) {
+    ///
+    /// Depending on the table's replacement policy, the save may be redirected to another entry in
+    /// the bucket or (if it would overwrite a deeper search of the same position) dropped entirely.
+    ///
+    /// Inputs:
+    /// * `depth`: the depth to which the position was searched.
+    /// * `best_move`: the best move found in the position.
+    /// * `value`: the evaluation found by the search.
+    /// * `bound`: the type of bound that `value` gives on the true evaluation.
+    /// * `static_eval`: the static evaluation of the position, or `Eval::MIN` if unknown.
+    /// * `is_pv`: whether the position was searched as a principal variation node.
+    pub fn save(
+        &mut self,
+        depth: i8,
+        best_move: Move,
+        value: Eval,
+        bound: Bound,
+        static_eval: Eval,
+        is_pv: bool,
+    ) {
         if !self.entry.is_null() {
+            let old = unsafe { *self.entry };
+            let old_is_current =
+                old.liveness() == Liveness::Occupied && old.age() == 0 && depth < old.depth;
+            if self.valid {
+                if self.policy != ReplacementPolicy::Oldest && old_is_current {
+                    // keep the deeper search of this position
+                    return;
+                }
+            } else if self.policy == ReplacementPolicy::TwoTier && old_is_current {
+                // not deep enough for the depth-preferred tier
+                self.entry = self.fallback;
+            }
+
+            let counters = unsafe { self.counters.as_ref().unwrap() };
+            let evicted = unsafe { *self.entry };
+            if !self.valid && evicted.liveness() == Liveness::Occupied {
+                counters.overwrites[DepthClass::of(evicted.depth) as usize]
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+
             unsafe {
                 *self.entry = TTEntry {
-                    tag: Liveness::Occupied as u8,
                     key_low16: self.hash as u16,
-                    depth,
                     best_move,
-                    lower_bound,
-                    upper_bound,
-                }
+                    value,
+                    static_eval,
+                    depth,
+                    tag: TTEntry::make_tag(bound, is_pv),
+                };
+                // a racing save to another entry in the bucket may clobber this extension, but
+                // that can only cause a miss
+                let shift = KEY_EXT_BITS * self.entry.offset_from(self.bucket.cast()) as u32;
+                let key_exts = (*self.bucket).key_ext & !(KEY_EXT_MASK << shift);
+                (*self.bucket).key_ext = key_exts | (key_ext(self.hash) << shift);
             }
         }
     }
 }
 
+#[allow(clippy::cast_possible_truncation)]
+/// Get the key extension for a hash key: the bits just above those stored in `key_low16`.
+const fn key_ext(hash_key: u64) -> u32 {
+    (hash_key >> 16) as u32 & KEY_EXT_MASK
+}
+
 impl TTEntry {
     /// A stand-in value for the depth to which captures only are searched.
     pub const DEPTH_CAPTURES: i8 = -1;
@@ -490,18 +1028,20 @@ mod tests {
     fn guaranteed_hit() {
         let tt = TTable::with_capacity(4);
         let entry = TTEntry {
-            tag: Liveness::Occupied as u8,
             key_low16: 12,
-            depth: 5,
             best_move: Move::normal(Square::E2, Square::E4),
-            lower_bound: Eval::DRAW,
-            upper_bound: Eval::centipawns(100),
+            value: Eval::DRAW,
+            static_eval: Eval::centipawns(100),
+            depth: 5,
+            tag: TTEntry::make_tag(Bound::Lower, false),
         };
         tt.get(12).save(
             entry.depth,
             entry.best_move,
-            entry.lower_bound,
-            entry.upper_bound,
+            entry.value,
+            entry.bound(),
+            entry.static_eval,
+            entry.is_pv(),
         );
 
         assert_eq!(tt.get(12).entry(), Some(&entry));
@@ -512,18 +1052,20 @@ mod tests {
     fn attempt_write_nosize_table() {
         let tt = TTable::new();
         let entry = TTEntry {
-            tag: Liveness::Occupied as u8,
             key_low16: 12,
-            depth: 5,
             best_move: Move::normal(Square::E2, Square::E4),
-            lower_bound: Eval::DRAW,
-            upper_bound: Eval::centipawns(100),
+            value: Eval::DRAW,
+            static_eval: Eval::centipawns(100),
+            depth: 5,
+            tag: TTEntry::make_tag(Bound::Lower, false),
         };
         tt.get(12).save(
             entry.depth,
             entry.best_move,
-            entry.lower_bound,
-            entry.upper_bound,
+            entry.value,
+            entry.bound(),
+            entry.static_eval,
+            entry.is_pv(),
         );
 
         assert_eq!(tt.get(12).entry(), None);
@@ -533,29 +1075,41 @@ mod tests {
     /// Test that an entry with the same hash can overwrite another entry.
     fn overwrite() {
         let e0 = TTEntry {
-            tag: Liveness::Occupied as u8,
             key_low16: 2022,
-            depth: 5,
             best_move: Move::normal(Square::E2, Square::E4),
-            lower_bound: Eval::DRAW,
-            upper_bound: Eval::centipawns(100),
+            value: Eval::DRAW,
+            static_eval: Eval::centipawns(100),
+            depth: 5,
+            tag: TTEntry::make_tag(Bound::Lower, false),
         };
         let e1 = TTEntry {
-            tag: Liveness::Occupied as u8,
             key_low16: 2022,
-            depth: 7,
             best_move: Move::normal(Square::E4, Square::E5),
-            lower_bound: Eval::BLACK_MATE,
-            upper_bound: -Eval::centipawns(100),
+            value: Eval::BLACK_MATE,
+            static_eval: -Eval::centipawns(100),
+            depth: 7,
+            tag: TTEntry::make_tag(Bound::Upper, true),
         };
 
         let tt = TTable::with_capacity(4);
 
-        tt.get(2022)
-            .save(e0.depth, e0.best_move, e0.lower_bound, e0.upper_bound);
+        tt.get(2022).save(
+            e0.depth,
+            e0.best_move,
+            e0.value,
+            e0.bound(),
+            e0.static_eval,
+            e0.is_pv(),
+        );
 
-        tt.get(2022)
-            .save(e1.depth, e1.best_move, e1.lower_bound, e1.upper_bound);
+        tt.get(2022).save(
+            e1.depth,
+            e1.best_move,
+            e1.value,
+            e1.bound(),
+            e1.static_eval,
+            e1.is_pv(),
+        );
 
         assert_eq!(tt.get(2022).entry(), Some(&e1));
     }
@@ -566,27 +1120,290 @@ mod tests {
         let mut tt = TTable::new();
         tt.resize(2000);
         let entry = TTEntry {
-            tag: Liveness::Occupied as u8,
             key_low16: 2022,
-            depth: 5,
             best_move: Move::normal(Square::E2, Square::E4),
-            lower_bound: Eval::DRAW,
-            upper_bound: Eval::centipawns(100),
+            value: Eval::DRAW,
+            static_eval: Eval::centipawns(100),
+            depth: 5,
+            tag: TTEntry::make_tag(Bound::Lower, false),
         };
         tt.get(2022).save(
             entry.depth,
             entry.best_move,
-            entry.lower_bound,
-            entry.upper_bound,
+            entry.value,
+            entry.bound(),
+            entry.static_eval,
+            entry.is_pv(),
         );
 
         assert_eq!(tt.get(2022).entry(), Some(&entry));
     }
 
+    #[test]
+    /// Test that a table large enough to use huge pages is zeroed and usable.
+    fn huge_table() {
+        let mut tt = TTable::with_size(64);
+        assert_ne!(tt.alloc_mode(), AllocMode::Empty);
+        assert_ne!(tt.alloc_mode(), AllocMode::Normal);
+        assert_eq!(tt.fill_rate_permill(), 0);
+
+        let best_move = Move::normal(Square::E2, Square::E4);
+        tt.get(2022).save(
+            5,
+            best_move,
+            Eval::DRAW,
+            Bound::Exact,
+            Eval::centipawns(100),
+            false,
+        );
+        assert_eq!(tt.get(2022).entry().unwrap().best_move, best_move);
+
+        // shrinking below the huge page threshold changes the alignment
+        tt.resize(1);
+        assert_eq!(tt.alloc_mode(), AllocMode::Normal);
+        tt.get(2022).save(
+            5,
+            best_move,
+            Eval::DRAW,
+            Bound::Exact,
+            Eval::centipawns(100),
+            false,
+        );
+        assert_eq!(tt.get(2022).entry().unwrap().best_move, best_move);
+    }
+
+    /// Fill the only bucket of a single-bucket table with the given depths, using the keys 1, 2, and
+    /// so on.
+    fn fill_bucket(tt: &TTable, depths: [i8; BUCKET_LEN]) {
+        for (key, depth) in (1..).zip(depths) {
+            tt.get(key).save(
+                depth,
+                Move::BAD_MOVE,
+                Eval::DRAW,
+                Bound::Exact,
+                Eval::DRAW,
+                false,
+            );
+        }
+    }
+
+    #[test]
+    /// Test that the oldest-first policy evicts deep entries, while the depth-preferred policy keeps
+    /// them.
+    fn depth_preferred_keeps_deep() {
+        let mut depths = [1; BUCKET_LEN];
+        depths[0] = 10;
+
+        let mut tt = TTable::with_capacity(0);
+        fill_bucket(&tt, depths);
+        tt.get(100).save(
+            TTEntry::DEPTH_CAPTURES,
+            Move::BAD_MOVE,
+            Eval::DRAW,
+            Bound::Exact,
+            Eval::DRAW,
+            false,
+        );
+        assert!(tt.get(1).entry().is_none());
+
+        tt.clear();
+        tt.policy = ReplacementPolicy::DepthPreferred;
+        fill_bucket(&tt, depths);
+        tt.get(100).save(
+            TTEntry::DEPTH_CAPTURES,
+            Move::BAD_MOVE,
+            Eval::DRAW,
+            Bound::Exact,
+            Eval::DRAW,
+            false,
+        );
+        assert_eq!(tt.get(1).entry().unwrap().depth, 10);
+        assert!(tt.get(100).entry().is_some());
+    }
+
+    #[test]
+    /// Test that a shallow search does not overwrite a deeper search of the same position under the
+    /// depth-preferred policy.
+    fn depth_preferred_same_key() {
+        let tt = TTable::with_policy(1, ReplacementPolicy::DepthPreferred);
+        tt.get(2022).save(
+            8,
+            Move::BAD_MOVE,
+            Eval::DRAW,
+            Bound::Exact,
+            Eval::DRAW,
+            false,
+        );
+        tt.get(2022).save(
+            TTEntry::DEPTH_CAPTURES,
+            Move::BAD_MOVE,
+            Eval::DRAW,
+            Bound::Exact,
+            Eval::DRAW,
+            false,
+        );
+        assert_eq!(tt.get(2022).entry().unwrap().depth, 8);
+
+        tt.get(2022).save(
+            9,
+            Move::BAD_MOVE,
+            Eval::DRAW,
+            Bound::Exact,
+            Eval::DRAW,
+            false,
+        );
+        assert_eq!(tt.get(2022).entry().unwrap().depth, 9);
+    }
+
+    #[test]
+    /// Test that a two-tier bucket sends shallow entries to its always-replace tier.
+    fn two_tier() {
+        let mut depths = [2; BUCKET_LEN];
+        depths[..DEPTH_TIER_LEN].fill(10);
+
+        let mut tt = TTable::with_capacity(0);
+        tt.policy = ReplacementPolicy::TwoTier;
+        fill_bucket(&tt, depths);
+        tt.get(100).save(
+            1,
+            Move::BAD_MOVE,
+            Eval::DRAW,
+            Bound::Exact,
+            Eval::DRAW,
+            false,
+        );
+
+        for key in 1..=DEPTH_TIER_LEN as u64 {
+            assert_eq!(tt.get(key).entry().unwrap().depth, 10);
+        }
+        assert!(tt.get(100).entry().is_some());
+
+        let stats = tt.stats();
+        assert_eq!(stats.overwrites[DepthClass::Shallow as usize], 1);
+        assert_eq!(stats.overwrites[DepthClass::Deep as usize], 0);
+
+        // a deeper search may take over the depth-preferred tier
+        tt.get(101).save(
+            12,
+            Move::BAD_MOVE,
+            Eval::DRAW,
+            Bound::Exact,
+            Eval::DRAW,
+            false,
+        );
+        assert_eq!(tt.stats().overwrites[DepthClass::Deep as usize], 1);
+    }
+
+    #[test]
+    /// Test that probes, hits, collisions, and occupancy are counted correctly.
+    fn stats() {
+        let mut tt = TTable::with_capacity(0);
+        fill_bucket(&tt, [3; BUCKET_LEN]);
+        tt.age_up(10);
+        assert!(tt.get(1).entry().is_some());
+        assert!(tt.get(100).entry().is_none());
+
+        let stats = tt.stats();
+        assert_eq!(stats.probes, BUCKET_LEN as u64 + 2);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.collisions, 1);
+        assert_eq!(stats.overwrites, [0; DepthClass::NUM]);
+        assert_eq!(stats.occupancy_by_age[0], 0);
+        assert_eq!(stats.occupancy_by_age[1], BUCKET_LEN as u64);
+
+        tt.reset_stats();
+        assert_eq!(tt.stats().probes, 0);
+    }
+
     #[test]
     /// Test that a `Bucket` is in fact the size of a cache line.
     fn bucket_size() {
         assert_eq!(size_of::<Bucket>(), LINE_SIZE);
+        assert_eq!(
+            BUCKET_LEN * size_of::<TTEntry>() + size_of::<u32>(),
+            LINE_SIZE
+        );
+        assert!(BUCKET_LEN * KEY_EXT_BITS as usize <= u32::BITS as usize);
+    }
+
+    #[test]
+    /// Test that entries are packed tightly enough to fit six in a bucket.
+    fn entry_size() {
+        assert_eq!(size_of::<TTEntry>(), 10);
+        assert_eq!(BUCKET_LEN, 6);
+    }
+
+    #[test]
+    /// Test that the fields packed into an entry's tag do not interfere with one another.
+    fn tag_packing() {
+        for bound in [Bound::None, Bound::Upper, Bound::Lower, Bound::Exact] {
+            for is_pv in [false, true] {
+                let mut entry = TTEntry {
+                    key_low16: 0,
+                    best_move: Move::BAD_MOVE,
+                    value: Eval::DRAW,
+                    static_eval: Eval::MIN,
+                    depth: 0,
+                    tag: TTEntry::make_tag(bound, is_pv),
+                };
+                for age in 0..=MAX_AGE {
+                    entry.tag = TTEntry::make_tag(bound, is_pv) + age;
+                    assert_eq!(entry.age(), age);
+                    assert_eq!(entry.bound(), bound);
+                    assert_eq!(entry.is_pv(), is_pv);
+                    assert!(entry.liveness() == Liveness::Occupied);
+                }
+            }
+        }
+    }
+
+    #[test]
+    /// Test that the bounds implied by each bound type are correct.
+    fn bound_values() {
+        let tt = TTable::with_capacity(0);
+        let value = Eval::centipawns(37);
+        for (key, bound, lower, upper) in [
+            (1, Bound::Exact, value, value),
+            (2, Bound::Lower, value, Eval::MAX),
+            (3, Bound::Upper, Eval::MIN, value),
+        ] {
+            tt.get(key)
+                .save(3, Move::BAD_MOVE, value, bound, Eval::DRAW, false);
+            let entry = tt.get(key).entry().copied().unwrap();
+            assert_eq!(entry.lower_bound(), lower);
+            assert_eq!(entry.upper_bound(), upper);
+        }
+    }
+
+    #[test]
+    /// Test that two keys which map to the same bucket are not confused for one another, whether
+    /// they differ in their lower 16 bits or only in the bits above them.
+    fn key_verification() {
+        let tt = TTable::with_capacity(0);
+        tt.get(0x1_2022).save(
+            4,
+            Move::BAD_MOVE,
+            Eval::DRAW,
+            Bound::Exact,
+            Eval::DRAW,
+            false,
+        );
+        assert!(tt.get(0x1_2022).entry().is_some());
+        assert!(tt.get(0x1_2023).entry().is_none());
+        assert!(tt.get(0x2_2022).entry().is_none());
+        assert!(tt.get(0x10_2022).entry().is_none());
+
+        // saving a neighbor in the same bucket keeps the first key's extension intact
+        tt.get(0x2_2022).save(
+            3,
+            Move::BAD_MOVE,
+            Eval::DRAW,
+            Bound::Exact,
+            Eval::DRAW,
+            false,
+        );
+        assert_eq!(tt.get(0x1_2022).entry().map(|e| e.depth), Some(4));
+        assert_eq!(tt.get(0x2_2022).entry().map(|e| e.depth), Some(3));
     }
 
     #[test]
@@ -597,7 +1414,9 @@ mod tests {
             10,
             Move::normal(Square::E2, Square::E4),
             Eval::BLACK_MATE,
+            Bound::Exact,
             Eval::DRAW,
+            false,
         );
 
         tt.age_up(0);
@@ -608,31 +1427,43 @@ mod tests {
     /// Test that aging up a transposition table removes old entries but keeps young ones.
     fn age_up_discrimination() {
         let e0 = TTEntry {
-            tag: Liveness::Occupied as u8,
             key_low16: 2022,
-            depth: 5,
             best_move: Move::normal(Square::E2, Square::E4),
-            lower_bound: Eval::DRAW,
-            upper_bound: Eval::centipawns(100),
+            value: Eval::DRAW,
+            static_eval: Eval::centipawns(100),
+            depth: 5,
+            tag: TTEntry::make_tag(Bound::Lower, false),
         };
         let e1 = TTEntry {
-            tag: Liveness::Occupied as u8,
             key_low16: 2022,
-            depth: 7,
             best_move: Move::normal(Square::E4, Square::E5),
-            lower_bound: Eval::BLACK_MATE,
-            upper_bound: -Eval::centipawns(100),
+            value: Eval::BLACK_MATE,
+            static_eval: -Eval::centipawns(100),
+            depth: 7,
+            tag: TTEntry::make_tag(Bound::Lower, false),
         };
 
         let mut tt = TTable::with_capacity(3);
 
-        tt.get(1)
-            .save(e0.depth, e0.best_move, e0.lower_bound, e0.upper_bound);
+        tt.get(1).save(
+            e0.depth,
+            e0.best_move,
+            e0.value,
+            e0.bound(),
+            e0.static_eval,
+            e0.is_pv(),
+        );
 
         tt.age_up(10);
 
-        tt.get(2)
-            .save(e1.depth, e1.best_move, e1.lower_bound, e1.upper_bound);
+        tt.get(2).save(
+            e1.depth,
+            e1.best_move,
+            e1.value,
+            e1.bound(),
+            e1.static_eval,
+            e1.is_pv(),
+        );
 
         tt.age_up(1);
 
@@ -0,0 +1,1165 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! A complete UCI engine.
+//!
+//! To keep the engine responsive, the work is split across three threads which communicate only by
+//! passing messages:
+//!
+//! * The reader thread reads lines from the GUI and forwards them to the controller.
+//! * The controller (the thread which called `Engine::run`) interprets each line, answering
+//!   anything which does not require searching (such as `isready`) immediately.
+//! * The search thread owns the `MainSearch`, and works through a queue of jobs sent by the
+//!   controller, such as searching a position or resizing the transposition table.
+//!
+//! Since only the search thread ever touches the transposition table, option changes which affect
+//! it are queued behind any running search instead of blocking the controller.
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    io::{BufRead, Write},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex, PoisonError,
+    },
+    thread::scope,
+    time::Duration,
+};
+
+use crate::base::{game::Game, Color, Move};
+use crate::engine::{
+    evaluate::{Eval, EvalWeights},
+    limit::SearchLimit,
+    params::SearchParam,
+    skill::{Skill, MAX_ELO},
+    thread::{MainSearch, SearchConfig},
+    time::{TimeControl, TimeManager},
+    transposition::TTable,
+    SearchError,
+};
+
+use super::{
+    Command, EngineInfo, EngineOption, GoOption, Message, OptionValue, DEFAULT_EVAL_WEIGHTS,
+    DEFAULT_MOVE_OVERHEAD_MS,
+};
+
+/// An event which the controller must respond to.
+enum Event {
+    /// The GUI sent a line.
+    Line(String),
+    /// The GUI's input was exhausted without a `quit` command.
+    Hangup,
+    /// The search thread finished a search and sent its best move.
+    SearchDone,
+}
+
+#[allow(clippy::large_enum_variant)]
+/// A job for the search thread to perform.
+enum Job {
+    /// Search a position.
+    Go {
+        /// The game to search.
+        game: Game,
+        /// The configuration of the search.
+        config: SearchConfig,
+        /// The time manager for the search.
+        time_manager: TimeManager,
+        /// Whether debug mode is on.
+        debug: bool,
+    },
+    /// Resize the transposition table to the given number of megabytes.
+    ResizeHash { size_mb: usize, debug: bool },
+    /// Clear the transposition table.
+    ClearHash,
+}
+
+#[derive(Debug)]
+/// A complete UCI engine, which talks to a GUI through any input and output.
+///
+/// The engine reads commands from `input` and writes its responses, including all information
+/// reported by its searches, to `output`.
+pub struct Engine<R, W> {
+    /// The source of commands from the GUI.
+    input: R,
+    /// The destination of messages to the GUI.
+    output: W,
+    /// The size of the transposition table, in megabytes, before the GUI sets any options.
+    hash_size_mb: usize,
+}
+
+impl<R: BufRead + Send, W: Write + Send + 'static> Engine<R, W> {
+    #[must_use]
+    /// Construct a new engine which will communicate through `input` and `output`.
+    /// The transposition table will start out at `hash_size_mb` megabytes.
+    pub fn new(input: R, output: W, hash_size_mb: usize) -> Engine<R, W> {
+        Engine {
+            input,
+            output,
+            hash_size_mb,
+        }
+    }
+
+    #[allow(clippy::missing_panics_doc)]
+    /// Run the engine until a `quit` command is given or the input is exhausted.
+    /// If the input runs out during a search with a depth, node, or time limit, the search is
+    /// allowed to finish and send its best move first.
+    /// Returns the output once the engine has shut down.
+    pub fn run(self) -> W {
+        let output = Arc::new(Mutex::new(self.output));
+        let (event_tx, event_rx) = channel();
+        let (job_tx, job_rx) = channel();
+
+        let mut searcher = MainSearch::new();
+        searcher.ttable.resize(self.hash_size_mb);
+        searcher.info_sink = output.clone();
+        // let the user know whether we got huge pages for the table
+        send(
+            &output,
+            Message::Info(&[EngineInfo::String(&hash_description(&searcher.ttable))]),
+        );
+
+        let input = self.input;
+        scope(|s| {
+            let search_events = event_tx.clone();
+            s.spawn(move || read_lines(input, &event_tx));
+            let limit = searcher.limit.clone();
+            let output = output.as_ref();
+            s.spawn(move || search_worker(searcher, &job_rx, &search_events, output));
+
+            Controller::new(output, job_tx, event_rx, limit).run();
+            // dropping the controller hangs up on the search thread, stopping it
+        });
+
+        // the search thread has exited, so it no longer holds on to the output
+        match Arc::try_unwrap(output) {
+            Ok(output) => output.into_inner().unwrap_or_else(PoisonError::into_inner),
+            Err(_) => unreachable!("all other handles to the output were dropped"),
+        }
+    }
+}
+
+/// Forward every line from `input` to the controller, stopping after a `quit` command.
+/// If `input` is exhausted or broken, a hangup will be sent instead.
+fn read_lines(mut input: impl BufRead, events: &Sender<Event>) {
+    loop {
+        let mut buf = String::new();
+        match input.read_line(&mut buf) {
+            Ok(0) | Err(_) => {
+                // the GUI hung up on us, so there's nothing left to do
+                let _ = events.send(Event::Hangup);
+                return;
+            }
+            Ok(_) => {
+                let is_quit = buf.trim() == "quit";
+                if events.send(Event::Line(buf)).is_err() || is_quit {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Perform jobs sent by the controller until it hangs up.
+fn search_worker<W: Write>(
+    mut searcher: MainSearch,
+    jobs: &Receiver<Job>,
+    events: &Sender<Event>,
+    output: &Mutex<W>,
+) {
+    for job in jobs {
+        match job {
+            Job::Go {
+                game,
+                config,
+                time_manager,
+                debug,
+            } => {
+                searcher.config = config;
+                searcher.time_manager = time_manager;
+                debug_info(output, "starting evaluation", debug);
+                // the GUI waits for a best move no matter what, so one is always sent, even if it
+                // is the null move because the game is over or the search failed
+                let message = match searcher.evaluate(&game) {
+                    Ok(info) => Message::BestMove {
+                        m: info.pv.first().copied(),
+                        ponder: info.pv.get(1).copied(),
+                    },
+                    Err(e) => {
+                        // search failed :(
+                        // notify the GUI, but there's not much else we can do
+                        error_info(output, &format!("search failed: {e:?}"));
+                        Message::BestMove {
+                            m: None,
+                            ponder: None,
+                        }
+                    }
+                };
+                send(output, message);
+                if debug {
+                    // scanning the table is slow, so only do it when asked
+                    debug_info(
+                        output,
+                        &format!("hash stats: {}", searcher.ttable.stats()),
+                        debug,
+                    );
+                }
+                // clean up after ourselves by aging up the transposition table.
+                // this prevents the table from being polluted with useless entries.
+                // (~30 El0)
+                // when analysing, the next search is likely to be of the same position, so the old
+                // entries are still useful.
+                if !searcher.config.analyse_mode {
+                    searcher.ttable.age_up(searcher.config.tt_max_age);
+                }
+                let _ = events.send(Event::SearchDone);
+            }
+            Job::ResizeHash { size_mb, debug } => {
+                searcher.ttable.resize(size_mb);
+                debug_info(output, &hash_description(&searcher.ttable), debug);
+            }
+            Job::ClearHash => searcher.ttable.clear(),
+        }
+    }
+}
+
+#[allow(clippy::struct_excessive_bools)]
+/// The state of the controller, which interprets commands from the GUI.
+struct Controller<'a, W: Write> {
+    /// The output to the GUI.
+    output: &'a Mutex<W>,
+    /// The queue of jobs for the search thread.
+    jobs: Sender<Job>,
+    /// The events sent from the reader and search threads.
+    events: Receiver<Event>,
+    /// Events which were received while waiting for a search to stop, to be handled later.
+    deferred: VecDeque<Event>,
+    /// The limit of the search thread's searches.
+    limit: Arc<SearchLimit>,
+    /// The current position to search.
+    game: Game,
+    /// Whether we are in debug mode.
+    debug: bool,
+    /// The time reserved for lag between us and the GUI.
+    move_overhead: Duration,
+    /// The configuration for the next search, as set by the GUI's options.
+    config: SearchConfig,
+    /// The skill level set by the GUI.
+    skill_level: Skill,
+    /// Whether the GUI has asked us to limit our strength to `elo`.
+    limit_strength: bool,
+    /// The rating to which the GUI has asked us to limit our strength.
+    elo: u16,
+    /// Whether a search is currently running.
+    searching: bool,
+    /// Whether the most recent search will only end when it is told to stop.
+    endless: bool,
+}
+
+impl<'a, W: Write> Controller<'a, W> {
+    /// Construct a controller in the state of a freshly started engine, which will send jobs to
+    /// `jobs` and handle the events from `events`.
+    fn new(
+        output: &'a Mutex<W>,
+        jobs: Sender<Job>,
+        events: Receiver<Event>,
+        limit: Arc<SearchLimit>,
+    ) -> Controller<'a, W> {
+        Controller {
+            output,
+            jobs,
+            events,
+            deferred: VecDeque::new(),
+            limit,
+            game: Game::new(),
+            debug: false,
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS),
+            config: SearchConfig::new(),
+            skill_level: Skill::FULL,
+            limit_strength: false,
+            elo: MAX_ELO,
+            searching: false,
+            endless: false,
+        }
+    }
+
+    /// Handle events until the GUI tells us to quit.
+    fn run(mut self) {
+        loop {
+            let event = match self.deferred.pop_front() {
+                Some(event) => event,
+                None => match self.events.recv() {
+                    Ok(event) => event,
+                    // everyone else hung up
+                    Err(_) => return,
+                },
+            };
+            match event {
+                Event::Line(line) => {
+                    if !self.handle_line(&line) {
+                        return;
+                    }
+                }
+                Event::Hangup => {
+                    // nobody can tell an endless search to stop anymore, but any other search
+                    // may as well finish and give its move
+                    if self.endless {
+                        self.stop_search();
+                    } else {
+                        self.wait_for_search();
+                    }
+                    return;
+                }
+                Event::SearchDone => self.searching = false,
+            }
+        }
+    }
+
+    /// Handle a single line from the GUI.
+    /// Returns `false` if the engine should quit.
+    fn handle_line(&mut self, line: &str) -> bool {
+        if line.trim().is_empty() {
+            // GUIs may send blank lines, which are harmless
+            return true;
+        }
+        let command = match Command::parse_line(line, self.game.board()) {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                // print out the error to the frontend and continue on to the
+                // next line
+                self.error(&format!("failed to parse line: {e}"));
+                return true;
+            }
+        };
+        match command {
+            Command::Uci => {
+                // identify the engine
+                send(
+                    self.output,
+                    Message::Id {
+                        // we trust that the build script actually did its job
+                        // and created the git hash environment variable
+                        name: Some(concat!("Tomato 0.1.0 (", env!("GIT_HASH"), ")")),
+                        author: Some("Clayton Ramsey"),
+                    },
+                );
+
+                // add options
+                for opt in EngineOption::ALL {
+                    send(self.output, opt.message());
+                }
+
+                send(self.output, Message::UciOk);
+            }
+            Command::Debug(new_debug) => {
+                // activate or deactivate debug mode
+                self.debug = new_debug;
+            }
+            Command::IsReady => {
+                // we were born ready, and the search thread never blocks us
+                send(self.output, Message::ReadyOk);
+            }
+            Command::SetOption { name, value } => self.set_option(&name, value.as_deref()),
+            Command::NewGame => {
+                self.game = Game::new();
+                // stop previous search
+                self.stop_search();
+                // clear the transposititon table
+                self.send_job(Job::ClearHash);
+            }
+            Command::Position { fen, moves } => match setup_game(fen.as_deref(), &moves) {
+                Ok(game) => {
+                    self.game = game;
+                    self.debug_info(&format!("current game: {}", self.game.board()));
+                }
+                Err(e) => self.error(&format!("{e}; keeping the previous position")),
+            },
+            Command::Go(opts) => {
+                debug_info(self.output, "go command received", self.debug);
+                // only one search may run at a time
+                self.stop_search();
+                self.go(&opts);
+            }
+            Command::Stop => {
+                // the search thread will send the best move when it stops
+                self.debug_info("now stopping search");
+                self.limit.stop();
+            }
+            Command::PonderHit => self.error("pondering is not supported"),
+            Command::Quit => {
+                // stop the ongoing search
+                self.stop_search();
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Execute a UCI `go` command, sending a new search job to the search thread.
+    /// This function has been broken out for readability.
+    fn go(&mut self, opts: &[GoOption]) {
+        // time remaining for players
+        let (mut wtime, mut btime) = (None, None);
+
+        // increments. by default assumed to be zero
+        let (mut winc, mut binc) = (0, 0);
+
+        // number of moves until increment achieved. if `None`, there is no increment.
+        let mut movestogo = None;
+
+        let mut infinite = false; // whether to search infinitely
+
+        let mut movetime = None;
+
+        let mut nodes_cap = None;
+
+        // by default, set the depth to search to be 99, so that the timer is the sole limiting
+        // factor
+        let mut depth = 99;
+        for opt in opts {
+            match opt {
+                GoOption::SearchMoves(_) => {
+                    self.error("searching move subsets is not supported");
+                }
+                GoOption::Ponder => {
+                    infinite = true;
+                }
+                &GoOption::WhiteTime(time) => {
+                    wtime = Some(time);
+                }
+                &GoOption::BlackTime(time) => {
+                    btime = Some(time);
+                }
+                &GoOption::WhiteInc(inc) => {
+                    winc = inc;
+                }
+                &GoOption::BlackInc(inc) => {
+                    binc = inc;
+                }
+                GoOption::MovesToGo(n) => {
+                    movestogo = Some(*n);
+                }
+                &GoOption::Depth(d) => {
+                    depth = d;
+                }
+                &GoOption::Nodes(num) => {
+                    nodes_cap = Some(num);
+                }
+                GoOption::Mate(_) => self.error("mate search is not supported"),
+                &GoOption::MoveTime(msecs) => {
+                    movetime = Some(Duration::from_millis(u64::from(msecs)));
+                }
+                GoOption::Infinite => {
+                    // on an infinite search, we will go as deep as we want
+                    // 99 is basically infinite in exponential growth
+                    depth = 99;
+                    infinite = true;
+                }
+            }
+        }
+
+        // a weakened engine searches shallowly, and picks its move with a fresh seed every time
+        let skill = self.skill();
+        if skill.is_limited() {
+            depth = depth.min(skill.depth());
+            nodes_cap = Some(nodes_cap.map_or(skill.nodes(), |n| n.min(skill.nodes())));
+        }
+
+        let (increment, remaining) = match self.game.board().player {
+            Color::White => (winc, wtime),
+            Color::Black => (binc, btime),
+        };
+        let increment = Duration::from_millis(u64::from(increment));
+        let control = if infinite {
+            TimeControl::Infinite
+        } else if let Some(mt) = movetime {
+            TimeControl::MoveTime(mt)
+        } else if let Some(rem) = remaining {
+            let remaining = Duration::from_millis(u64::from(rem));
+            match movestogo {
+                Some(moves_to_go) => TimeControl::Cyclic {
+                    remaining,
+                    increment,
+                    moves_to_go,
+                },
+                None => TimeControl::Incremental {
+                    remaining,
+                    increment,
+                },
+            }
+        } else {
+            TimeControl::Infinite
+        };
+        let time_manager = TimeManager::new(control, self.move_overhead);
+        self.debug_info(&format!(
+            "search time: soft {:?}, hard {:?}",
+            time_manager.soft_limit(),
+            time_manager.hard_limit()
+        ));
+
+        // configure the limit here rather than in the search thread, so that a `stop` sent right
+        // after this `go` cannot be lost
+        if let Err(e) = self.start_limit(nodes_cap, time_manager.hard_limit()) {
+            self.error(&format!("could not start search: {e:?}"));
+            return;
+        }
+
+        self.send_job(Job::Go {
+            game: self.game.clone(),
+            config: SearchConfig {
+                depth,
+                skill,
+                seed: fastrand::u64(..),
+                ..self.config.clone()
+            },
+            time_manager,
+            debug: self.debug,
+        });
+        self.searching = true;
+        self.endless =
+            matches!(control, TimeControl::Infinite) && nodes_cap.is_none() && 99 <= depth;
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    /// Set the value of an option, as requested by the GUI.
+    fn set_option(&mut self, name: &str, value: Option<&str>) {
+        let Some(opt) = EngineOption::from_name(name) else {
+            // search parameters are hidden options, since they are only meant for tuning
+            if let Err(e) = SearchParam::set_option(&mut self.config, name, value) {
+                self.error(&e);
+            }
+            return;
+        };
+        let value = match opt.parse_value(value) {
+            Ok(value) => value,
+            Err(e) => {
+                self.error(&e);
+                return;
+            }
+        };
+
+        // values have been checked against their option's bounds, so the casts below are safe
+        match (opt, value) {
+            (EngineOption::Hash, OptionValue::Spin(size_mb)) => {
+                // the search thread will get to it once it's done searching
+                self.send_job(Job::ResizeHash {
+                    size_mb: size_mb as usize,
+                    debug: self.debug,
+                });
+            }
+            (EngineOption::Threads, OptionValue::Spin(n_threads)) => {
+                self.config.n_helpers = n_threads as usize - 1;
+            }
+            (EngineOption::ClearHash, _) => self.send_job(Job::ClearHash),
+            (EngineOption::MoveOverhead, OptionValue::Spin(overhead_ms)) => {
+                self.move_overhead = Duration::from_millis(overhead_ms as u64);
+            }
+            (EngineOption::AnalyseMode, OptionValue::Check(analyse_mode)) => {
+                self.config.analyse_mode = analyse_mode;
+            }
+            (EngineOption::ShowWdl, OptionValue::Check(show_wdl)) => {
+                self.config.show_wdl = show_wdl;
+            }
+            (EngineOption::LimitStrength, OptionValue::Check(limit_strength)) => {
+                self.limit_strength = limit_strength;
+            }
+            (EngineOption::Elo, OptionValue::Spin(elo)) => self.elo = elo as u16,
+            (EngineOption::SkillLevel, OptionValue::Spin(level)) => {
+                self.skill_level = Skill::new(level as u8);
+            }
+            (EngineOption::Contempt, OptionValue::Spin(contempt_cp)) => {
+                self.config.contempt = Eval::centipawns(contempt_cp as i16);
+            }
+            (EngineOption::RandomizeDraws, OptionValue::Check(randomize_draws)) => {
+                self.config.randomize_draws = randomize_draws;
+            }
+            (EngineOption::EvalWeights, OptionValue::String(path)) => {
+                self.config.weights = Arc::new(EvalWeights::DEFAULT);
+                if path.is_empty() || path == DEFAULT_EVAL_WEIGHTS {
+                    return;
+                }
+                match EvalWeights::load(&path) {
+                    // a search which is still running keeps its own reference to the old weights
+                    Ok(weights) => self.config.weights = Arc::new(weights),
+                    Err(e) => self.error(&format!("{e}; using default weights")),
+                }
+            }
+            (opt, value) => unreachable!("value {value:?} does not match type of option {opt:?}"),
+        }
+    }
+
+    /// Get the skill at which the next search should play.
+    /// If the GUI has limited our strength, we play at the weaker of the skill level and the
+    /// limited rating.
+    fn skill(&self) -> Skill {
+        if self.limit_strength {
+            self.skill_level.min(Skill::from_elo(self.elo))
+        } else {
+            self.skill_level
+        }
+    }
+
+    /// Reset the search limit for a new search with the given node cap and duration, and start
+    /// its timer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the limit's locks were poisoned.
+    fn start_limit(
+        &self,
+        nodes_cap: Option<u64>,
+        duration: Option<Duration>,
+    ) -> Result<(), SearchError> {
+        *self.limit.nodes_cap.write()? = nodes_cap;
+        *self.limit.search_duration.lock()? = duration;
+        self.limit.start()
+    }
+
+    /// Notify any active search to stop, and then block until it has stopped.
+    /// Any lines received from the GUI in the meantime will be handled afterward.
+    fn stop_search(&mut self) {
+        if !self.searching {
+            return;
+        }
+        self.debug_info("now stopping search");
+        self.limit.stop();
+        self.wait_for_search();
+        self.debug_info("search stopped");
+    }
+
+    /// Block until the active search, if any, has finished.
+    /// Any lines received from the GUI in the meantime will be handled afterward.
+    fn wait_for_search(&mut self) {
+        while self.searching {
+            match self.events.recv() {
+                Ok(Event::SearchDone) | Err(_) => self.searching = false,
+                Ok(event) => self.deferred.push_back(event),
+            }
+        }
+    }
+
+    /// Send a job to the search thread.
+    fn send_job(&self, job: Job) {
+        if self.jobs.send(job).is_err() {
+            self.error("search thread has died");
+        }
+    }
+
+    /// Inform the GUI of an error.
+    /// Errors are always sent, even outside of debug mode.
+    fn error(&self, s: &str) {
+        error_info(self.output, s);
+    }
+
+    /// Print out a debug info message to the GUI.
+    /// Will have no effect if we are not in debug mode.
+    fn debug_info(&self, s: &str) {
+        debug_info(self.output, s, self.debug);
+    }
+}
+
+/// Send a message to the GUI.
+fn send(output: &Mutex<impl Write>, message: impl Display) {
+    // a panic while printing can't leave the output in a state that matters to us
+    let mut output = output.lock().unwrap_or_else(PoisonError::into_inner);
+    // if the GUI has hung up, there's nobody left to complain to
+    let _ = writeln!(output, "{message}").and_then(|()| output.flush());
+}
+
+/// Print out a debug info message to the GUI.
+/// Will have no effect if `debug` is `false`.
+fn debug_info(output: &Mutex<impl Write>, s: &str, debug: bool) {
+    if debug {
+        send(output, Message::Info(&[EngineInfo::String(s)]));
+    }
+}
+
+/// Inform the GUI of an error.
+fn error_info(output: &Mutex<impl Write>, s: &str) {
+    send(
+        output,
+        Message::Info(&[EngineInfo::String(&format!("error: {s}"))]),
+    );
+}
+
+/// Set up a game from a starting FEN (or the start position, if `fen` is `None`) and a sequence
+/// of moves played from it.
+///
+/// # Errors
+///
+/// This function will return an error if the FEN is malformed or any of the moves are illegal.
+fn setup_game(fen: Option<&str>, moves: &[Move]) -> Result<Game, String> {
+    let mut game = match fen {
+        None => Game::new(),
+        Some(fen) => Game::from_fen(fen).map_err(|e| format!("invalid FEN `{fen}`: {e}"))?,
+    };
+    for &m in moves {
+        game.try_move(m)
+            .map_err(|()| format!("illegal move {m} in position {}", game.board()))?;
+    }
+
+    Ok(game)
+}
+
+/// Describe the size of a transposition table and how its memory was allocated.
+fn hash_description(ttable: &TTable) -> String {
+    format!(
+        "hash table: {} MB, {}",
+        ttable.size_mb(),
+        ttable.alloc_mode()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::{movegen::GenMode, Piece, Square};
+    use crate::engine::evaluate::Score;
+    use std::{env, fs};
+
+    /// Run the engine on a scripted session with a small hash table, and collect its output
+    /// lines.
+    fn session(script: &str) -> Vec<String> {
+        let output = Engine::new(script.as_bytes(), Vec::new(), 1).run();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    /// Find the index of the first line of output starting with `prefix`.
+    fn position_of(lines: &[String], prefix: &str) -> Option<usize> {
+        lines.iter().position(|l| l.starts_with(prefix))
+    }
+
+    /// Extract the best move from a session's output, and check that it is legal in `g`.
+    fn assert_legal_bestmove(lines: &[String], g: &Game) {
+        let line = &lines[position_of(lines, "bestmove").expect("no bestmove sent")];
+        let m_str = line.split_ascii_whitespace().nth(1).unwrap();
+        let m = Move::from_uci(m_str, g.board()).unwrap();
+        assert!(g.get_moves::<{ GenMode::All }>().contains(&m));
+    }
+
+    #[test]
+    /// Test that the engine identifies itself and lists its options before `uciok`.
+    fn handshake() {
+        let lines = session("uci\nquit\n");
+        let id = position_of(&lines, "id name Tomato").unwrap();
+        let hash = position_of(&lines, "option name Hash").unwrap();
+        let ok = position_of(&lines, "uciok").unwrap();
+        assert!(id < hash && hash < ok);
+    }
+
+    #[test]
+    /// Test that the engine shuts down cleanly when its input ends without a `quit`.
+    fn eof_quits() {
+        let lines = session("isready\n");
+        assert!(position_of(&lines, "readyok").is_some());
+    }
+
+    #[test]
+    /// Test that an endless search is stopped, and still gives a move, when the input ends.
+    fn eof_stops_endless_search() {
+        let lines = session("position startpos\ngo infinite\n");
+        assert_legal_bestmove(&lines, &Game::new());
+    }
+
+    #[test]
+    /// Test that `isready` is answered while a search is running, instead of after it.
+    fn isready_during_search() {
+        let lines = session("position startpos\ngo infinite\nisready\nstop\nquit\n");
+        let ready = position_of(&lines, "readyok").unwrap();
+        let best = position_of(&lines, "bestmove").unwrap();
+        assert!(ready < best);
+        assert_legal_bestmove(&lines, &Game::new());
+    }
+
+    #[test]
+    /// Test that changing the hash size during a search neither blocks nor breaks the search.
+    fn setoption_during_search() {
+        let lines = session(
+            "position startpos moves e2e4\ngo infinite\nsetoption name Hash value 2\nisready\nstop\n\
+             go depth 3\nquit\n",
+        );
+        let mut g = Game::new();
+        g.make_move(Move::normal(Square::E2, Square::E4));
+        assert!(position_of(&lines, "readyok").is_some());
+        assert_eq!(
+            lines.iter().filter(|l| l.starts_with("bestmove")).count(),
+            2
+        );
+        assert_legal_bestmove(&lines, &g);
+    }
+
+    #[test]
+    /// Test that a second `go` while searching stops the first search instead of running both.
+    fn go_while_searching() {
+        let lines = session("go infinite\ngo depth 2\nquit\n");
+        assert_eq!(
+            lines.iter().filter(|l| l.starts_with("bestmove")).count(),
+            2
+        );
+    }
+
+    #[test]
+    /// Test that every option offered in the handshake is listed before `uciok`.
+    fn handshake_lists_options() {
+        let lines = session("uci\nquit\n");
+        let ok = position_of(&lines, "uciok").unwrap();
+        for opt in EngineOption::ALL {
+            let listing = position_of(&lines, &opt.message().to_string()).unwrap();
+            assert!(listing < ok);
+        }
+    }
+
+    #[test]
+    /// Test that a search with several threads still gives a legal move.
+    fn threads() {
+        let lines = session("setoption name Threads value 4\nposition startpos\ngo depth 5\n");
+        assert!(position_of(&lines, "info string error").is_none());
+        assert_legal_bestmove(&lines, &Game::new());
+    }
+
+    #[test]
+    /// Test that win/draw/loss estimates are only reported once they are asked for.
+    fn show_wdl() {
+        let lines = session("go depth 2\nsetoption name UCI_ShowWDL value true\ngo depth 2\n");
+        let second_search = position_of(&lines, "bestmove").unwrap();
+        assert!(lines[..second_search].iter().all(|l| !l.contains(" wdl ")));
+        assert!(lines[second_search..].iter().any(|l| l.contains(" wdl ")));
+    }
+
+    #[test]
+    /// Test that the remaining options are accepted, and that bad values for them are not.
+    fn other_options() {
+        let lines = session(
+            "setoption name Clear Hash\nsetoption name UCI_AnalyseMode value true\n\
+             setoption name MoveOverhead value 100\nisready\n",
+        );
+        assert!(position_of(&lines, "info string error").is_none());
+        let lines = session(
+            "setoption name UCI_AnalyseMode value maybe\nsetoption name Threads value 0\n\
+             setoption name MoveOverhead\n",
+        );
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|l| l.starts_with("info string error"))
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    /// Test that search parameters can be set, but are not listed to the GUI.
+    fn hidden_search_params() {
+        let lines = session("uci\nquit\n");
+        for param in SearchParam::ALL {
+            assert!(lines.iter().all(|l| !l.contains(param.name())));
+        }
+
+        let output = Mutex::new(Vec::new());
+        let (jobs, _) = channel();
+        let (_, events) = channel();
+        let mut controller = Controller::new(&output, jobs, events, Arc::new(SearchLimit::new()));
+        // names are case-insensitive, like those of the listed options
+        assert!(controller.handle_line("setoption name AspirationNarrow value 25"));
+        assert!(controller.handle_line("setoption name lmrearlymoves value 6"));
+        assert_eq!(SearchParam::AspirationNarrow.get(&controller.config), 25);
+        assert_eq!(SearchParam::LmrEarlyMoves.get(&controller.config), 6);
+        assert!(output.lock().unwrap().is_empty());
+
+        // the largest age of a transposition table entry is 15, so 16 is out of range
+        let (_, max_age) = SearchParam::TtMaxAge.bounds();
+        assert_eq!(max_age, 15);
+        assert!(controller.handle_line("setoption name TtMaxAge value 16"));
+        assert_eq!(
+            SearchParam::TtMaxAge.get(&controller.config),
+            SearchParam::TtMaxAge.default_value()
+        );
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.starts_with("info string error"));
+
+        let lines = session(
+            "setoption name AspirationNarrow value 25\nsetoption name LmrEarlyMoves value 6\n\
+             position startpos\ngo depth 3\n",
+        );
+        assert!(position_of(&lines, "info string error").is_none());
+        assert!(position_of(&lines, "bestmove").is_some());
+    }
+
+    #[test]
+    /// Test that a weakened engine searches no deeper than its skill allows, but still plays a
+    /// legal move.
+    fn skill_level() {
+        for script in [
+            "setoption name Skill Level value 0\nposition startpos\ngo depth 20\n",
+            "setoption name UCI_LimitStrength value true\nsetoption name UCI_Elo value 800\n\
+             position startpos\ngo depth 20\n",
+        ] {
+            let lines = session(script);
+            assert!(position_of(&lines, "info string error").is_none());
+            assert!(position_of(&lines, "info depth 1 ").is_some());
+            assert!(position_of(&lines, "info depth 2 ").is_none());
+            assert_legal_bestmove(&lines, &Game::new());
+        }
+
+        // the rating is ignored unless strength is limited
+        let lines = session("setoption name UCI_Elo value 800\nposition startpos\ngo depth 2\n");
+        assert!(position_of(&lines, "info depth 2 ").is_some());
+
+        let lines =
+            session("setoption name UCI_Elo value 100\nsetoption name Skill Level value 21\n");
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|l| l.starts_with("info string error"))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    /// Test that contempt decides whether the engine takes a threefold repetition.
+    fn contempt() {
+        let moves = "position startpos moves g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1\n";
+        let lines = session(&format!(
+            "setoption name Contempt value -100\n{moves}go depth 4\n"
+        ));
+        assert!(position_of(&lines, "bestmove f6g8").is_some());
+        let lines = session(&format!(
+            "setoption name Contempt value 100\nsetoption name RandomizeDraws value true\n\
+             {moves}go depth 4\n"
+        ));
+        assert!(position_of(&lines, "info string error").is_none());
+        assert!(position_of(&lines, "bestmove").is_some());
+        assert!(position_of(&lines, "bestmove f6g8").is_none());
+    }
+
+    #[test]
+    /// Test that evaluation weights can be loaded from a file, and that a missing file falls back
+    /// to the default weights.
+    fn eval_weights() {
+        let mut weights = EvalWeights::DEFAULT;
+        weights.material[Piece::Queen as usize] = Score::centipawns(5000, 5000);
+        let path = env::temp_dir().join(format!("tomato-weights-{}.txt", std::process::id()));
+        fs::write(&path, weights.to_string()).unwrap();
+        let lines = session(&format!(
+            "setoption name EvalWeights value {}\nposition fen 4k3/8/8/8/8/8/8/3QK3 w - - 0 1\n\
+            go depth 1\n",
+            path.display()
+        ));
+        assert!(position_of(&lines, "info string error").is_none());
+        let info = &lines[position_of(&lines, "info depth 1 ").unwrap()];
+        let mut tokens = info.split_whitespace();
+        tokens.find(|&t| t == "cp").unwrap();
+        let cp: i16 = tokens.next().unwrap().parse().unwrap();
+        // the default queen is worth about a fifth as much
+        assert!((4500..5500).contains(&cp), "{info}");
+
+        // weights with no midgame are rejected rather than crashing the search
+        fs::write(&path, "eg_limit 3000\n").unwrap();
+        let lines = session(&format!(
+            "setoption name EvalWeights value {}\nposition startpos\ngo depth 2\n",
+            path.display()
+        ));
+        fs::remove_file(&path).unwrap();
+        assert!(position_of(&lines, "info string error").is_some());
+        assert!(position_of(&lines, "bestmove").is_some());
+
+        let lines = session(
+            "setoption name EvalWeights value /nonexistent/weights.txt\n\
+            setoption name EvalWeights value <default>\nposition startpos\ngo depth 1\n",
+        );
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|l| l.starts_with("info string error"))
+                .count(),
+            1
+        );
+        assert!(position_of(&lines, "bestmove").is_some());
+    }
+
+    #[test]
+    /// Test that a fixed-depth search reports each completed depth before giving its best move.
+    fn go_depth_reports_info() {
+        let lines = session("position startpos\ngo depth 3\n");
+        let best = position_of(&lines, "bestmove").unwrap();
+        for depth in 1..=3 {
+            let info = position_of(&lines, &format!("info depth {depth} ")).unwrap();
+            assert!(info < best);
+        }
+        assert_legal_bestmove(&lines, &Game::new());
+    }
+
+    #[test]
+    /// Test that a search of a position which is already over gives the null move right away,
+    /// instead of searching it again and again or never answering.
+    fn go_without_moves() {
+        for fen in [
+            // checkmate
+            "7k/6Q1/6K1/8/8/8/8/8 b - - 0 1",
+            // stalemate
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+        ] {
+            for go in ["go movetime 100", "go depth 5", "go infinite\nstop"] {
+                let lines = session(&format!("position fen {fen}\n{go}\n"));
+                assert!(position_of(&lines, "info depth").is_none(), "{fen}: {go}");
+                assert_eq!(
+                    lines.iter().filter(|l| l.starts_with("bestmove")).count(),
+                    1
+                );
+                assert!(lines.contains(&"bestmove 0000".to_string()), "{fen}: {go}");
+            }
+        }
+    }
+
+    #[test]
+    /// Test that the moves given in a `position` command are played before searching.
+    fn position_with_moves() {
+        let lines =
+            session("position fen 7k/8/8/8/8/8/8/K5R1 w - - 0 1 moves g1g2 h8h7\ngo depth 2\n");
+        let mut g = Game::from_fen("7k/8/8/8/8/8/8/K5R1 w - - 0 1").unwrap();
+        g.make_move(Move::normal(Square::G1, Square::G2));
+        g.make_move(Move::normal(Square::H8, Square::H7));
+        assert_legal_bestmove(&lines, &g);
+    }
+
+    #[test]
+    /// Test that debug messages are only sent after debug mode has been turned on.
+    fn debug_mode() {
+        let lines = session("position startpos\ndebug on\nposition startpos moves e2e4\n");
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|l| l.starts_with("info string current game"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    /// Test that errors are reported even outside of debug mode.
+    fn errors_reported() {
+        let lines = session("setoption name Foo value 1\nsetoption name Hash value 1000000000\n");
+        assert!(position_of(&lines, "info string error: unknown option key `Foo`").is_some());
+        assert!(position_of(
+            &lines,
+            "info string error: value `1000000000` for option `Hash`"
+        )
+        .is_some());
+    }
+
+    #[test]
+    /// Test that a malformed `position` command is reported, and the previous position is kept.
+    fn bad_position_keeps_previous() {
+        let mut g = Game::new();
+        g.make_move(Move::normal(Square::E2, Square::E4));
+        for bad in [
+            "position fen 8/8/8/8/8/8/8/8 w - - 0 1",
+            "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq",
+            "position fen 9/9/9/9/9/9/9/9/9 w - - 0 1",
+            "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+            "position startpos moves e2e5",
+            "position startpos moves e7e5",
+            "position startpos moves e2e4 e7e5 e1g1",
+            "position startpos moves e2",
+            "position startpos moves é2é4",
+            "position",
+        ] {
+            let lines = session(&format!(
+                "position startpos moves e2e4\n{bad}\ngo depth 1\n"
+            ));
+            assert!(position_of(&lines, "info string error").is_some(), "{bad}");
+            assert_legal_bestmove(&lines, &g);
+        }
+    }
+
+    /// Generate a line of random garbage, biased toward tokens that appear in UCI commands.
+    fn garbage_line(rng: &fastrand::Rng) -> String {
+        const TOKENS: [&str; 24] = [
+            "uci",
+            "isready",
+            "setoption",
+            "name",
+            "value",
+            "Hash",
+            "position",
+            "startpos",
+            "fen",
+            "moves",
+            "e2e4",
+            "e7e5",
+            "g1f3",
+            "e1g1",
+            "a7a8q",
+            "go",
+            "depth",
+            "nodes",
+            "wtime",
+            "movestogo",
+            "debug",
+            "8/8/8",
+            "w",
+            "-",
+        ];
+        let mut line = String::new();
+        for _ in 0..rng.usize(0..12) {
+            match rng.u8(0..4) {
+                0 => line.extend((0..rng.usize(1..6)).map(|_| rng.char(..))),
+                1 => line += &rng.i64(..).to_string(),
+                _ => line += TOKENS[rng.usize(..TOKENS.len())],
+            }
+            line.push(' ');
+        }
+
+        line.replace(['\n', '\r'], " ")
+    }
+
+    #[test]
+    /// Test that the engine survives a session of random garbage, and still responds afterward.
+    fn garbage_session() {
+        let rng = fastrand::Rng::with_seed(37);
+        for _ in 0..20 {
+            let mut script = String::new();
+            for _ in 0..50 {
+                // searches could take arbitrarily long, so leave them out
+                let line = garbage_line(&rng);
+                if !line.trim_start().starts_with("go") {
+                    script += &line;
+                    script.push('\n');
+                }
+            }
+            script += "isready\n";
+            let lines = session(&script);
+            assert!(position_of(&lines, "readyok").is_some());
+        }
+    }
+
+    #[test]
+    /// Test that a `stop` with no search running, and an unparsable line, are both ignored.
+    fn harmless_commands() {
+        let lines = session("stop\nfoo bar\nisready\nquit\n");
+        assert!(position_of(&lines, "readyok").is_some());
+        assert!(position_of(&lines, "bestmove").is_none());
+    }
+}
@@ -26,13 +26,23 @@
 //!
 //! `UciCommand` describes all the messages that can be received for a UCI engine.
 //! Meanwhile, `UciMessage` describes all the messages that the engine can send back to the GUI.
+//! `Engine` ties the two together, running a complete UCI session over any input and output.
 //!
 //! For a full specification of the UCI standard, see [here](https://backscattering.de/).
 
+mod engine;
+mod options;
 mod send;
-use crate::base::{Board, Move};
+use crate::base::{
+    movegen::{get_moves, GenMode},
+    Board, Move,
+};
 
-pub use send::{EngineInfo, Message, OptionType};
+pub use engine::Engine;
+pub use options::{
+    EngineOption, OptionValue, DEFAULT_EVAL_WEIGHTS, DEFAULT_HASH_SIZE_MB, DEFAULT_MOVE_OVERHEAD_MS,
+};
+pub use send::{EngineInfo, InfoSink, Message, NullSink, OptionType, StdoutSink};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 /// An enum representing the set of all commands that the GUI can send to the engine via UCI.
@@ -207,7 +217,7 @@ impl Command {
                 return Ok(Command::SetOption {
                     name: key,
                     value: Some(value),
-                })
+                });
             };
 
             if !value.is_empty() {
@@ -263,13 +273,13 @@ impl Command {
         )?;
 
         let mut moves = Vec::new();
-        if let Some(m_tok) = next_move_tok {
-            let m = Move::from_uci(m_tok, &board)?;
-            board.make_move(m);
-            moves.push(m);
-        }
-        for tok in tokens {
+        for tok in next_move_tok.into_iter().chain(tokens) {
             let m = Move::from_uci(tok, &board)?;
+            // `Move::from_uci` makes no attempt to check legality, so a malformed move could
+            // otherwise corrupt the board
+            if !get_moves::<{ GenMode::All }>(&board).contains(&m) {
+                return Err(format!("illegal move `{tok}` in position {board}"));
+            }
             board.make_move(m);
             moves.push(m);
         }
@@ -305,7 +315,9 @@ impl Command {
                     // keyword
                     loop {
                         let Some(m_tok) = peeks.peek() else { break };
-                        let Ok(m) = Move::from_uci(m_tok, board) else { break };
+                        let Ok(m) = Move::from_uci(m_tok, board) else {
+                            break;
+                        };
                         moves.push(m);
                         // consume the token that we peeked
                         peeks.next();
@@ -335,7 +347,7 @@ impl Command {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::base::Square;
+    use crate::base::{fuzz::mutate, Square};
 
     #[test]
     /// Test that an ordinary "startpos" UCI position command is parsed  correctly.
@@ -514,4 +526,36 @@ mod tests {
             Ok(Command::Debug(false))
         );
     }
+
+    #[test]
+    /// Test that parsing lines of random characters never panics.
+    fn garbage_chars() {
+        let rng = fastrand::Rng::with_seed(2023);
+        let board = Board::default();
+        for _ in 0..10_000 {
+            let line: String = (0..rng.usize(0..40)).map(|_| rng.char(..)).collect();
+            let _ = Command::parse_line(&line, &board);
+        }
+    }
+
+    #[test]
+    /// Test that parsing valid commands with random characters swapped, inserted, or deleted never
+    /// panics.
+    fn mutated_commands() {
+        const COMMANDS: [&str; 6] = [
+            "position fen rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1 moves e7e5",
+            "position startpos moves e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1g1",
+            "position fen 4k3/1P6/8/8/8/8/8/4K2R w K - 0 1 moves b7b8q",
+            "go wtime 1000 btime 1000 winc 10 binc 10 movestogo 5",
+            "go searchmoves e2e4 d2d4 depth 5 nodes 100",
+            "setoption name Hash value 16",
+        ];
+        const ALPHABET: &[u8] = b" /0123456789abcdefghknpqrwxKQRBNP-";
+        let rng = fastrand::Rng::with_seed(37);
+        let board = Board::default();
+        for _ in 0..10_000 {
+            let line = mutate(COMMANDS[rng.usize(..COMMANDS.len())], ALPHABET, &rng);
+            let _ = Command::parse_line(&line, &board);
+        }
+    }
 }
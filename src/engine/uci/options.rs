@@ -0,0 +1,304 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! The options which the engine exposes to the GUI.
+//!
+//! Every option is described exactly once, by `EngineOption`.
+//! Both the list of options sent in response to a `uci` command and the validation of values
+//! given by `setoption` are derived from that description, so the two can never disagree.
+
+use crate::engine::skill::{MAX_ELO, MAX_LEVEL, MIN_ELO};
+
+use super::{Message, OptionType};
+
+/// The default size of the transposition table, in megabytes.
+pub const DEFAULT_HASH_SIZE_MB: usize = 500;
+
+/// The largest transposition table size, in megabytes, which the GUI may ask for.
+const MAX_HASH_SIZE_MB: i64 = 128_000; // not my problem if you OOM your computer
+
+/// The largest number of threads which the GUI may ask for.
+const MAX_THREADS: i64 = 256;
+
+/// The default time, in milliseconds, reserved for communication lag with the GUI on every move.
+pub const DEFAULT_MOVE_OVERHEAD_MS: u64 = 30;
+
+/// The largest move overhead, in milliseconds, which the GUI may ask for.
+const MAX_MOVE_OVERHEAD_MS: i64 = 5_000;
+
+/// The largest contempt, in centipawns, in either direction which the GUI may ask for.
+const MAX_CONTEMPT_CP: i64 = 100;
+
+/// The value of the `EvalWeights` option which selects the compiled-in evaluation weights.
+pub const DEFAULT_EVAL_WEIGHTS: &str = "<default>";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// An option which the GUI may configure.
+pub enum EngineOption {
+    /// The size of the transposition table, in megabytes.
+    Hash,
+    /// The number of threads to search with.
+    Threads,
+    /// Clear the transposition table.
+    ClearHash,
+    /// The time, in milliseconds, reserved for lag between the engine and the GUI on every move.
+    MoveOverhead,
+    /// Whether the engine is being used to analyse positions rather than to play a game.
+    AnalyseMode,
+    /// Whether the engine should report win/draw/loss estimates along with its evaluations.
+    ShowWdl,
+    /// Whether the engine should weaken itself to the rating given by `Elo`.
+    LimitStrength,
+    /// The rating at which the engine should play when its strength is limited.
+    Elo,
+    /// The skill level at which the engine should play, where the highest level is full
+    /// strength.
+    SkillLevel,
+    /// How much worse than an even position a draw is for the engine, in centipawns.
+    Contempt,
+    /// Whether the scores of drawn positions should be randomly perturbed.
+    RandomizeDraws,
+    /// The path of a file of evaluation weights to use instead of the compiled-in ones.
+    EvalWeights,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+/// A value given to an option by the GUI, which has been checked against the option's type.
+pub enum OptionValue {
+    /// The value of a spin option, which is guaranteed to be within the option's bounds.
+    Spin(i64),
+    /// The value of a check option.
+    Check(bool),
+    /// The value of a string option.
+    String(String),
+    /// A button was pressed.
+    Button,
+}
+
+impl EngineOption {
+    /// Every option which the engine supports, in the order in which they are listed to the GUI.
+    pub const ALL: [EngineOption; 12] = [
+        EngineOption::Hash,
+        EngineOption::Threads,
+        EngineOption::ClearHash,
+        EngineOption::MoveOverhead,
+        EngineOption::AnalyseMode,
+        EngineOption::ShowWdl,
+        EngineOption::LimitStrength,
+        EngineOption::Elo,
+        EngineOption::SkillLevel,
+        EngineOption::Contempt,
+        EngineOption::RandomizeDraws,
+        EngineOption::EvalWeights,
+    ];
+
+    #[must_use]
+    /// Get the name of this option, as it is shown to the GUI.
+    pub const fn name(self) -> &'static str {
+        match self {
+            EngineOption::Hash => "Hash",
+            EngineOption::Threads => "Threads",
+            EngineOption::ClearHash => "Clear Hash",
+            EngineOption::MoveOverhead => "MoveOverhead",
+            EngineOption::AnalyseMode => "UCI_AnalyseMode",
+            EngineOption::ShowWdl => "UCI_ShowWDL",
+            EngineOption::LimitStrength => "UCI_LimitStrength",
+            EngineOption::Elo => "UCI_Elo",
+            EngineOption::SkillLevel => "Skill Level",
+            EngineOption::Contempt => "Contempt",
+            EngineOption::RandomizeDraws => "RandomizeDraws",
+            EngineOption::EvalWeights => "EvalWeights",
+        }
+    }
+
+    #[must_use]
+    #[allow(clippy::cast_possible_wrap)]
+    /// Get the type of this option, including its default value.
+    pub const fn option_type(self) -> OptionType<'static> {
+        match self {
+            EngineOption::Hash => OptionType::Spin {
+                default: DEFAULT_HASH_SIZE_MB as i64,
+                min: 0,
+                max: MAX_HASH_SIZE_MB,
+            },
+            EngineOption::Threads => OptionType::Spin {
+                default: 1,
+                min: 1,
+                max: MAX_THREADS,
+            },
+            EngineOption::ClearHash => OptionType::Button,
+            EngineOption::MoveOverhead => OptionType::Spin {
+                default: DEFAULT_MOVE_OVERHEAD_MS as i64,
+                min: 0,
+                max: MAX_MOVE_OVERHEAD_MS,
+            },
+            EngineOption::AnalyseMode
+            | EngineOption::ShowWdl
+            | EngineOption::LimitStrength
+            | EngineOption::RandomizeDraws => OptionType::Check(Some(false)),
+            EngineOption::Elo => OptionType::Spin {
+                default: MAX_ELO as i64,
+                min: MIN_ELO as i64,
+                max: MAX_ELO as i64,
+            },
+            EngineOption::SkillLevel => OptionType::Spin {
+                default: MAX_LEVEL as i64,
+                min: 0,
+                max: MAX_LEVEL as i64,
+            },
+            EngineOption::Contempt => OptionType::Spin {
+                default: 0,
+                min: -MAX_CONTEMPT_CP,
+                max: MAX_CONTEMPT_CP,
+            },
+            EngineOption::EvalWeights => OptionType::String(Some(DEFAULT_EVAL_WEIGHTS)),
+        }
+    }
+
+    #[must_use]
+    /// Find the option with the given name.
+    /// As required by the UCI standard, names are not case sensitive.
+    pub fn from_name(name: &str) -> Option<EngineOption> {
+        EngineOption::ALL
+            .into_iter()
+            .find(|opt| opt.name().eq_ignore_ascii_case(name))
+    }
+
+    #[must_use]
+    /// Construct the message which informs the GUI of this option.
+    pub const fn message(self) -> Message<'static> {
+        Message::Option {
+            name: self.name(),
+            opt: self.option_type(),
+        }
+    }
+
+    /// Check a value given by the GUI for this option against its type.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `value` is missing or does not fit the option's
+    /// type.
+    pub fn parse_value(self, value: Option<&str>) -> Result<OptionValue, String> {
+        let name = self.name();
+        match (self.option_type(), value) {
+            (OptionType::Button, _) => Ok(OptionValue::Button),
+            (_, None) => Err(format!("no value given for option `{name}`")),
+            (OptionType::Spin { min, max, .. }, Some(v)) => match v.parse::<i64>() {
+                Ok(x) if (min..=max).contains(&x) => Ok(OptionValue::Spin(x)),
+                _ => Err(format!(
+                    "value `{v}` for option `{name}` is not an integer from {min} to {max}"
+                )),
+            },
+            (OptionType::Check(_), Some(v)) => match v {
+                "true" => Ok(OptionValue::Check(true)),
+                "false" => Ok(OptionValue::Check(false)),
+                _ => Err(format!(
+                    "value `{v}` for option `{name}` is not `true` or `false`"
+                )),
+            },
+            (OptionType::String(_), Some(v)) => Ok(OptionValue::String(v.to_string())),
+            (OptionType::Combo { default, vars }, Some(v)) => {
+                if default == Some(v) || vars.contains(&v) {
+                    Ok(OptionValue::String(v.to_string()))
+                } else {
+                    Err(format!("value `{v}` is not a choice for option `{name}`"))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that every option can be found by its name, regardless of case.
+    fn find_by_name() {
+        for opt in EngineOption::ALL {
+            assert_eq!(EngineOption::from_name(opt.name()), Some(opt));
+            assert_eq!(
+                EngineOption::from_name(&opt.name().to_uppercase()),
+                Some(opt)
+            );
+        }
+        assert_eq!(EngineOption::from_name("Foo"), None);
+    }
+
+    #[test]
+    /// Test that the default value of every option is accepted by its own validation.
+    fn defaults_are_valid() {
+        for opt in EngineOption::ALL {
+            let default = match opt.option_type() {
+                OptionType::Spin { default, .. } => Some(default.to_string()),
+                OptionType::Check(default) => default.map(|b| b.to_string()),
+                OptionType::String(default) | OptionType::Combo { default, .. } => {
+                    default.map(str::to_string)
+                }
+                OptionType::Button => None,
+            };
+            assert!(opt.parse_value(default.as_deref()).is_ok(), "{opt:?}");
+        }
+    }
+
+    #[test]
+    /// Test that spin values are bounded.
+    fn spin_bounds() {
+        let opt = EngineOption::Threads;
+        assert_eq!(opt.parse_value(Some("4")), Ok(OptionValue::Spin(4)));
+        assert!(opt.parse_value(Some("0")).is_err());
+        assert!(opt.parse_value(Some("257")).is_err());
+        assert!(opt.parse_value(Some("four")).is_err());
+        assert!(opt.parse_value(None).is_err());
+    }
+
+    #[test]
+    /// Test that check values must be `true` or `false`.
+    fn check_values() {
+        let opt = EngineOption::ShowWdl;
+        assert_eq!(opt.parse_value(Some("true")), Ok(OptionValue::Check(true)));
+        assert_eq!(
+            opt.parse_value(Some("false")),
+            Ok(OptionValue::Check(false))
+        );
+        assert!(opt.parse_value(Some("yes")).is_err());
+    }
+
+    #[test]
+    /// Test that buttons need no value.
+    fn button_press() {
+        assert_eq!(
+            EngineOption::ClearHash.parse_value(None),
+            Ok(OptionValue::Button)
+        );
+    }
+
+    #[test]
+    /// Test that the option listing is formatted as the UCI standard requires.
+    fn option_listing() {
+        assert_eq!(
+            EngineOption::ClearHash.message().to_string(),
+            "option name Clear Hash type button"
+        );
+        assert_eq!(
+            EngineOption::ShowWdl.message().to_string(),
+            "option name UCI_ShowWDL type check default false"
+        );
+    }
+}
@@ -18,7 +18,12 @@
 
 //! Messages that can be sent to the GUI, and a formatter for those messages.
 
-use std::{fmt, time::Duration};
+use std::{
+    fmt,
+    io::{stdout, Write},
+    sync::Mutex,
+    time::Duration,
+};
 
 use crate::base::Move;
 
@@ -53,9 +58,13 @@ pub enum Message<'a> {
     ///
     /// `m` is the best move that it found, and `ponder` may optionally be the opponent's reply to
     /// the best move that the engine would like to think about.
+    /// If there is no move to play, `m` is `None`, which is sent as the null move `0000`.
     /// Directly before a `BestMove`, the engine should send an `Info` message with the final search
     /// information.
-    BestMove { m: Move, ponder: Option<Move> },
+    BestMove {
+        m: Option<Move>,
+        ponder: Option<Move>,
+    },
     /// Give the GUI some information about what the engine is thinking.
     Info(&'a [EngineInfo<'a>]),
 }
@@ -94,6 +103,15 @@ pub enum EngineInfo<'a> {
     HashFull(u16),
     /// The number of nodes searched per second by the engine.
     NodeSpeed(u64),
+    /// The expected chances of the engine winning, drawing, or losing, in thousandths.
+    Wdl {
+        /// The chance of a win.
+        win: u16,
+        /// The chance of a draw.
+        draw: u16,
+        /// The chance of a loss.
+        loss: u16,
+    },
     /// Any string which should be displayed to the GUI.
     /// The string may not contain any newlines (`\n`).
     String(&'a str),
@@ -142,7 +160,10 @@ impl<'a> fmt::Display for Message<'a> {
             Message::ReadyOk => write!(f, "readyok")?,
             Message::Option { name, ref opt } => write_option(f, name, opt)?,
             Message::BestMove { m, ponder } => {
-                write!(f, "bestmove {}", m.to_uci())?;
+                match m {
+                    Some(m) => write!(f, "bestmove {}", m.to_uci())?,
+                    None => write!(f, "bestmove 0000")?,
+                }
                 if let Some(pondermove) = ponder {
                     write!(f, " ponder {}", pondermove.to_uci())?;
                 }
@@ -154,6 +175,50 @@ impl<'a> fmt::Display for Message<'a> {
     }
 }
 
+/// A destination for the information that an engine produces while it searches.
+///
+/// Searches report their progress through an `InfoSink` rather than printing directly, so that
+/// the same search can talk to a GUI, a test harness, or nobody at all.
+/// Any closure taking a slice of `EngineInfo` is an `InfoSink`.
+pub trait InfoSink: Send + Sync {
+    /// Report some information about the search.
+    fn info(&self, infos: &[EngineInfo]);
+}
+
+impl<F: Fn(&[EngineInfo]) + Send + Sync> InfoSink for F {
+    fn info(&self, infos: &[EngineInfo]) {
+        self(infos);
+    }
+}
+
+impl<W: Write + Send> InfoSink for Mutex<W> {
+    fn info(&self, infos: &[EngineInfo]) {
+        // a poisoned or hung-up output has nobody left to listen, so errors are ignored
+        if let Ok(mut output) = self.lock() {
+            let _ = writeln!(output, "{}", Message::Info(infos)).and_then(|()| output.flush());
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// An `InfoSink` which prints every report to standard output.
+pub struct StdoutSink;
+
+impl InfoSink for StdoutSink {
+    fn info(&self, infos: &[EngineInfo]) {
+        let mut output = stdout().lock();
+        let _ = writeln!(output, "{}", Message::Info(infos)).and_then(|()| output.flush());
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// An `InfoSink` which discards every report.
+pub struct NullSink;
+
+impl InfoSink for NullSink {
+    fn info(&self, _: &[EngineInfo]) {}
+}
+
 /// Helper function to build an output line to inform the GUI of an option.
 fn write_option(f: &mut fmt::Formatter, name: &str, opt: &OptionType) -> fmt::Result {
     write!(f, "option name {name} ")?;
@@ -239,6 +304,7 @@ fn write_info(f: &mut fmt::Formatter, infos: &[EngineInfo]) -> fmt::Result {
             }
             EngineInfo::HashFull(load) => write!(f, " hashfull {load}")?,
             EngineInfo::NodeSpeed(speed) => write!(f, " nps {speed}")?,
+            EngineInfo::Wdl { win, draw, loss } => write!(f, " wdl {win} {draw} {loss}")?,
             // We split this info into two lines if
             EngineInfo::String(s) => {
                 write!(f, " string {s}")?;
@@ -315,6 +381,29 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Test an info message with a win/draw/loss estimate following the score.
+    fn info_wdl() {
+        assert_eq!(
+            format!(
+                "{}",
+                Message::Info(&[
+                    EngineInfo::Score {
+                        eval: Eval::centipawns(35),
+                        is_lower_bound: false,
+                        is_upper_bound: false,
+                    },
+                    EngineInfo::Wdl {
+                        win: 123,
+                        draw: 800,
+                        loss: 77
+                    },
+                ])
+            ),
+            "info score cp 35 wdl 123 800 77"
+        );
+    }
+
     #[test]
     /// Test an id message.
     fn id() {
@@ -419,12 +508,22 @@ mod tests {
             format!(
                 "{}",
                 Message::BestMove {
-                    m: Move::normal(Square::E2, Square::E4),
+                    m: Some(Move::normal(Square::E2, Square::E4)),
                     ponder: None
                 }
             ),
             "bestmove e2e4"
         );
+        assert_eq!(
+            format!(
+                "{}",
+                Message::BestMove {
+                    m: None,
+                    ponder: None
+                }
+            ),
+            "bestmove 0000"
+        );
     }
 
     #[test]
@@ -434,7 +533,7 @@ mod tests {
             format!(
                 "{}",
                 Message::BestMove {
-                    m: Move::normal(Square::E2, Square::E4),
+                    m: Some(Move::normal(Square::E2, Square::E4)),
                     ponder: Some(Move::normal(Square::E7, Square::E5)),
                 }
             ),
@@ -3,3 +3,4 @@
 #![feature(once_cell)]
 pub mod base;
 pub mod engine;
+pub mod stats;
@@ -0,0 +1,574 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Statistics for engine matches: Elo estimates and sequential probability ratio tests.
+//!
+//! Results can be counted either per game (`Trinomial`: wins, draws, and losses) or per pair of
+//! games played from the same opening with colors swapped (`Pentanomial`).
+//! Pairs are counted by the number of points the first player scored across both games, which
+//! removes most of the noise caused by unbalanced openings.
+//!
+//! `Sprt` implements the generalized sequential probability ratio test used by Fishtest, in which
+//! the log-likelihood ratio between two hypothesized Elo differences is approximated from the mean
+//! and variance of the pair scores.
+//! A test can be stopped as soon as the log-likelihood ratio leaves the bounds given by the
+//! requested error rates.
+
+use std::fmt::{Display, Formatter};
+
+/// The distance from 0 and 1 at which scores are clamped before being converted into Elo
+/// differences, so that perfect scores give large but finite differences (about 2400 Elo).
+const SCORE_EPSILON: f64 = 1e-6;
+
+#[must_use]
+/// Convert an Elo difference into the expected fraction of points scored by the stronger player.
+///
+/// # Examples
+///
+/// ```
+/// use tomato::stats::elo_to_score;
+///
+/// assert_eq!(elo_to_score(0.0), 0.5);
+/// assert!((elo_to_score(400.0) - 10.0 / 11.0).abs() < 1e-12);
+/// ```
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+#[must_use]
+/// Convert an expected fraction of points scored into an Elo difference.
+/// This is the inverse of `elo_to_score`.
+/// Scores of 0 and 1 result in infinite Elo differences.
+pub fn score_to_elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+#[must_use]
+/// Approximate the error function, with an absolute error of at most 1.2e-7.
+pub fn erf(x: f64) -> f64 {
+    // Chebyshev fit of the complementary error function, from Numerical Recipes
+    let t = 1.0 / (1.0 + 0.5 * x.abs());
+    let poly = [
+        -1.265_512_23,
+        1.000_023_68,
+        0.374_091_96,
+        0.096_784_18,
+        -0.186_288_06,
+        0.278_868_07,
+        -1.135_203_98,
+        1.488_515_87,
+        -0.822_152_23,
+        0.170_872_77,
+    ]
+    .iter()
+    .rev()
+    .fold(0.0, |acc, c| acc * t + c);
+    let erfc = t * (-x * x + poly).exp();
+    if x < 0.0 {
+        erfc - 1.0
+    } else {
+        1.0 - erfc
+    }
+}
+
+#[must_use]
+/// Compute the cumulative distribution function of the standard normal distribution.
+pub fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+#[must_use]
+/// Compute the quantile function of the standard normal distribution: the value `x` for which
+/// `normal_cdf(x) == p`.
+///
+/// # Examples
+///
+/// ```
+/// use tomato::stats::normal_quantile;
+///
+/// assert!((normal_quantile(0.975) - 1.959_964).abs() < 1e-5);
+/// assert!(normal_quantile(0.5).abs() < 1e-6);
+/// ```
+pub fn normal_quantile(p: f64) -> f64 {
+    // the CDF is monotonic, so bisect until the interval stops shrinking
+    let (mut lo, mut hi) = (-40.0, 40.0);
+    loop {
+        let mid: f64 = (lo + hi) / 2.0;
+        if mid == lo || mid == hi {
+            return mid;
+        }
+        if normal_cdf(mid) < p {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// An estimate of the Elo difference between two players, along with a confidence interval.
+pub struct EloEstimate {
+    /// The most likely Elo difference.
+    pub elo: f64,
+    /// The lower end of the confidence interval.
+    pub lower: f64,
+    /// The upper end of the confidence interval.
+    pub upper: f64,
+}
+
+impl EloEstimate {
+    #[must_use]
+    /// Get the half-width of the confidence interval of this estimate, as is conventionally
+    /// reported with a +/- sign.
+    pub fn margin(&self) -> f64 {
+        (self.upper - self.lower) / 2.0
+    }
+
+    #[must_use]
+    /// Construct an estimate from the mean and variance of `n` scores, each ranging from 0 to 1.
+    ///
+    /// With no scores at all, nothing is known, so the interval covers every Elo difference.
+    /// Scores with no variance say nothing about how spread out the scores really are, so the
+    /// largest variance possible, 1/4, is assumed instead of giving an interval of zero width.
+    fn from_score(mean: f64, variance: f64, n: u32, confidence: f64) -> EloEstimate {
+        let (mean, variance) = if n == 0 {
+            (0.5, 0.25)
+        } else if variance == 0.0 {
+            (mean, 0.25)
+        } else {
+            (mean, variance)
+        };
+        // with no scores, the deviation is infinite and the interval is clamped below
+        let deviation = (variance / f64::from(n)).sqrt();
+        let z = normal_quantile(0.5 + confidence / 2.0);
+        let elo = |score: f64| score_to_elo(score.clamp(SCORE_EPSILON, 1.0 - SCORE_EPSILON));
+        EloEstimate {
+            elo: elo(mean),
+            lower: elo(mean - z * deviation),
+            upper: elo(mean + z * deviation),
+        }
+    }
+}
+
+impl Display for EloEstimate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.1} +/- {:.1}", self.elo, self.margin())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// The results of a set of games, counted one game at a time from the perspective of one player.
+pub struct Trinomial {
+    /// The number of games won.
+    pub wins: u32,
+    /// The number of games lost.
+    pub losses: u32,
+    /// The number of games drawn.
+    pub draws: u32,
+}
+
+impl Trinomial {
+    #[must_use]
+    /// Get the total number of games counted.
+    pub const fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    #[must_use]
+    /// Get the fraction of points scored, counting draws as half a point.
+    pub fn score(&self) -> f64 {
+        (f64::from(self.wins) + f64::from(self.draws) / 2.0) / f64::from(self.games().max(1))
+    }
+
+    #[must_use]
+    /// Estimate the Elo difference implied by these results, with a confidence interval covering
+    /// the given fraction (such as 0.95) of the probability mass.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tomato::stats::Trinomial;
+    ///
+    /// let results = Trinomial {
+    ///     wins: 60,
+    ///     losses: 11,
+    ///     draws: 29,
+    /// };
+    /// let estimate = results.elo(0.95);
+    /// assert_eq!(format!("{:.1}", estimate.elo), "186.2");
+    /// assert!(estimate.lower < 150.0 && 220.0 < estimate.upper);
+    /// ```
+    pub fn elo(&self, confidence: f64) -> EloEstimate {
+        let n = f64::from(self.games().max(1));
+        let mean = self.score();
+        let variance = (f64::from(self.wins) * (1.0 - mean).powi(2)
+            + f64::from(self.losses) * mean.powi(2)
+            + f64::from(self.draws) * (0.5 - mean).powi(2))
+            / n;
+        EloEstimate::from_score(mean, variance, self.games(), confidence)
+    }
+
+    #[must_use]
+    /// Compute the likelihood of superiority: the probability that the player who achieved these
+    /// results is the stronger one.
+    /// Draws carry no information about which player is stronger, so only decisive games count.
+    pub fn los(&self) -> f64 {
+        let wins = f64::from(self.wins);
+        let losses = f64::from(self.losses);
+        if wins + losses == 0.0 {
+            return 0.5;
+        }
+        0.5 * (1.0 + erf((wins - losses) / (2.0 * (wins + losses)).sqrt()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// The results of a set of game pairs, where each pair is played from the same opening with the
+/// colors swapped.
+pub struct Pentanomial {
+    /// The number of pairs in which the player scored each possible number of half-points.
+    /// For example, `counts[0]` is the number of pairs which were lost twice, and `counts[3]` is
+    /// the number of pairs with one win and one draw.
+    pub counts: [u32; 5],
+}
+
+impl Pentanomial {
+    /// Count a pair of games, given the number of half-points the player scored across both.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `half_points` is greater than 4.
+    pub fn add(&mut self, half_points: usize) {
+        self.counts[half_points] += 1;
+    }
+
+    #[must_use]
+    /// Get the total number of pairs counted.
+    pub fn pairs(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    #[must_use]
+    /// Get the mean and variance of the score of a pair, normalized so that a pair score ranges
+    /// from 0 to 1.
+    fn moments(&self) -> (f64, f64) {
+        let n = f64::from(self.pairs().max(1));
+        let values = [0.0, 0.25, 0.5, 0.75, 1.0];
+        let mean = self
+            .counts
+            .iter()
+            .zip(values)
+            .map(|(&count, value)| f64::from(count) * value)
+            .sum::<f64>()
+            / n;
+        let variance = self
+            .counts
+            .iter()
+            .zip(values)
+            .map(|(&count, value)| f64::from(count) * (value - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        (mean, variance)
+    }
+
+    #[must_use]
+    /// Get the fraction of points scored.
+    pub fn score(&self) -> f64 {
+        self.moments().0
+    }
+
+    #[must_use]
+    /// Estimate the Elo difference implied by these results, with a confidence interval covering
+    /// the given fraction (such as 0.95) of the probability mass.
+    pub fn elo(&self, confidence: f64) -> EloEstimate {
+        let (mean, variance) = self.moments();
+        EloEstimate::from_score(mean, variance, self.pairs(), confidence)
+    }
+
+    #[must_use]
+    /// Compute the likelihood of superiority: the probability that the player who achieved these
+    /// results is the stronger one.
+    pub fn los(&self) -> f64 {
+        let (mean, variance) = self.moments();
+        if variance == 0.0 {
+            return 0.5;
+        }
+        normal_cdf((mean - 0.5) / (variance / f64::from(self.pairs())).sqrt())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The state of a sequential probability ratio test.
+pub enum SprtStatus {
+    /// There is not yet enough evidence to decide.
+    Continue,
+    /// The null hypothesis (that the Elo difference is `elo0`) was accepted.
+    AcceptH0,
+    /// The alternative hypothesis (that the Elo difference is `elo1`) was accepted.
+    AcceptH1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// A sequential probability ratio test (SPRT) between two hypothesized Elo differences.
+pub struct Sprt {
+    /// The Elo difference under the null hypothesis.
+    pub elo0: f64,
+    /// The Elo difference under the alternative hypothesis.
+    pub elo1: f64,
+    /// The probability of accepting the alternative hypothesis when the null hypothesis is true.
+    pub alpha: f64,
+    /// The probability of accepting the null hypothesis when the alternative hypothesis is true.
+    pub beta: f64,
+}
+
+impl Sprt {
+    #[must_use]
+    /// Construct a new test between an Elo difference of `elo0` and one of `elo1`, with false
+    /// positive rate `alpha` and false negative rate `beta`.
+    pub const fn new(elo0: f64, elo1: f64, alpha: f64, beta: f64) -> Sprt {
+        Sprt {
+            elo0,
+            elo1,
+            alpha,
+            beta,
+        }
+    }
+
+    #[must_use]
+    /// Get the log-likelihood ratio below which the null hypothesis is accepted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tomato::stats::Sprt;
+    ///
+    /// let sprt = Sprt::new(0.0, 2.0, 0.05, 0.05);
+    /// assert_eq!(format!("({:.2}, {:.2})", sprt.lower_bound(), sprt.upper_bound()), "(-2.94, 2.94)");
+    /// ```
+    pub fn lower_bound(&self) -> f64 {
+        (self.beta / (1.0 - self.alpha)).ln()
+    }
+
+    #[must_use]
+    /// Get the log-likelihood ratio above which the alternative hypothesis is accepted.
+    pub fn upper_bound(&self) -> f64 {
+        ((1.0 - self.beta) / self.alpha).ln()
+    }
+
+    #[must_use]
+    /// Compute the log-likelihood ratio of the alternative hypothesis against the null hypothesis,
+    /// given the results of the test so far.
+    ///
+    /// The ratio is approximated from the mean and variance of the pair scores, as in Fishtest's
+    /// generalized SPRT.
+    /// If the results have no variance, there is no evidence either way and the ratio is 0.
+    pub fn llr(&self, results: &Pentanomial) -> f64 {
+        let (mean, variance) = results.moments();
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let s0 = elo_to_score(self.elo0);
+        let s1 = elo_to_score(self.elo1);
+        f64::from(results.pairs()) * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+
+    #[must_use]
+    /// Determine whether the test has reached a decision with the given results.
+    pub fn status(&self, results: &Pentanomial) -> SprtStatus {
+        let llr = self.llr(results);
+        if llr <= self.lower_bound() {
+            SprtStatus::AcceptH0
+        } else if self.upper_bound() <= llr {
+            SprtStatus::AcceptH1
+        } else {
+            SprtStatus::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Assert that `actual` is within `tolerance` of `expected`.
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    /// Test that the bounds match those published for common SPRT configurations: (-2.94, 2.94)
+    /// for alpha = beta = 0.05, and (-2.25, 2.89) for alpha = 0.05, beta = 0.1.
+    fn bounds() {
+        let sprt = Sprt::new(0.0, 2.0, 0.05, 0.05);
+        assert_close(sprt.lower_bound(), -2.944_439, 1e-6);
+        assert_close(sprt.upper_bound(), 2.944_439, 1e-6);
+        let sprt = Sprt::new(0.0, 5.0, 0.05, 0.1);
+        assert_close(sprt.lower_bound(), -2.251_292, 1e-6);
+        assert_close(sprt.upper_bound(), 2.890_372, 1e-6);
+    }
+
+    #[test]
+    /// Test the log-likelihood ratio against reference values from Fishtest's logistic
+    /// pentanomial approximation.
+    fn llr() {
+        let sprt = Sprt::new(0.0, 2.0, 0.05, 0.05);
+        let results = Pentanomial {
+            counts: [130, 5078, 11188, 5308, 140],
+        };
+        assert_close(sprt.llr(&results), 2.726_033, 1e-5);
+        assert_eq!(sprt.status(&results), SprtStatus::Continue);
+
+        let results = Pentanomial {
+            counts: [10, 30, 50, 35, 15],
+        };
+        assert_close(sprt.llr(&results), 0.140_018, 1e-5);
+
+        let sprt = Sprt::new(-1.75, 0.25, 0.05, 0.05);
+        let results = Pentanomial {
+            counts: [400, 3000, 6000, 2800, 380],
+        };
+        assert_close(sprt.llr(&results), -3.016_517, 1e-5);
+        assert_eq!(sprt.status(&results), SprtStatus::AcceptH0);
+    }
+
+    #[test]
+    /// Test that a test accepts the alternative hypothesis once a player is clearly stronger.
+    fn accept_h1() {
+        let sprt = Sprt::new(0.0, 10.0, 0.05, 0.05);
+        let mut results = Pentanomial::default();
+        while sprt.status(&results) == SprtStatus::Continue {
+            results.add(1);
+            results.add(2);
+            results.add(3);
+            results.add(3);
+        }
+        assert_eq!(sprt.status(&results), SprtStatus::AcceptH1);
+    }
+
+    #[test]
+    /// Test that results with no variance give no evidence either way.
+    fn no_variance() {
+        let sprt = Sprt::new(0.0, 2.0, 0.05, 0.05);
+        let results = Pentanomial {
+            counts: [0, 0, 100, 0, 0],
+        };
+        assert_eq!(sprt.llr(&results), 0.0);
+        assert_eq!(results.los(), 0.5);
+        assert_close(results.elo(0.95).elo, 0.0, 1e-9);
+    }
+
+    #[test]
+    /// Test that Elo estimates stay finite with no results, with results that have no variance,
+    /// and with intervals that reach past a perfect score.
+    fn elo_edge_cases() {
+        let assert_sane = |estimate: EloEstimate| {
+            assert!(estimate.lower.is_finite() && estimate.upper.is_finite());
+            assert!(estimate.lower <= estimate.elo && estimate.elo <= estimate.upper);
+            assert!(0.0 < estimate.margin(), "{estimate:?}");
+            assert!(!estimate.to_string().contains("NaN"));
+        };
+
+        // no results
+        for estimate in [
+            Trinomial::default().elo(0.95),
+            Pentanomial::default().elo(0.95),
+        ] {
+            assert_sane(estimate);
+            assert_eq!(estimate.elo, 0.0);
+            assert_close(estimate.upper, -estimate.lower, 1e-6);
+        }
+
+        // one win and one draw, whose interval reaches past a score of 1
+        let estimate = Trinomial {
+            wins: 1,
+            losses: 0,
+            draws: 1,
+        }
+        .elo(0.95);
+        assert_sane(estimate);
+        assert_eq!(format!("{:.1}", estimate.elo), "190.8");
+
+        // results with no variance
+        let pair = Pentanomial {
+            counts: [0, 0, 0, 1, 0],
+        };
+        assert_sane(pair.elo(0.95));
+        assert_eq!(format!("{:.1}", pair.elo(0.95).elo), "190.8");
+        let sweep = Trinomial {
+            wins: 2,
+            losses: 0,
+            draws: 0,
+        };
+        assert_sane(sweep.elo(0.95));
+        assert_close(sweep.elo(0.95).elo, 2400.0, 1.0);
+    }
+
+    #[test]
+    /// Test the Elo estimate and likelihood of superiority of pentanomial results.
+    fn pentanomial_elo() {
+        let results = Pentanomial {
+            counts: [10, 30, 50, 35, 15],
+        };
+        let estimate = results.elo(0.95);
+        assert_close(estimate.elo, 18.630_457, 1e-5);
+        assert_close(estimate.lower, -12.479_893, 1e-3);
+        assert_close(estimate.upper, 50.042_988, 1e-3);
+        assert_close(results.los(), 0.879_698, 1e-5);
+    }
+
+    #[test]
+    /// Test that the Elo estimate and LOS of the match in `results.txt` are reproduced.
+    /// The tool which produced `results.txt` approximates the normal quantile, so its margin
+    /// differs slightly from ours.
+    fn trinomial_elo() {
+        let results = Trinomial {
+            wins: 60,
+            losses: 11,
+            draws: 29,
+        };
+        let estimate = results.elo(0.95);
+        assert_eq!(format!("{:.1}", estimate.elo), "186.2");
+        assert_close(estimate.margin(), 62.5, 0.1);
+        assert_eq!(format!("{:.1}", 100.0 * results.los()), "100.0");
+
+        let even = Trinomial {
+            wins: 10,
+            losses: 10,
+            draws: 5,
+        };
+        assert_close(even.elo(0.95).elo, 0.0, 1e-9);
+        assert_close(even.los(), 0.5, 1e-6);
+    }
+
+    #[test]
+    /// Test that the normal quantile function inverts the normal CDF.
+    fn quantiles() {
+        for (p, x) in [
+            (0.5, 0.0),
+            (0.975, 1.959_964),
+            (0.95, 1.644_854),
+            (0.025, -1.959_964),
+        ] {
+            assert_close(normal_quantile(p), x, 1e-5);
+            assert_close(normal_cdf(x), p, 1e-6);
+        }
+    }
+}
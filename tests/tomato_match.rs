@@ -0,0 +1,104 @@
+/*
+  Tomato, a UCI-compatible chess engine.
+  Copyright (C) 2022 Clayton Ramsey.
+
+  Tomato is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  Tomato is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! A short match between two copies of the engine, played through `tomato-match`.
+
+use std::{env, fs, fs::File, io::BufReader, process::Command};
+
+use tomato::base::pgn::{PgnGame, PgnReader, PgnResult};
+
+/// An opening in which White has four queens and mates in two moves.
+const MATING_OPENING: &str = "4k3/8/8/8/8/8/8/QQQQK3 w - - 0 1";
+
+/// The standard starting position, from which no game ends within a few moves.
+const START_OPENING: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[test]
+/// Test that a match of two pairs of games is played out, adjudicated, recorded, and scored
+/// correctly: the first pair is won by White each time, and the second pair is drawn by the move
+/// limit.
+fn short_match() {
+    let dir = env::temp_dir().join(format!("tomato-match-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let openings = dir.join("openings.txt");
+    let pgn = dir.join("games.pgn");
+    fs::write(&openings, format!("{MATING_OPENING}\n{START_OPENING}\n")).unwrap();
+
+    let engine = env!("CARGO_BIN_EXE_tomato");
+    let output = Command::new(env!("CARGO_BIN_EXE_tomato-match"))
+        .args([
+            "--engine",
+            &format!("cmd={engine}"),
+            "name=a",
+            "option.Hash=1",
+        ])
+        .args([
+            "--engine",
+            &format!("cmd={engine}"),
+            "name=b",
+            "option.Hash=1",
+        ])
+        .args(["--tc", "2+0.02", "--games", "4", "--concurrency", "2"])
+        .args(["--maxmoves", "8", "--openings", openings.to_str().unwrap()])
+        .args(["--pgnout", pgn.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{stdout}");
+
+    let mut games: Vec<PgnGame> = PgnReader::new(BufReader::new(File::open(&pgn).unwrap()))
+        .collect::<Result<_, _>>()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // games finish in any order, but each keeps its round
+    games.sort_by_key(|g| g.tag("Round").unwrap().parse::<u32>().unwrap());
+    assert_eq!(games.len(), 4);
+    for (i, game) in games.iter().enumerate() {
+        let (white, black) = if i % 2 == 0 { ("a", "b") } else { ("b", "a") };
+        assert_eq!(game.tag("White"), Some(white));
+        assert_eq!(game.tag("Black"), Some(black));
+        assert_eq!(game.tag("Result"), Some(game.result.token()));
+    }
+
+    // the first pair is played from the mating opening
+    for game in &games[..2] {
+        assert_eq!(game.tag("FEN"), Some(MATING_OPENING));
+        assert_eq!(game.result, PgnResult::WhiteWins);
+        assert_eq!(game.tag("Termination"), Some("normal"));
+        assert_eq!(game.moves.len(), 3);
+    }
+    // the second pair is played from the starting position until the move limit
+    for game in &games[2..] {
+        assert_eq!(game.tag("FEN"), None);
+        assert_eq!(game.result, PgnResult::Draw);
+        assert_eq!(game.tag("Termination"), Some("adjudication"));
+        assert_eq!(game.moves.len(), 16);
+    }
+
+    // each engine won once as White, and the other two games were drawn
+    let score = stdout.lines().rfind(|l| l.starts_with("Score of a vs b:"));
+    assert_eq!(
+        score,
+        Some("Score of a vs b: 1 - 1 - 2  [0.500] 4"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("a playing White: 1 - 0 - 1"), "{stdout}");
+    assert!(stdout.contains("Ptnml(0-2): 0, 0, 2, 0, 0;"), "{stdout}");
+    assert!(stdout.contains("4 of 4 games finished."), "{stdout}");
+}